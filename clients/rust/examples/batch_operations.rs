@@ -30,15 +30,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Store multiple values
         BatchOp::Put {
             key: "batch:user:1".to_string(),
-            value: r#"{"name":"Alice","role":"admin"}"#.to_string(),
+            value: br#"{"name":"Alice","role":"admin"}"#.to_vec(),
         },
         BatchOp::Put {
             key: "batch:user:2".to_string(),
-            value: r#"{"name":"Bob","role":"user"}"#.to_string(),
+            value: br#"{"name":"Bob","role":"user"}"#.to_vec(),
         },
         BatchOp::Put {
             key: "batch:user:3".to_string(),
-            value: r#"{"name":"Charlie","role":"user"}"#.to_string(),
+            value: br#"{"name":"Charlie","role":"user"}"#.to_vec(),
         },
         // Read back a value
         BatchOp::Get {
@@ -48,6 +48,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         BatchOp::Get {
             key: "batch:user:999".to_string(),
         },
+        // Metadata only, and the users stored so far
+        BatchOp::Head {
+            key: "batch:user:2".to_string(),
+        },
+        BatchOp::List {
+            prefix: "batch:user:".to_string(),
+            start_after: None,
+            limit: Some(10),
+        },
     ];
 
     info!("Executing batch with {} operations...", operations.len());
@@ -73,7 +82,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         "  [{}] GET {} - value: {}",
                         i + 1,
                         key,
-                        value.as_ref().unwrap_or(&"<none>".to_string())
+                        value.as_deref().map_or("<none>".into(), String::from_utf8_lossy)
                     );
                 } else {
                     info!("  [{}] GET {} - not found", i + 1, key);
@@ -82,6 +91,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             kv_storage_client::BatchResult::Delete { key, deleted } => {
                 info!("  [{}] DELETE {} - deleted: {}", i + 1, key, deleted);
             }
+            kv_storage_client::BatchResult::Copy { key, source, .. } => {
+                info!("  [{}] COPY {} -> {}", i + 1, source, key);
+            }
+            kv_storage_client::BatchResult::Rename { key, source, .. } => {
+                info!("  [{}] RENAME {} -> {}", i + 1, source, key);
+            }
+            kv_storage_client::BatchResult::Head { key, found, size, .. } => {
+                info!("  [{}] HEAD {} - found: {}, size: {:?}", i + 1, key, found, size);
+            }
+            kv_storage_client::BatchResult::Exists { key, exists } => {
+                info!("  [{}] EXISTS {} - {}", i + 1, key, exists);
+            }
+            kv_storage_client::BatchResult::Cas { key, swapped, .. } => {
+                info!("  [{}] CAS {} - swapped: {}", i + 1, key, swapped);
+            }
+            kv_storage_client::BatchResult::List { prefix, keys, more } => {
+                info!("  [{}] LIST {} - {} keys, more: {}", i + 1, prefix, keys.len(), more);
+            }
             kv_storage_client::BatchResult::Error { key, error } => {
                 info!("  [{}] ERROR on {}: {}", i + 1, key, error);
             }
@@ -13,6 +13,8 @@ use hyper_util::client::legacy::Client as HttpClient;
 use hyper_util::rt::TokioExecutor;
 use tracing::debug;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 use crate::error::{Error, Result};
@@ -45,6 +47,13 @@ fn encode_key(key: &str) -> String {
     utf8_percent_encode(key, PATH_SEGMENT).to_string()
 }
 
+/// RFC 3230 `Digest` value for an upload, checked by the server before it
+/// stores the value.
+fn content_digest(value: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, value);
+    format!("sha-256={}", BASE64.encode(digest.as_ref()))
+}
+
 /// Configuration options for the KV Storage client
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
@@ -462,6 +471,9 @@ impl Client {
     /// # Returns
     /// Information about the stored value including its hash
     ///
+    /// A SHA-256 `Digest` header is sent along, so a body truncated in
+    /// transit is rejected by the server instead of stored.
+    ///
     /// # Example
     /// ```rust,no_run
     /// # use kv_storage_client::Client;
@@ -476,21 +488,31 @@ impl Client {
     pub async fn put(&self, key: &str, value: &[u8]) -> Result<PutResponse> {
         let mut headers = HashMap::new();
         headers.insert("content-type".to_string(), "application/octet-stream".to_string());
+        headers.insert("digest".to_string(), content_digest(value));
 
         let path = format!("/{}", encode_key(key));
         let response = self
             .request(&path, &hyper::Method::PUT, Some(Bytes::copy_from_slice(value)), Some(headers))
             .await?;
 
+        let headers = response.headers();
+        let hash_algorithm = headers
+            .get("x-hash-algorithm")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("xxhash3")
+            .to_string();
+        let deduplicated = headers
+            .get("x-deduplicated")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v == "true");
+
         let body_bytes = Self::read_body_to_bytes(response.into_body()).await?;
         let hash = String::from_utf8_lossy(&body_bytes).trim().to_string();
 
-        let hash_algorithm = "xxhash3-128".to_string();
-
         Ok(PutResponse {
             hash,
             hash_algorithm,
-            deduplicated: false, // TODO: Parse from response headers
+            deduplicated,
         })
     }
 
@@ -598,6 +620,61 @@ impl Client {
         }
     }
 
+    /// Copy a key server-side; both keys then share the stored value
+    ///
+    /// # Arguments
+    /// * `source` - The key to copy from
+    /// * `destination` - The key to create or replace
+    /// * `overwrite` - Whether to replace an existing destination
+    ///
+    /// # Returns
+    /// The copy result; `Error::NotFound` if the source does not exist
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use kv_storage_client::Client;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), kv_storage_client::Error> {
+    /// # let client = Client::new("http://localhost:3000", "token")?;
+    /// let result = client.copy("releases/latest", "releases/v1.2", false).await?;
+    /// println!("{} now shares {} with {}", result.destination, result.hash, result.source);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn copy(&self, source: &str, destination: &str, overwrite: bool) -> Result<CopyResponse> {
+        self.copy_request("/copy", source, destination, overwrite).await
+    }
+
+    /// Rename a key server-side in a single transaction
+    ///
+    /// # Arguments
+    /// * `source` - The key to move
+    /// * `destination` - Its new name
+    /// * `overwrite` - Whether to replace an existing destination
+    ///
+    /// # Returns
+    /// The rename result; `Error::NotFound` if the source does not exist
+    pub async fn rename(&self, source: &str, destination: &str, overwrite: bool) -> Result<CopyResponse> {
+        self.copy_request("/rename", source, destination, overwrite).await
+    }
+
+    async fn copy_request(&self, path: &str, source: &str, destination: &str, overwrite: bool) -> Result<CopyResponse> {
+        let json = serde_json::json!({
+            "source": source,
+            "destination": destination,
+            "overwrite": overwrite,
+        });
+
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "application/json".to_string());
+
+        let response = self
+            .request(path, &hyper::Method::POST, Some(Bytes::from(json.to_string())), Some(headers))
+            .await?;
+        let body_bytes = Self::read_body_to_bytes(response.into_body()).await?;
+        Ok(serde_json::from_slice(&body_bytes)?)
+    }
+
     /// Get metadata about a key without retrieving the value
     ///
     /// # Arguments
@@ -670,6 +747,68 @@ impl Client {
         Ok(result)
     }
 
+    /// List keys with prefix, bounds and cursor pagination
+    ///
+    /// Pass the returned `next_cursor` back as `options.cursor` to fetch the
+    /// next page; it is `None` once the listing is exhausted.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use kv_storage_client::{Client, ListOptions};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), kv_storage_client::Error> {
+    /// # let client = Client::new("http://localhost:3000", "token")?;
+    /// let mut options = ListOptions { prefix: Some("user:".to_string()), ..Default::default() };
+    /// loop {
+    ///     let page = client.list_with(&options).await?;
+    ///     for key_info in &page.keys {
+    ///         println!("{}", key_info.key);
+    ///     }
+    ///     match page.next_cursor {
+    ///         Some(cursor) => options.cursor = Some(cursor),
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_with(&self, options: &ListOptions) -> Result<ListResponse> {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(prefix) = &options.prefix {
+            query.append_pair("prefix", prefix);
+        }
+        if let Some(start_after) = &options.start_after {
+            query.append_pair("start_after", start_after);
+        }
+        if let Some(cursor) = &options.cursor {
+            query.append_pair("cursor", cursor);
+        }
+        if let Some(end) = &options.end {
+            query.append_pair("end", end);
+        }
+        if let Some(delimiter) = &options.delimiter {
+            query.append_pair("delimiter", delimiter);
+        }
+        if options.reverse {
+            query.append_pair("reverse", "true");
+        }
+        if let Some(limit) = options.limit {
+            query.append_pair("limit", &limit.min(1000).to_string());
+        }
+        let path = format!("/keys?{}", query.finish());
+
+        let mut headers = HashMap::new();
+        headers.insert("accept".to_string(), "application/json".to_string());
+
+        let response = self
+            .request(&path, &hyper::Method::GET, None, Some(headers))
+            .await?;
+
+        let body_bytes = Self::read_body_to_bytes(response.into_body()).await?;
+        let result: ListResponse = serde_json::from_slice(&body_bytes)?;
+        Ok(result)
+    }
+
     /// List all keys (convenience method with default pagination)
     pub async fn list(&self) -> Result<ListResponse> {
         self.list_keys(0, 100).await
@@ -677,6 +816,9 @@ impl Client {
 
     /// Execute multiple operations in a batch
     ///
+    /// Values are sent and returned base64-encoded, so they may hold any
+    /// bytes.
+    ///
     /// # Arguments
     /// * `operations` - Array of batch operations to execute
     ///
@@ -692,8 +834,8 @@ impl Client {
     /// use kv_storage_client::BatchOp;
     ///
     /// let ops = vec![
-    ///     BatchOp::Put { key: "user:1".to_string(), value: r#"{"name":"John"}"#.to_string() },
-    ///     BatchOp::Put { key: "user:2".to_string(), value: r#"{"name":"Jane"}"#.to_string() },
+    ///     BatchOp::Put { key: "user:1".to_string(), value: br#"{"name":"John"}"#.to_vec() },
+    ///     BatchOp::Put { key: "user:2".to_string(), value: vec![0x00, 0xFF] },
     ///     BatchOp::Get { key: "user:1".to_string() },
     ///     BatchOp::Delete { key: "old-key".to_string() },
     /// ];
@@ -710,7 +852,21 @@ impl Client {
     /// # }
     /// ```
     pub async fn batch(&self, operations: Vec<BatchOp>) -> Result<BatchResponse> {
-        let json = serde_json::to_string(&operations)?;
+        // Each put carries a digest of its value for the server to verify
+        let operations = operations
+            .iter()
+            .map(|op| {
+                let mut json = serde_json::to_value(op)?;
+                if let BatchOp::Put { value, .. } = op {
+                    json["digest"] = content_digest(value).into();
+                }
+                Ok(json)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let json = serde_json::to_string(&serde_json::json!({
+            "encoding": "base64",
+            "ops": operations,
+        }))?;
 
         let mut headers = HashMap::new();
         headers.insert("content-type".to_string(), "application/json".to_string());
@@ -785,6 +941,14 @@ impl Client {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_content_digest() {
+        assert_eq!(
+            content_digest(b"abc"),
+            "sha-256=ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+        );
+    }
+
     // ===== parse_fingerprint tests =====
 
     #[test]
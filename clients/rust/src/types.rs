@@ -8,7 +8,7 @@ use http::HeaderMap;
 pub struct PutResponse {
     /// Hash of the stored content
     pub hash: String,
-    /// Hash algorithm used (e.g., "xxhash3-128")
+    /// Hash algorithm used (e.g., "xxhash3" or "sha256-128")
     pub hash_algorithm: String,
     /// Whether the content was deduplicated (same hash already existed)
     pub deduplicated: bool,
@@ -29,6 +29,9 @@ pub struct KeyInfo {
     pub refs: u64,
     /// Unix timestamp when the key was created
     pub created_at: u64,
+    /// Unix timestamp of the last write to the key
+    #[serde(default)]
+    pub modified_at: u64,
 }
 
 /// Response from a list request
@@ -38,6 +41,32 @@ pub struct ListResponse {
     pub keys: Vec<KeyInfo>,
     /// Total number of keys
     pub total: u64,
+    /// Rolled-up key groups when listing with a delimiter
+    #[serde(default)]
+    pub common_prefixes: Vec<String>,
+    /// Cursor for the next page, `None` on the last page
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+/// Options for a cursor-based key listing
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// Only list keys starting with this prefix
+    pub prefix: Option<String>,
+    /// Resume after this key (exclusive)
+    pub start_after: Option<String>,
+    /// Opaque cursor from a previous [`ListResponse::next_cursor`]
+    pub cursor: Option<String>,
+    /// Stop before this key (exclusive)
+    pub end: Option<String>,
+    /// Group keys by the next occurrence of this delimiter after the prefix
+    /// (e.g. `/` for folder-style browsing) into `common_prefixes`
+    pub delimiter: Option<String>,
+    /// List in descending key order
+    pub reverse: bool,
+    /// Maximum number of keys per page (server caps at 1000)
+    pub limit: Option<usize>,
 }
 
 /// A single batch operation
@@ -49,8 +78,9 @@ pub enum BatchOp {
     Put {
         /// The key to store
         key: String,
-        /// The value to store
-        value: String,
+        /// The value to store, any bytes (sent base64-encoded)
+        #[serde(with = "base64_bytes")]
+        value: Vec<u8>,
     },
     /// Retrieve a value
     #[serde(rename = "get")]
@@ -64,6 +94,80 @@ pub enum BatchOp {
         /// The key to delete
         key: String,
     },
+    /// Point a key at the value of another key, server-side
+    #[serde(rename = "copy")]
+    Copy {
+        /// The key to copy from
+        source: String,
+        /// The key to create or replace
+        destination: String,
+        /// Set to `Some(false)` to fail if the destination exists
+        #[serde(skip_serializing_if = "Option::is_none")]
+        overwrite: Option<bool>,
+    },
+    /// Move a key to a new name, server-side
+    #[serde(rename = "rename")]
+    Rename {
+        /// The key to move
+        source: String,
+        /// Its new name
+        destination: String,
+        /// Set to `Some(false)` to fail if the destination exists
+        #[serde(skip_serializing_if = "Option::is_none")]
+        overwrite: Option<bool>,
+    },
+    /// Retrieve a key's metadata without its value
+    #[serde(rename = "head")]
+    Head {
+        /// The key to look up
+        key: String,
+    },
+    /// Check whether a key exists
+    #[serde(rename = "exists")]
+    Exists {
+        /// The key to check
+        key: String,
+    },
+    /// Store a value only if the key still holds the expected content
+    #[serde(rename = "cas")]
+    Cas {
+        /// The key to store
+        key: String,
+        /// The value to store, any bytes (sent base64-encoded)
+        #[serde(with = "base64_bytes")]
+        value: Vec<u8>,
+        /// Hash the key must hold, or `None` if it must not exist
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expected: Option<String>,
+    },
+    /// List keys under a prefix
+    #[serde(rename = "list")]
+    List {
+        /// Only keys starting with this prefix
+        prefix: String,
+        /// Resume after this key
+        #[serde(skip_serializing_if = "Option::is_none")]
+        start_after: Option<String>,
+        /// Maximum number of keys, server default if `None`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limit: Option<usize>,
+    },
+}
+
+/// A key listed by a batch LIST operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchListedKey {
+    /// The key
+    pub key: String,
+    /// Hash of its content
+    pub hash: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Unix time of the last write
+    pub modified_at: u64,
+    /// Unix time the key expires, if it has a TTL
+    #[serde(default)]
+    pub expires_at: Option<u64>,
 }
 
 /// Result of a single batch operation
@@ -84,7 +188,8 @@ pub enum BatchResult {
         /// The key that was retrieved
         key: String,
         /// The value, if found
-        value: Option<String>,
+        #[serde(default, with = "base64_bytes::option")]
+        value: Option<Vec<u8>>,
         /// Whether the key was found
         found: bool,
     },
@@ -95,6 +200,82 @@ pub enum BatchResult {
         /// Whether the key was actually deleted
         deleted: bool,
     },
+    /// Successful COPY operation
+    Copy {
+        /// The destination key
+        key: String,
+        /// The key copied from
+        source: String,
+        /// Hash of the shared content
+        hash: String,
+        /// Whether the destination was created rather than replaced
+        created: bool,
+    },
+    /// Successful RENAME operation
+    Rename {
+        /// The new key name
+        key: String,
+        /// The old key name
+        source: String,
+        /// Hash of the content
+        hash: String,
+        /// Whether the destination was created rather than replaced
+        created: bool,
+    },
+    /// Successful HEAD operation
+    Head {
+        /// The key that was looked up
+        key: String,
+        /// Whether the key was found; the other fields are `None` if not
+        found: bool,
+        /// Hash of the content
+        #[serde(default)]
+        hash: Option<String>,
+        /// Algorithm of the hash
+        #[serde(default)]
+        hash_algorithm: Option<String>,
+        /// Size in bytes
+        #[serde(default)]
+        size: Option<u64>,
+        /// Number of references to the content
+        #[serde(default)]
+        refs: Option<u64>,
+        /// Unix time the key was created
+        #[serde(default)]
+        created_at: Option<u64>,
+        /// Unix time of the last write
+        #[serde(default)]
+        modified_at: Option<u64>,
+        /// Unix time the key expires, if it has a TTL
+        #[serde(default)]
+        expires_at: Option<u64>,
+    },
+    /// Successful EXISTS operation
+    Exists {
+        /// The key that was checked
+        key: String,
+        /// Whether the key exists
+        exists: bool,
+    },
+    /// Completed CAS operation
+    Cas {
+        /// The key that was compared
+        key: String,
+        /// Whether the value was stored
+        swapped: bool,
+        /// Hash the key holds now, `None` if it does not exist
+        #[serde(default)]
+        hash: Option<String>,
+    },
+    /// Successful LIST operation
+    List {
+        /// The prefix that was listed
+        prefix: String,
+        /// Keys found, in key order
+        keys: Vec<BatchListedKey>,
+        /// Whether more keys follow the last one returned
+        more: bool,
+    },
     /// Failed operation
     Error {
         /// The key that caused the error
@@ -104,6 +285,19 @@ pub enum BatchResult {
     },
 }
 
+/// Response from a copy or rename request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopyResponse {
+    /// The key copied or moved from
+    pub source: String,
+    /// The key written
+    pub destination: String,
+    /// Hash of the content
+    pub hash: String,
+    /// Whether the destination was created rather than replaced
+    pub created: bool,
+}
+
 /// Response from a batch request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchResponse {
@@ -112,12 +306,18 @@ pub struct BatchResponse {
 }
 
 impl BatchResult {
-    /// Returns the key associated with this result
+    /// Returns the key associated with this result, or the prefix of a LIST
     pub fn key(&self) -> &str {
         match self {
             BatchResult::Put { key, .. } => key,
             BatchResult::Get { key, .. } => key,
             BatchResult::Delete { key, .. } => key,
+            BatchResult::Copy { key, .. } => key,
+            BatchResult::Rename { key, .. } => key,
+            BatchResult::Head { key, .. } => key,
+            BatchResult::Exists { key, .. } => key,
+            BatchResult::Cas { key, .. } => key,
+            BatchResult::List { prefix, .. } => prefix,
             BatchResult::Error { key, .. } => key,
         }
     }
@@ -136,6 +336,40 @@ impl BatchResult {
     }
 }
 
+/// Batch values travel as base64 strings, so binary values survive the JSON
+/// batch format
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(serde::de::Error::custom)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(value: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|encoded| BASE64.decode(encoded).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
+
 /// HEAD request response headers
 #[derive(Debug, Clone)]
 pub struct HeadInfo {
@@ -183,10 +183,9 @@ async fn test_batch_operations() {
     // Cleanup before test
     cleanup(&client, &[&key1, &key2]).await;
 
-    // Batch operations - values must be JSON strings for batch API
     let ops = vec![
-        BatchOp::Put { key: key1.clone(), value: "batch1".to_string() },
-        BatchOp::Put { key: key2.clone(), value: "batch2".to_string() },
+        BatchOp::Put { key: key1.clone(), value: b"batch1".to_vec() },
+        BatchOp::Put { key: key2.clone(), value: b"batch2".to_vec() },
         BatchOp::Get { key: key1.clone() },
     ];
 
@@ -204,6 +203,86 @@ async fn test_batch_operations() {
     cleanup(&client, &[&key1, &key2]).await;
 }
 
+#[tokio::test]
+async fn test_batch_binary_values() {
+    let client = get_client();
+    let key = get_test_key("batch_binary");
+    cleanup(&client, &[&key]).await;
+
+    let binary: Vec<u8> = (0..=255u8).rev().collect();
+    let ops = vec![
+        BatchOp::Put { key: key.clone(), value: binary.clone() },
+        BatchOp::Get { key: key.clone() },
+    ];
+    let response = client.batch(ops).await.unwrap();
+    match &response.results[1] {
+        kv_storage_client::BatchResult::Get { value, found, .. } => {
+            assert!(*found);
+            assert_eq!(value.as_deref(), Some(binary.as_slice()));
+        }
+        other => panic!("Expected Get result, got {:?}", other),
+    }
+    assert_eq!(client.get(&key).await.unwrap(), Some(binary));
+
+    cleanup(&client, &[&key]).await;
+}
+
+#[tokio::test]
+async fn test_batch_head_exists_cas_list() {
+    use kv_storage_client::BatchResult;
+
+    let client = get_client();
+    let key = get_test_key("batch_cas/a");
+    let other = get_test_key("batch_cas/b");
+    cleanup(&client, &[&key, &other]).await;
+
+    let put = client.put(&key, b"one").await.unwrap();
+    let prefix = key.trim_end_matches('a').to_string();
+    let ops = vec![
+        BatchOp::Head { key: key.clone() },
+        BatchOp::Exists { key: other.clone() },
+        BatchOp::Cas { key: key.clone(), value: b"two".to_vec(), expected: Some(put.hash.clone()) },
+        BatchOp::Cas { key: key.clone(), value: b"three".to_vec(), expected: Some(put.hash.clone()) },
+        BatchOp::Cas { key: other.clone(), value: b"new".to_vec(), expected: None },
+        BatchOp::List { prefix: prefix.clone(), start_after: None, limit: Some(1) },
+    ];
+    let results = client.batch(ops).await.unwrap().results;
+
+    match &results[0] {
+        BatchResult::Head { found, hash, size, .. } => {
+            assert!(*found);
+            assert_eq!(hash.as_deref(), Some(put.hash.as_str()));
+            assert_eq!(*size, Some(3));
+        }
+        other => panic!("Expected Head result, got {:?}", other),
+    }
+    assert!(matches!(&results[1], BatchResult::Exists { exists: false, .. }));
+    let swapped_hash = match &results[2] {
+        BatchResult::Cas { swapped: true, hash: Some(hash), .. } => hash.clone(),
+        other => panic!("Expected swapped Cas result, got {:?}", other),
+    };
+    match &results[3] {
+        BatchResult::Cas { swapped, hash, .. } => {
+            assert!(!*swapped);
+            assert_eq!(hash.as_deref(), Some(swapped_hash.as_str()));
+        }
+        other => panic!("Expected Cas result, got {:?}", other),
+    }
+    assert!(matches!(&results[4], BatchResult::Cas { swapped: true, .. }));
+    match &results[5] {
+        BatchResult::List { keys, more, .. } => {
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].key, key);
+            assert!(*more);
+        }
+        other => panic!("Expected List result, got {:?}", other),
+    }
+    assert_eq!(results[5].key(), prefix);
+    assert_eq!(client.get(&key).await.unwrap(), Some(b"two".to_vec()));
+
+    cleanup(&client, &[&key, &other]).await;
+}
+
 #[tokio::test]
 async fn test_keys_with_spaces() {
     let client = get_client();
@@ -454,7 +533,7 @@ async fn test_batch_mixed_success_and_failure() {
 
     // Batch with PUT, GET existing, DELETE
     let ops = vec![
-        BatchOp::Put { key: new_key.clone(), value: "new_value".to_string() },
+        BatchOp::Put { key: new_key.clone(), value: b"new_value".to_vec() },
         BatchOp::Get { key: existing_key.clone() },
         BatchOp::Delete { key: existing_key.clone() },
     ];
@@ -473,6 +552,39 @@ async fn test_batch_mixed_success_and_failure() {
     cleanup(&client, &[&new_key]).await;
 }
 
+#[tokio::test]
+async fn test_copy_and_rename() {
+    let client = get_client();
+    let source = get_test_key("copy_source");
+    let copy = get_test_key("copy_dest");
+    let renamed = get_test_key("copy_renamed");
+
+    cleanup(&client, &[&source, &copy, &renamed]).await;
+    client.put(&source, b"copied value").await.unwrap();
+
+    let result = client.copy(&source, &copy, true).await.unwrap();
+    assert!(result.created);
+    assert_eq!(client.get(&copy).await.unwrap(), Some(b"copied value".to_vec()));
+
+    // Refuses to replace an existing destination unless asked to
+    assert!(client.copy(&source, &copy, false).await.is_err());
+
+    let result = client.rename(&copy, &renamed, false).await.unwrap();
+    assert!(result.created);
+    assert!(client.get(&copy).await.unwrap().is_none());
+    assert_eq!(client.get(&renamed).await.unwrap(), Some(b"copied value".to_vec()));
+
+    let ops = vec![
+        BatchOp::Copy { source: source.clone(), destination: copy.clone(), overwrite: None },
+        BatchOp::Rename { source: copy.clone(), destination: renamed.clone(), overwrite: Some(false) },
+    ];
+    let response = client.batch(ops).await.unwrap();
+    assert!(matches!(&response.results[0], kv_storage_client::BatchResult::Copy { created: true, .. }));
+    assert!(response.results[1].is_error());
+
+    cleanup(&client, &[&source, &copy, &renamed]).await;
+}
+
 // ========== Session Management Tests ==========
 
 #[tokio::test]
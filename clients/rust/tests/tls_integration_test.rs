@@ -89,8 +89,8 @@ async fn test_https_batch_operations() {
     use kv_storage_client::BatchOp;
     
     let ops = vec![
-        BatchOp::Put { key: key1.clone(), value: "value1".to_string() },
-        BatchOp::Put { key: key2.clone(), value: "value2".to_string() },
+        BatchOp::Put { key: key1.clone(), value: b"value1".to_vec() },
+        BatchOp::Put { key: key2.clone(), value: b"value2".to_vec() },
         BatchOp::Get { key: key1.clone() },
     ];
 
@@ -1,5 +1,8 @@
 use std::env;
 
+use crate::storage::versions::VersionPolicy;
+use crate::util::hash::HashAlgorithm;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub db_path: String,
@@ -12,6 +15,14 @@ pub struct Config {
     pub flush_interval_ms: Option<u64>,
     pub ssl_cert: Option<String>,
     pub ssl_key: Option<String>,
+    pub ttl_sweep_interval_ms: u64, // 0 = expired keys are never swept
+    pub gc_sweep_interval_ms: u64,  // 0 = no background orphan sweep
+    pub scrub_bytes_per_sec: u64,   // 0 = no background scrubbing
+    pub scrub_pass_interval_ms: u64, // Pause between full scrub passes
+    pub hash_algorithm: HashAlgorithm, // Content hash for newly written objects
+    pub versioning: VersionPolicy,     // Keys whose overwritten values are kept
+    pub trash_retention_secs: u64,     // 0 = deletes are permanent
+    pub max_value_size: u64,           // 0 = no limit
 }
 
 impl Config {
@@ -78,6 +89,70 @@ impl Config {
                 .unwrap_or(1000)
         );
 
+        // Parse TTL sweep interval (in milliseconds, default: 60000)
+        let ttl_sweep_interval_ms = env::var("KV_TTL_SWEEP_INTERVAL_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(60_000);
+
+        // Parse orphaned object sweep interval (in milliseconds, default: 1 hour)
+        let gc_sweep_interval_ms = env::var("KV_GC_SWEEP_INTERVAL_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(3_600_000);
+
+        // Background scrub rate (supports: 8M, 1G, etc., default: 8M per second)
+        let scrub_bytes_per_sec = match env::var("KV_SCRUB_BYTES_PER_SEC") {
+            Ok(s) => parse_size(&s)
+                .ok_or_else(|| format!("Invalid KV_SCRUB_BYTES_PER_SEC: {}", s))? as u64,
+            Err(_) => 8 * 1024 * 1024,
+        };
+
+        // Pause after each full scrub pass (in milliseconds, default: 1 day)
+        let scrub_pass_interval_ms = env::var("KV_SCRUB_PASS_INTERVAL_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(86_400_000);
+
+        // Content hash used to address new objects (default: xxhash3)
+        let hash_algorithm = match env::var("KV_HASH_ALGORITHM") {
+            Ok(name) => HashAlgorithm::parse(&name)
+                .ok_or_else(|| format!("Unsupported KV_HASH_ALGORITHM: {}", name))?,
+            Err(_) => HashAlgorithm::default(),
+        };
+
+        // Prefixes whose overwrites keep history, comma-separated; `*` is every key
+        let prefixes = env::var("KV_VERSIONED_PREFIXES")
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(|p| if p == "*" { String::new() } else { p.to_string() })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let max_versions = match env::var("KV_MAX_VERSIONS") {
+            Ok(s) => s.parse::<u64>().map_err(|_| format!("Invalid KV_MAX_VERSIONS: {}", s))?,
+            Err(_) => 10,
+        };
+        let max_age_secs = match env::var("KV_VERSION_MAX_AGE_SECS") {
+            Ok(s) => s.parse::<u64>().map_err(|_| format!("Invalid KV_VERSION_MAX_AGE_SECS: {}", s))?,
+            Err(_) => 0,
+        };
+        let versioning = VersionPolicy { prefixes, max_versions, max_age_secs };
+
+        let trash_retention_secs = match env::var("KV_TRASH_RETENTION_SECS") {
+            Ok(s) => s.parse::<u64>().map_err(|_| format!("Invalid KV_TRASH_RETENTION_SECS: {}", s))?,
+            Err(_) => 0,
+        };
+
+        // Largest value a PUT may store (supports: 64M, 1G, etc., default: 256M)
+        let max_value_size = match env::var("KV_MAX_VALUE_SIZE") {
+            Ok(s) => parse_size(&s)
+                .ok_or_else(|| format!("Invalid KV_MAX_VALUE_SIZE: {}", s))? as u64,
+            Err(_) => 256 * 1024 * 1024,
+        };
+
         Ok(Config {
             db_path,
             auth_token,
@@ -89,6 +164,14 @@ impl Config {
             flush_interval_ms,
             ssl_cert,
             ssl_key,
+            ttl_sweep_interval_ms,
+            gc_sweep_interval_ms,
+            scrub_bytes_per_sec,
+            scrub_pass_interval_ms,
+            hash_algorithm,
+            versioning,
+            trash_retention_secs,
+            max_value_size,
         })
     }
 }
@@ -149,6 +232,7 @@ mod tests {
         env::remove_var("PORT");
         env::remove_var("BIND_ADDR");
         env::remove_var("HOST");
+        env::remove_var("KV_TTL_SWEEP_INTERVAL_MS");
         // Set required env vars only
         env::set_var("TOKEN", "test-token");
 
@@ -162,6 +246,7 @@ mod tests {
         assert_eq!(config.compression_level, 1);
         assert!(config.cache_capacity_bytes.is_none());
         assert_eq!(config.flush_interval_ms, Some(1000));
+        assert_eq!(config.ttl_sweep_interval_ms, 60_000);
     }
 
     #[test]
@@ -353,6 +438,157 @@ mod tests {
         env::remove_var("SSL_KEY");
     }
 
+    #[test]
+    #[serial]
+    fn test_config_ttl_sweep_interval() {
+        env::remove_var("COMPRESSION_LEVEL");
+        env::remove_var("KV_CACHE_CAPACITY");
+        env::remove_var("KV_FLUSH_INTERVAL_MS");
+        env::set_var("TOKEN", "test-token");
+
+        env::set_var("KV_TTL_SWEEP_INTERVAL_MS", "0");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.ttl_sweep_interval_ms, 0);
+
+        // Clean up
+        env::remove_var("KV_TTL_SWEEP_INTERVAL_MS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_gc_sweep_interval() {
+        env::set_var("TOKEN", "test-token");
+
+        env::remove_var("KV_GC_SWEEP_INTERVAL_MS");
+        assert_eq!(Config::from_env().unwrap().gc_sweep_interval_ms, 3_600_000);
+
+        env::set_var("KV_GC_SWEEP_INTERVAL_MS", "0");
+        assert_eq!(Config::from_env().unwrap().gc_sweep_interval_ms, 0);
+
+        // Clean up
+        env::remove_var("KV_GC_SWEEP_INTERVAL_MS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_scrub() {
+        env::set_var("TOKEN", "test-token");
+
+        env::remove_var("KV_SCRUB_BYTES_PER_SEC");
+        env::remove_var("KV_SCRUB_PASS_INTERVAL_MS");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.scrub_bytes_per_sec, 8 * 1024 * 1024);
+        assert_eq!(config.scrub_pass_interval_ms, 86_400_000);
+
+        env::set_var("KV_SCRUB_BYTES_PER_SEC", "64K");
+        env::set_var("KV_SCRUB_PASS_INTERVAL_MS", "1000");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.scrub_bytes_per_sec, 64 * 1024);
+        assert_eq!(config.scrub_pass_interval_ms, 1000);
+
+        env::set_var("KV_SCRUB_BYTES_PER_SEC", "0");
+        assert_eq!(Config::from_env().unwrap().scrub_bytes_per_sec, 0);
+
+        env::set_var("KV_SCRUB_BYTES_PER_SEC", "fast");
+        assert!(Config::from_env().is_err());
+
+        // Clean up
+        env::remove_var("KV_SCRUB_BYTES_PER_SEC");
+        env::remove_var("KV_SCRUB_PASS_INTERVAL_MS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_versioning() {
+        env::set_var("TOKEN", "test-token");
+        env::remove_var("KV_MAX_VERSIONS");
+        env::remove_var("KV_VERSION_MAX_AGE_SECS");
+
+        env::remove_var("KV_VERSIONED_PREFIXES");
+        let config = Config::from_env().unwrap();
+        assert!(!config.versioning.applies_to("config/app.json"));
+        assert_eq!((config.versioning.max_versions, config.versioning.max_age_secs), (10, 0));
+
+        env::set_var("KV_VERSIONED_PREFIXES", "config/, settings/ ,");
+        env::set_var("KV_MAX_VERSIONS", "0");
+        env::set_var("KV_VERSION_MAX_AGE_SECS", "86400");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.versioning.prefixes, vec!["config/", "settings/"]);
+        assert!(config.versioning.applies_to("settings/theme"));
+        assert!(!config.versioning.applies_to("cache/page"));
+        assert_eq!((config.versioning.max_versions, config.versioning.max_age_secs), (0, 86400));
+
+        env::set_var("KV_VERSIONED_PREFIXES", "*");
+        assert!(Config::from_env().unwrap().versioning.applies_to("anything"));
+
+        env::set_var("KV_MAX_VERSIONS", "many");
+        assert!(Config::from_env().is_err());
+
+        // Clean up
+        env::remove_var("KV_VERSIONED_PREFIXES");
+        env::remove_var("KV_MAX_VERSIONS");
+        env::remove_var("KV_VERSION_MAX_AGE_SECS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_trash() {
+        env::set_var("TOKEN", "test-token");
+
+        env::remove_var("KV_TRASH_RETENTION_SECS");
+        assert_eq!(Config::from_env().unwrap().trash_retention_secs, 0);
+
+        env::set_var("KV_TRASH_RETENTION_SECS", "604800");
+        assert_eq!(Config::from_env().unwrap().trash_retention_secs, 604_800);
+
+        env::set_var("KV_TRASH_RETENTION_SECS", "1w");
+        assert!(Config::from_env().is_err());
+
+        // Clean up
+        env::remove_var("KV_TRASH_RETENTION_SECS");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_max_value_size() {
+        env::set_var("TOKEN", "test-token");
+
+        env::remove_var("KV_MAX_VALUE_SIZE");
+        assert_eq!(Config::from_env().unwrap().max_value_size, 256 * 1024 * 1024);
+
+        env::set_var("KV_MAX_VALUE_SIZE", "16M");
+        assert_eq!(Config::from_env().unwrap().max_value_size, 16 * 1024 * 1024);
+
+        env::set_var("KV_MAX_VALUE_SIZE", "0");
+        assert_eq!(Config::from_env().unwrap().max_value_size, 0);
+
+        env::set_var("KV_MAX_VALUE_SIZE", "lots");
+        assert!(Config::from_env().is_err());
+
+        // Clean up
+        env::remove_var("KV_MAX_VALUE_SIZE");
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_hash_algorithm() {
+        env::set_var("TOKEN", "test-token");
+
+        env::remove_var("KV_HASH_ALGORITHM");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.hash_algorithm, HashAlgorithm::Xxh3);
+
+        env::set_var("KV_HASH_ALGORITHM", "sha256");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.hash_algorithm, HashAlgorithm::Sha256);
+
+        env::set_var("KV_HASH_ALGORITHM", "md5");
+        assert!(Config::from_env().unwrap_err().contains("KV_HASH_ALGORITHM"));
+
+        // Clean up
+        env::remove_var("KV_HASH_ALGORITHM");
+    }
+
     // ===== PORT and HOST tests =====
 
     #[test]
@@ -9,8 +9,12 @@ pub enum Error {
     Auth(String),
     NotFound(String),
     Conflict(String),
+    PreconditionFailed(String),
+    Unavailable(String),
     InvalidRequest(String),
+    PayloadTooLarge(String),
     Compression(String),
+    Corrupt(String),
     Hash(String),
     Internal(String),
 }
@@ -23,8 +27,12 @@ impl fmt::Display for Error {
             Error::Auth(msg) => write!(f, "Authentication error: {}", msg),
             Error::NotFound(msg) => write!(f, "Not found: {}", msg),
             Error::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            Error::PreconditionFailed(msg) => write!(f, "Precondition failed: {}", msg),
+            Error::Unavailable(msg) => write!(f, "Service unavailable: {}", msg),
             Error::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
+            Error::PayloadTooLarge(msg) => write!(f, "Payload too large: {}", msg),
             Error::Compression(msg) => write!(f, "Compression error: {}", msg),
+            Error::Corrupt(msg) => write!(f, "Corrupt object: {}", msg),
             Error::Hash(msg) => write!(f, "Hash error: {}", msg),
             Error::Internal(msg) => write!(f, "Internal error: {}", msg),
         }
@@ -38,11 +46,14 @@ impl Error {
     pub fn status_code(&self) -> StatusCode {
         match self {
             Error::Storage(_) | Error::Transaction(_) | Error::Internal(_) |
-            Error::Compression(_) | Error::Hash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Compression(_) | Error::Corrupt(_) | Error::Hash(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Auth(_) => StatusCode::UNAUTHORIZED,
             Error::NotFound(_) => StatusCode::NOT_FOUND,
             Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
         }
     }
 }
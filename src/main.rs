@@ -5,9 +5,12 @@ use hyper_util::rt::{TokioIo, TokioExecutor};
 use tracing::{info, error};
 
 use kv_storage::Config;
-use kv_storage::storage::{DbWrapper, StorageDb};
+use kv_storage::storage::{DbWrapper, StorageDb, expiry, fsck, gc, scrub, trash, versions};
 use kv_storage::server::Handler;
-use kv_storage::util::{compression::Compressor, metrics::Metrics};
+use kv_storage::util::{compression::Compressor, metrics::Metrics, time::unix_now};
+
+/// Maximum number of expired keys removed per sweep tick
+const TTL_SWEEP_BATCH: usize = 10_000;
 
 fn build_http2_builder() -> http2::Builder<TokioExecutor> {
     let mut builder = http2::Builder::new(TokioExecutor::new());
@@ -57,8 +60,38 @@ fn load_tls_config(cert_path: &str, key_path: &str) -> Result<Arc<tokio_rustls::
     Ok(Arc::new(config))
 }
 
+/// `kv-storage fsck [--repair] [--db PATH]`: check the database offline and
+/// print the report as JSON. Exits 1 if problems were found and not repaired.
+fn run_fsck(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut repair = false;
+    let mut db_path = std::env::var("DB_PATH").unwrap_or_else(|_| "./kv_db".to_string());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--repair" => repair = true,
+            "--db" => db_path = args.next().ok_or("--db requires a path")?.clone(),
+            other => return Err(format!("Unknown fsck argument {} (usage: kv-storage fsck [--repair] [--db PATH])", other).into()),
+        }
+    }
+
+    let db: StorageDb = Arc::new(DbWrapper::open(&db_path)?);
+    let report = fsck::check(&db, &Compressor::default(), repair)?;
+    db.flush()?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.is_clean() && !report.repaired {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("fsck") {
+        return run_fsck(&args[2..]);
+    }
+
     // Initialize tracing
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -110,13 +143,157 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let handler = Handler::new(
         db.clone(),
         config.auth_token.clone(),
-        compressor,
+        compressor.clone(),
         metrics.clone(),
-    );
+    )
+    .with_hash_algorithm(config.hash_algorithm)
+    .with_versioning(config.versioning.clone())
+    .with_trash_retention(config.trash_retention_secs)
+    .with_max_value_size(config.max_value_size);
+    info!("Content hash: {}", config.hash_algorithm.name());
+    if !config.versioning.prefixes.is_empty() {
+        info!("Versioned prefixes: {:?} (keep {} versions, max age {} s)",
+            config.versioning.prefixes, config.versioning.max_versions, config.versioning.max_age_secs);
+    }
+    if config.trash_retention_secs > 0 {
+        info!("Trash retention: {} s", config.trash_retention_secs);
+    }
 
     // Set up graceful shutdown
     let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(false);
 
+    // Spawn TTL sweeper, which also drops versions and deleted keys past
+    // their retention
+    if config.ttl_sweep_interval_ms > 0 {
+        info!("TTL sweep interval: {} ms", config.ttl_sweep_interval_ms);
+        let db = db.clone();
+        let metrics = metrics.clone();
+        let versioning = config.versioning.clone();
+        let trash_retention_secs = config.trash_retention_secs;
+        let interval = std::time::Duration::from_millis(config.ttl_sweep_interval_ms);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let sweep_db = db.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    expiry::sweep_expired(&sweep_db, unix_now(), TTL_SWEEP_BATCH)
+                }).await;
+
+                match result {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(removed)) => {
+                        info!("TTL sweep removed {} expired keys", removed);
+                        metrics.add_expired(removed as u64);
+                    }
+                    Ok(Err(e)) => error!("TTL sweep error: {}", e),
+                    Err(e) => error!("TTL sweep task failed: {}", e),
+                }
+
+                // With the trash off this empties whatever an earlier run left
+                let purge_db = db.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    trash::purge(&purge_db, trash_retention_secs, unix_now())
+                }).await;
+
+                match result {
+                    Ok(Ok(report)) if report.purged == 0 => {}
+                    Ok(Ok(report)) => info!(
+                        "Trash purge dropped {} deleted keys, freeing {} objects",
+                        report.purged, report.freed_objects
+                    ),
+                    Ok(Err(e)) => error!("Trash purge error: {}", e),
+                    Err(e) => error!("Trash purge task failed: {}", e),
+                }
+
+                if !versioning.has_retention() {
+                    continue;
+                }
+                let db = db.clone();
+                let versioning = versioning.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    versions::prune(&db, &versioning, unix_now())
+                }).await;
+
+                match result {
+                    Ok(Ok(report)) if report.removed == 0 => {}
+                    Ok(Ok(report)) => info!(
+                        "Version retention dropped {} versions, freeing {} objects",
+                        report.removed, report.freed_objects
+                    ),
+                    Ok(Err(e)) => error!("Version retention error: {}", e),
+                    Err(e) => error!("Version retention task failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Spawn orphaned object sweeper
+    if config.gc_sweep_interval_ms > 0 {
+        info!("GC sweep interval: {} ms", config.gc_sweep_interval_ms);
+        let db = db.clone();
+        let metrics = metrics.clone();
+        let interval = std::time::Duration::from_millis(config.gc_sweep_interval_ms);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let db = db.clone();
+                let result = tokio::task::spawn_blocking(move || gc::sweep_orphans(&db)).await;
+
+                match result {
+                    Ok(Ok(report)) if report.reclaimed_objects == 0 => {}
+                    Ok(Ok(report)) => {
+                        info!(
+                            "GC sweep reclaimed {} orphaned objects ({} bytes)",
+                            report.reclaimed_objects, report.reclaimed_bytes
+                        );
+                        metrics.add_gc_reclaimed(report.reclaimed_objects, report.reclaimed_bytes);
+                    }
+                    Ok(Err(e)) => error!("GC sweep error: {}", e),
+                    Err(e) => error!("GC sweep task failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Spawn object scrubber: each tick verifies about one second's worth of
+    // bytes, then it rests between full passes
+    if config.scrub_bytes_per_sec > 0 {
+        info!("Scrub rate: {} bytes/s, pass interval: {} ms",
+            config.scrub_bytes_per_sec, config.scrub_pass_interval_ms);
+        let db = db.clone();
+        let metrics = metrics.clone();
+        let budget = config.scrub_bytes_per_sec;
+        let pass_interval = std::time::Duration::from_millis(config.scrub_pass_interval_ms);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let db = db.clone();
+                let compressor = compressor.clone();
+                let result = tokio::task::spawn_blocking(move || scrub::scrub_step(&db, &compressor, budget)).await;
+
+                match result {
+                    Ok(Ok(progress)) => {
+                        metrics.add_scrubbed(progress.objects, progress.bytes, progress.corrupt, progress.pass_complete);
+                        if progress.pass_complete {
+                            info!("Scrub pass complete");
+                            tokio::time::sleep(pass_interval).await;
+                            ticker.reset();
+                        }
+                    }
+                    Ok(Err(e)) => error!("Scrub error: {}", e),
+                    Err(e) => error!("Scrub task failed: {}", e),
+                }
+            }
+        });
+    }
+
     // Spawn signal handler
     tokio::spawn(async move {
         let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
@@ -0,0 +1,198 @@
+//! Response body types
+//!
+//! Small responses are sent as a single buffer. Large values are produced by a
+//! blocking task and streamed to the client through a bounded channel, so a
+//! slow reader never causes more than a few chunks to be buffered.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use tokio::sync::mpsc;
+
+use crate::error::Error;
+
+/// Size of each streamed chunk
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks buffered between a blocking producer and the connection
+pub const STREAM_CHANNEL_DEPTH: usize = 4;
+
+/// Body type returned by all handlers
+pub type ResponseBody = BoxBody<Bytes, Error>;
+
+/// A body sent as a single buffer.
+#[inline]
+pub fn full(data: impl Into<Bytes>) -> ResponseBody {
+    Full::new(data.into()).map_err(|never| match never {}).boxed()
+}
+
+/// An empty body.
+#[inline]
+pub fn empty() -> ResponseBody {
+    Empty::new().map_err(|never| match never {}).boxed()
+}
+
+/// A body sent as a sequence of buffers, without joining them.
+pub fn chunks(chunks: Vec<Bytes>) -> ResponseBody {
+    ChunksBody { chunks: chunks.into() }.boxed()
+}
+
+/// Streams `range` of the bytes produced by `reader` from a blocking task.
+///
+/// The task stops as soon as the client goes away.
+pub fn stream_reader<R>(reader: R, range: Range<u64>) -> ResponseBody
+where
+    R: Read + Send + 'static,
+{
+    stream_reader_parts(reader, vec![(Bytes::new(), range)], Bytes::new())
+}
+
+/// Streams several ranges of the bytes produced by `reader` from a blocking
+/// task, each preceded by its header and followed at the end by `trailer`.
+///
+/// The ranges must be in ascending order and must not overlap, so the
+/// reader is read once from start to finish. The task stops as soon as the
+/// client goes away.
+pub fn stream_reader_parts<R>(reader: R, parts: Vec<(Bytes, Range<u64>)>, trailer: Bytes) -> ResponseBody
+where
+    R: Read + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_DEPTH);
+
+    tokio::task::spawn_blocking(move || {
+        let mut reader = reader;
+        let mut position = 0;
+        for (header, range) in parts {
+            if !header.is_empty() && tx.blocking_send(Ok(header)).is_err() {
+                return;
+            }
+            let skip = range.start.saturating_sub(position);
+            if let Err(e) = std::io::copy(&mut reader.by_ref().take(skip), &mut std::io::sink()) {
+                let _ = tx.blocking_send(Err(read_error(e)));
+                return;
+            }
+            if !send_range(&tx, reader.by_ref().take(range.end.saturating_sub(range.start))) {
+                return;
+            }
+            position = range.end;
+        }
+        if !trailer.is_empty() {
+            let _ = tx.blocking_send(Ok(trailer));
+        }
+    });
+
+    ChannelBody { rx }.boxed()
+}
+
+/// Sends everything `reader` yields in chunks. Returns false if reading
+/// failed or the client went away.
+fn send_range(tx: &mpsc::Sender<Result<Bytes, Error>>, mut reader: impl Read) -> bool {
+    loop {
+        let mut chunk = vec![0u8; STREAM_CHUNK_SIZE];
+        let item = match reader.read(&mut chunk) {
+            Ok(0) => return true,
+            Ok(n) => {
+                chunk.truncate(n);
+                Ok(Bytes::from(chunk))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(read_error(e)),
+        };
+        let failed = item.is_err();
+        if tx.blocking_send(item).is_err() || failed {
+            return false;
+        }
+    }
+}
+
+/// A body fed through the returned sender, for output produced over time,
+/// such as progress of a long operation.
+pub fn channel() -> (mpsc::Sender<Result<Bytes, Error>>, ResponseBody) {
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_DEPTH);
+    (tx, ChannelBody { rx }.boxed())
+}
+
+fn read_error(e: std::io::Error) -> Error {
+    Error::Compression(format!("Decompression failed: {}", e))
+}
+
+/// Body made of buffers that are already in memory.
+struct ChunksBody {
+    chunks: VecDeque<Bytes>,
+}
+
+impl Body for ChunksBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(self.chunks.pop_front().map(|chunk| Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.chunks.iter().map(|chunk| chunk.len() as u64).sum())
+    }
+}
+
+/// Body fed by a channel of chunks.
+struct ChannelBody {
+    rx: mpsc::Receiver<Result<Bytes, Error>>,
+}
+
+impl Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.rx.poll_recv(cx).map(|item| item.map(|chunk| chunk.map(Frame::data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_reader_range() {
+        let data: Vec<u8> = (0..STREAM_CHUNK_SIZE * 3).map(|i| i as u8).collect();
+        let range = 10..(STREAM_CHUNK_SIZE as u64 * 2 + 5);
+
+        let body = stream_reader(std::io::Cursor::new(data.clone()), range.clone());
+        let collected = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&collected[..], &data[range.start as usize..range.end as usize]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reader_parts() {
+        let data: Vec<u8> = (0..STREAM_CHUNK_SIZE * 3).map(|i| i as u8).collect();
+        let far = STREAM_CHUNK_SIZE as u64 * 2;
+        let parts = vec![
+            (Bytes::from_static(b"<a>"), 2..4),
+            (Bytes::from_static(b"<b>"), far..far + 3),
+        ];
+
+        let body = stream_reader_parts(std::io::Cursor::new(data.clone()), parts, Bytes::from_static(b"<end>"));
+        let collected = body.collect().await.unwrap().to_bytes();
+        let mut expected = b"<a>".to_vec();
+        expected.extend_from_slice(&data[2..4]);
+        expected.extend_from_slice(b"<b>");
+        expected.extend_from_slice(&data[far as usize..far as usize + 3]);
+        expected.extend_from_slice(b"<end>");
+        assert_eq!(&collected[..], &expected[..]);
+    }
+}
@@ -1,5 +1,4 @@
-use hyper::{Request, Response, body::Incoming, Version};
-use http_body_util::Full;
+use hyper::{Method, Request, Response, body::Incoming, Version};
 use hyper::body::Bytes;
 use std::sync::Arc;
 use std::future::Future;
@@ -8,9 +7,11 @@ use tracing::{info, debug};
 use zeroize::Zeroize;
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, full};
 use crate::storage::StorageDb;
+use crate::storage::versions::VersionPolicy;
 use crate::server::middleware::auth::check_auth;
-use crate::util::{compression::Compressor, metrics::Metrics};
+use crate::util::{compression::Compressor, hash::HashAlgorithm, metrics::Metrics};
 use crate::server::handlers;
 
 /// Secure token wrapper that zeros memory on drop.
@@ -41,6 +42,10 @@ pub struct Handler {
     auth_token: AuthToken,
     compressor: Arc<Compressor>,
     metrics: Arc<Metrics>,
+    hash_algorithm: HashAlgorithm,
+    versioning: Arc<VersionPolicy>,
+    trash_retention_secs: u64,
+    max_value_size: u64,
 }
 
 impl Handler {
@@ -55,10 +60,38 @@ impl Handler {
             auth_token: AuthToken::new(auth_token),
             compressor,
             metrics,
+            hash_algorithm: HashAlgorithm::default(),
+            versioning: Arc::default(),
+            trash_retention_secs: 0,
+            max_value_size: 0,
         }
     }
 
-    pub async fn handle(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Error> {
+    /// Address new objects with `algorithm` instead of xxHash3.
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Keep overwritten values of the keys `policy` covers.
+    pub fn with_versioning(mut self, policy: VersionPolicy) -> Self {
+        self.versioning = Arc::new(policy);
+        self
+    }
+
+    /// Move deleted keys to the trash for `secs` seconds; 0 deletes outright.
+    pub fn with_trash_retention(mut self, secs: u64) -> Self {
+        self.trash_retention_secs = secs;
+        self
+    }
+
+    /// Reject values larger than `bytes` with `413`; 0 accepts any size.
+    pub fn with_max_value_size(mut self, bytes: u64) -> Self {
+        self.max_value_size = bytes;
+        self
+    }
+
+    pub async fn handle(&self, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
         // Log request with HTTP version
         let http_version = format_http_version(req.version());
         debug!("{} {} {}", req.method(), req.uri().path(), http_version);
@@ -81,60 +114,121 @@ impl Handler {
             }
             ("GET", "/metrics") => self.handle_metrics(),
             ("GET", "/keys") => self.handle_list_keys(query.as_deref()),
+            ("GET", "/trash") => self.handle_list_trash(query.as_deref()),
+            ("GET", _) if handlers::objects::parse_keys_path(&path).is_some() => {
+                self.handle_object_keys(&path, query.as_deref())
+            }
+            ("GET" | "HEAD", _) if handlers::objects::parse_object_path(&path).is_some() => {
+                self.handle_get_object(&path, method == Method::HEAD).await
+            }
             ("GET", _) if path.len() > 1 => {
                 let key = &path[1..];
-                self.handle_get(key).await
+                self.handle_get(key, req).await
             }
             ("HEAD", _) if path.len() > 1 => {
                 let key = &path[1..];
-                self.handle_head(key).await
+                self.handle_head(key, req).await
             }
+            // A bare DELETE /keys still deletes the key named "keys"
+            ("DELETE", "/keys") if query.is_some() => self.handle_delete_prefix(query.as_deref()).await,
             ("DELETE", _) if path.len() > 1 => {
                 let key = &path[1..];
-                self.handle_delete(key).await
+                self.handle_delete(key, req).await
             }
             ("POST", "/batch") => self.handle_batch(req).await,
+            ("POST", "/objects/missing") => self.handle_missing_objects(req).await,
+            ("POST", "/copy") => self.handle_copy(req, false).await,
+            ("POST", "/rename") => self.handle_copy(req, true).await,
+            ("POST", "/restore") => self.handle_restore(req).await,
+            ("POST", "/trash/restore") => self.handle_restore_deleted(req).await,
+            ("POST", "/admin/gc") => self.handle_gc().await,
+            ("POST", "/admin/rehash") => self.handle_rehash().await,
+            ("POST", "/admin/fsck") => self.handle_fsck(query.as_deref()).await,
             _ => Err(Error::NotFound("Path not found".to_string())),
         };
 
         result.or_else(|e| Ok(Self::error_response(e)))
     }
 
-    async fn handle_put(&self, key: &str, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Error> {
+    async fn handle_put(&self, key: &str, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
         handlers::put::handle_put(self, key, req).await
     }
 
-    async fn handle_get(&self, key: &str) -> Result<Response<Full<Bytes>>, Error> {
-        handlers::get::handle_get(self, key).await
+    async fn handle_get(&self, key: &str, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::get::handle_get(self, key, req).await
+    }
+
+    async fn handle_head(&self, key: &str, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::head::handle_head(self, key, req).await
+    }
+
+    async fn handle_delete(&self, key: &str, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::delete::handle_delete(self, key, req).await
     }
 
-    async fn handle_head(&self, key: &str) -> Result<Response<Full<Bytes>>, Error> {
-        handlers::head::handle_head(self, key).await
+    async fn handle_delete_prefix(&self, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
+        handlers::delete::handle_delete_prefix(self, query).await
     }
 
-    async fn handle_delete(&self, key: &str) -> Result<Response<Full<Bytes>>, Error> {
-        handlers::delete::handle_delete(self, key).await
+    async fn handle_restore(&self, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::versions::handle_restore(self, req).await
     }
 
-    fn handle_list_keys(&self, query: Option<&str>) -> Result<Response<Full<Bytes>>, Error> {
+    fn handle_list_trash(&self, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
+        handlers::trash::handle_list_trash(self, query)
+    }
+
+    async fn handle_restore_deleted(&self, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::trash::handle_restore_deleted(self, req).await
+    }
+
+    fn handle_list_keys(&self, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
         handlers::list::handle_list(self, query)
     }
 
-    async fn handle_batch(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Error> {
+    fn handle_object_keys(&self, path: &str, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
+        handlers::objects::handle_object_keys(self, path, query)
+    }
+
+    async fn handle_get_object(&self, path: &str, head_only: bool) -> Result<Response<ResponseBody>, Error> {
+        handlers::objects::handle_get_object(self, path, head_only).await
+    }
+
+    async fn handle_missing_objects(&self, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
+        handlers::objects::handle_missing_objects(self, req).await
+    }
+
+    async fn handle_copy(&self, req: Request<Incoming>, rename: bool) -> Result<Response<ResponseBody>, Error> {
+        handlers::copy::handle_copy(self, req, rename).await
+    }
+
+    async fn handle_batch(&self, req: Request<Incoming>) -> Result<Response<ResponseBody>, Error> {
         handlers::batch::handle_batch(self, req).await
     }
 
-    fn handle_metrics(&self) -> Result<Response<Full<Bytes>>, Error> {
+    async fn handle_gc(&self) -> Result<Response<ResponseBody>, Error> {
+        handlers::admin::handle_gc(self).await
+    }
+
+    async fn handle_rehash(&self) -> Result<Response<ResponseBody>, Error> {
+        handlers::admin::handle_rehash(self).await
+    }
+
+    async fn handle_fsck(&self, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
+        handlers::admin::handle_fsck(self, query).await
+    }
+
+    fn handle_metrics(&self) -> Result<Response<ResponseBody>, Error> {
         handlers::metrics::handle_metrics(self)
     }
 
-    fn error_response(error: Error) -> Response<Full<Bytes>> {
+    fn error_response(error: Error) -> Response<ResponseBody> {
         let status = error.status_code();
         let body = Bytes::from(format!("Error: {}\n", error));
         Response::builder()
             .status(status)
             .header("Content-Type", "text/plain")
-            .body(Full::new(body))
+            .body(full(body))
             .unwrap()
     }
 
@@ -153,6 +247,31 @@ impl Handler {
     pub fn metrics(&self) -> &Arc<Metrics> {
         &self.metrics
     }
+
+    #[inline]
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    #[inline]
+    pub fn versioning(&self) -> &Arc<VersionPolicy> {
+        &self.versioning
+    }
+
+    #[inline]
+    pub fn trash_retention(&self) -> u64 {
+        self.trash_retention_secs
+    }
+
+    /// Fails with `413` if a value of `size` bytes is over the configured limit.
+    pub fn check_value_size(&self, size: u64) -> Result<(), Error> {
+        if self.max_value_size > 0 && size > self.max_value_size {
+            return Err(Error::PayloadTooLarge(format!(
+                "Value exceeds the {} byte limit", self.max_value_size
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// Format HTTP version for logging
@@ -169,7 +288,7 @@ fn format_http_version(version: Version) -> &'static str {
 
 // Implement Hyper's Service trait for HTTP/2
 impl hyper::service::Service<Request<Incoming>> for Handler {
-    type Response = Response<Full<Bytes>>;
+    type Response = Response<ResponseBody>;
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
@@ -0,0 +1,74 @@
+use hyper::{Response, StatusCode};
+use hyper::body::Bytes;
+
+use crate::error::Error;
+use crate::server::body::{ResponseBody, full};
+use crate::server::Handler;
+use crate::storage::{fsck, gc, rehash};
+
+/// Run an orphan sweep now and report what it reclaimed.
+pub async fn handle_gc(handler: &Handler) -> Result<Response<ResponseBody>, Error> {
+    let db = handler.db().clone();
+    let report = tokio::task::spawn_blocking(move || gc::sweep_orphans(&db))
+        .await
+        .map_err(|e| Error::Internal(format!("GC task failed: {}", e)))??;
+
+    handler.metrics().add_gc_reclaimed(report.reclaimed_objects, report.reclaimed_bytes);
+
+    let json = serde_json::to_string(&report)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// Move every key onto the configured content hash.
+pub async fn handle_rehash(handler: &Handler) -> Result<Response<ResponseBody>, Error> {
+    let db = handler.db().clone();
+    let compressor = handler.compressor().clone();
+    let algorithm = handler.hash_algorithm();
+    let report = tokio::task::spawn_blocking(move || rehash::rehash_keys(&db, &compressor, algorithm))
+        .await
+        .map_err(|e| Error::Internal(format!("Rehash task failed: {}", e)))??;
+
+    let json = serde_json::to_string(&report)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// Check the storage trees, repairing them with `?repair=true`.
+pub async fn handle_fsck(handler: &Handler, query: Option<&str>) -> Result<Response<ResponseBody>, Error> {
+    let mut repair = false;
+    for (k, v) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        if k == "repair" {
+            repair = match v.as_ref() {
+                "" | "1" | "true" => true,
+                "0" | "false" => false,
+                _ => return Err(Error::InvalidRequest(format!("Invalid repair value: {}", v))),
+            };
+        }
+    }
+
+    let db = handler.db().clone();
+    let compressor = handler.compressor().clone();
+    let report = tokio::task::spawn_blocking(move || fsck::check(&db, &compressor, repair))
+        .await
+        .map_err(|e| Error::Internal(format!("Fsck task failed: {}", e)))??;
+
+    let json = serde_json::to_string(&report)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
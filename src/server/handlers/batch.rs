@@ -1,21 +1,128 @@
-use hyper::{Request, Response, StatusCode, body::Incoming};
-use http_body_util::Full;
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyper::{HeaderMap, Request, Response, StatusCode, body::Incoming};
 use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use serde::de::{self, Visitor};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, read_body_to_bytes};
+use crate::server::body::{ResponseBody, full};
 use crate::server::Handler;
-use crate::util::hash::Hash;
+use crate::server::handlers::common::{expiry_from_ttl, precondition_from, validate_key, validate_new_key};
+use crate::server::handlers::list::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::server::handlers::copy;
+use crate::storage::{dedup, scrub, BatchWrite, KeyMeta, KeyRange, Precondition, TransactionManager};
+use crate::util::checksum::ExpectedChecksums;
+use crate::util::hash::{Hash, HashAlgorithm};
+use crate::util::time::unix_now;
+
+/// Content type of the binary batch format
+const CBOR_CONTENT_TYPE: &str = "application/cbor";
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "op")]
 pub enum BatchOp {
     #[serde(rename = "put")]
-    Put { key: String, value: String },
+    Put {
+        key: String,
+        value: BatchValue,
+        /// How a text `value` is encoded, overriding the request's default
+        #[serde(default)]
+        encoding: Option<ValueEncoding>,
+        /// Optional lifetime in seconds
+        #[serde(default)]
+        ttl: Option<u64>,
+        /// Same semantics as the `If-Match` header
+        #[serde(default)]
+        if_match: Option<String>,
+        /// Same semantics as the `If-None-Match` header
+        #[serde(default)]
+        if_none_match: Option<String>,
+        /// Same semantics as the `X-Expected-Hash` header
+        #[serde(default)]
+        expected_hash: Option<String>,
+        /// Same semantics as the `Content-MD5` header
+        #[serde(default)]
+        content_md5: Option<String>,
+        /// Same semantics as the `Digest` header
+        #[serde(default)]
+        digest: Option<String>,
+    },
+    /// Point a key at an already stored object, like a PUT with `X-Content-Hash`
+    #[serde(rename = "link")]
+    Link {
+        key: String,
+        hash: String,
+        #[serde(default)]
+        ttl: Option<u64>,
+        #[serde(default)]
+        if_match: Option<String>,
+        #[serde(default)]
+        if_none_match: Option<String>,
+    },
+    /// Same semantics as `POST /copy`
+    #[serde(rename = "copy")]
+    Copy {
+        source: String,
+        destination: String,
+        #[serde(default)]
+        overwrite: Option<bool>,
+    },
+    /// Same semantics as `POST /rename`
+    #[serde(rename = "rename")]
+    Rename {
+        source: String,
+        destination: String,
+        #[serde(default)]
+        overwrite: Option<bool>,
+    },
     #[serde(rename = "get")]
-    Get { key: String },
+    Get {
+        key: String,
+        /// How to encode the value in a JSON response, overriding the
+        /// request's default
+        #[serde(default)]
+        encoding: Option<ValueEncoding>,
+    },
     #[serde(rename = "delete")]
-    Delete { key: String },
+    Delete {
+        key: String,
+        #[serde(default)]
+        if_match: Option<String>,
+        #[serde(default)]
+        if_none_match: Option<String>,
+    },
+    /// Metadata of a key without its value
+    #[serde(rename = "head")]
+    Head { key: String },
+    #[serde(rename = "exists")]
+    Exists { key: String },
+    /// Write `value` only if the key holds the `expected` hash, or does not
+    /// exist when `expected` is absent; a mismatch is reported, not an error
+    #[serde(rename = "cas", alias = "compare_and_swap")]
+    Cas {
+        key: String,
+        value: BatchValue,
+        #[serde(default)]
+        encoding: Option<ValueEncoding>,
+        #[serde(default)]
+        expected: Option<String>,
+        #[serde(default)]
+        ttl: Option<u64>,
+    },
+    /// Up to `limit` keys under `prefix`, in key order
+    #[serde(rename = "list")]
+    List {
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        start_after: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
 }
 
 #[derive(Debug, Serialize)]
@@ -23,61 +130,217 @@ pub enum BatchResult {
     #[serde(rename = "put")]
     Put { key: String, hash: String, created: bool },
     #[serde(rename = "get")]
-    Get { key: String, value: Option<String>, found: bool },
+    Get {
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<BatchValue>,
+        found: bool,
+    },
     #[serde(rename = "delete")]
     Delete { key: String, deleted: bool },
+    /// `key` is the destination
+    #[serde(rename = "copy")]
+    Copy { key: String, source: String, hash: String, created: bool },
+    #[serde(rename = "rename")]
+    Rename { key: String, source: String, hash: String, created: bool },
+    #[serde(rename = "head")]
+    Head {
+        key: String,
+        found: bool,
+        #[serde(flatten, skip_serializing_if = "Option::is_none")]
+        meta: Option<HeadInfo>,
+    },
+    #[serde(rename = "exists")]
+    Exists { key: String, exists: bool },
+    /// `hash` is what the key holds after the op: the new value's if it
+    /// was swapped, otherwise the current one, absent if there is none
+    #[serde(rename = "cas")]
+    Cas {
+        key: String,
+        swapped: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+    #[serde(rename = "list")]
+    List {
+        prefix: String,
+        keys: Vec<ListedKey>,
+        /// Whether more keys follow the last one listed
+        more: bool,
+    },
     #[serde(rename = "error")]
     Error { key: String, error: String },
 }
 
-// Helper to ensure consistent JSON serialization
-impl BatchResult {
-    fn to_json_result(&self) -> serde_json::Value {
+/// Metadata a batch `head` reports for a live key
+#[derive(Debug, Serialize)]
+pub struct HeadInfo {
+    hash: String,
+    hash_algorithm: String,
+    size: u64,
+    refs: u64,
+    created_at: u64,
+    modified_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+/// One key of a batch `list`
+#[derive(Debug, Serialize)]
+pub struct ListedKey {
+    key: String,
+    hash: String,
+    size: u64,
+    modified_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+/// How text values are encoded in a JSON batch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueEncoding {
+    /// The text is the value; binary values read back lossily
+    #[default]
+    Utf8,
+    /// Standard base64 with padding, safe for any value
+    Base64,
+}
+
+/// A value carried in a batch: a string in JSON, and either a string or a
+/// byte string in CBOR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchValue {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl BatchValue {
+    /// The raw value, decoding text as `encoding` says. Byte strings are
+    /// taken as they are.
+    pub fn decode(self, encoding: ValueEncoding) -> Result<Vec<u8>, Error> {
+        match (self, encoding) {
+            (BatchValue::Bytes(bytes), _) => Ok(bytes),
+            (BatchValue::Text(text), ValueEncoding::Utf8) => Ok(text.into_bytes()),
+            (BatchValue::Text(text), ValueEncoding::Base64) => BASE64.decode(text.as_bytes())
+                .map_err(|e| Error::InvalidRequest(format!("Invalid base64 value: {}", e))),
+        }
+    }
+
+    /// `data` as it is returned in `format` with `encoding`.
+    pub fn encode(data: &[u8], format: BatchFormat, encoding: ValueEncoding) -> Self {
+        match (format, encoding) {
+            (BatchFormat::Cbor, _) => BatchValue::Bytes(data.to_vec()),
+            (BatchFormat::Json, ValueEncoding::Utf8) => BatchValue::Text(String::from_utf8_lossy(data).into_owned()),
+            (BatchFormat::Json, ValueEncoding::Base64) => BatchValue::Text(BASE64.encode(data)),
+        }
+    }
+}
+
+impl Serialize for BatchValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         match self {
-            BatchResult::Put { key, hash, created } => {
-                serde_json::json!({
-                    "put": {
-                        "key": key,
-                        "hash": hash,
-                        "created": created
-                    }
-                })
-            }
-            BatchResult::Get { key, value, found } => {
-                if let Some(v) = value {
-                    serde_json::json!({
-                        "get": {
-                            "key": key,
-                            "value": v,
-                            "found": found
-                        }
-                    })
-                } else {
-                    serde_json::json!({
-                        "get": {
-                            "key": key,
-                            "found": found
-                        }
-                    })
-                }
+            BatchValue::Text(text) => serializer.serialize_str(text),
+            BatchValue::Bytes(bytes) => serializer.serialize_bytes(bytes),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BatchValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = BatchValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or byte string")
             }
-            BatchResult::Delete { key, deleted } => {
-                serde_json::json!({
-                    "delete": {
-                        "key": key,
-                        "deleted": deleted
-                    }
-                })
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<BatchValue, E> {
+                Ok(BatchValue::Text(v.to_string()))
             }
-            BatchResult::Error { key, error } => {
-                serde_json::json!({
-                    "error": {
-                        "key": key,
-                        "error": error
-                    }
-                })
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<BatchValue, E> {
+                Ok(BatchValue::Text(v))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<BatchValue, E> {
+                Ok(BatchValue::Bytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<BatchValue, E> {
+                Ok(BatchValue::Bytes(v))
             }
         }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Wire format of a batch, chosen by the request's `Content-Type`; the
+/// response is sent in the same format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    Json,
+    Cbor,
+}
+
+impl BatchFormat {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let is_cbor = headers.get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim_start().starts_with(CBOR_CONTENT_TYPE));
+        if is_cbor { BatchFormat::Cbor } else { BatchFormat::Json }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            BatchFormat::Json => "application/json",
+            BatchFormat::Cbor => CBOR_CONTENT_TYPE,
+        }
+    }
+
+    /// Parse a batch given as a bare list of ops or as a request object.
+    fn parse(self, data: &[u8]) -> Result<BatchRequest, Error> {
+        match self {
+            BatchFormat::Json => {
+                let parsed = if data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+                    serde_json::from_slice::<BatchRequest>(data)
+                } else {
+                    serde_json::from_slice::<Vec<BatchOp>>(data).map(BatchRequest::from)
+                };
+                parsed.map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))
+            }
+            BatchFormat::Cbor => {
+                // Major type 5 is a map, anything else is taken for the list
+                let parsed = if data.first().is_some_and(|b| b >> 5 == 5) {
+                    ciborium::from_reader::<BatchRequest, _>(data)
+                } else {
+                    ciborium::from_reader::<Vec<BatchOp>, _>(data).map(BatchRequest::from)
+                };
+                parsed.map_err(|e| Error::InvalidRequest(format!("Invalid CBOR: {}", e)))
+            }
+        }
+    }
+
+    fn respond<T: Serialize>(self, status: StatusCode, body: &T) -> Result<Response<ResponseBody>, Error> {
+        let bytes = match self {
+            BatchFormat::Json => serde_json::to_vec_pretty(body)
+                .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?,
+            BatchFormat::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(body, &mut buf)
+                    .map_err(|e| Error::Internal(format!("CBOR serialization error: {}", e)))?;
+                buf
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .header("Content-Type", self.content_type())
+            .body(full(Bytes::from(bytes)))
+            .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
     }
 }
 
@@ -86,50 +349,172 @@ pub struct BatchResponse {
     results: Vec<BatchResult>,
 }
 
+/// `POST /batch` body in object form, which carries options alongside the ops
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    /// Apply every write in one transaction, all or nothing
+    #[serde(default)]
+    atomic: bool,
+    /// Default encoding of the ops' text values
+    #[serde(default)]
+    encoding: ValueEncoding,
+    ops: Vec<BatchOp>,
+}
+
+impl From<Vec<BatchOp>> for BatchRequest {
+    fn from(ops: Vec<BatchOp>) -> Self {
+        Self { atomic: false, encoding: ValueEncoding::default(), ops }
+    }
+}
+
+/// Response to an atomic batch that was rolled back
+#[derive(Debug, Serialize)]
+struct AtomicFailure {
+    error: String,
+    /// Position of the op that failed, absent if the batch failed as a whole
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+/// A batch put validated, encoded and addressed, ready to be written
+struct PreparedPut {
+    compressed: Vec<u8>,
+    hash: Hash,
+    algorithm: HashAlgorithm,
+    size: u64,
+    expires_at: Option<u64>,
+}
+
 pub async fn handle_batch(
     handler: &Handler,
     req: Request<Incoming>,
-) -> Result<Response<Full<Bytes>>, Error> {
+) -> Result<Response<ResponseBody>, Error> {
+    let format = BatchFormat::from_headers(req.headers());
+
     // Read body
     let data = read_body_to_bytes(req.into_body()).await?;
 
-    // Parse operations
-    let ops: Vec<BatchOp> = serde_json::from_slice(&data)
-        .map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+    // Parse operations, given as a bare array or as `{"ops": [...], ...}`
+    let request = format.parse(&data)?;
+
+    if request.atomic {
+        return handle_atomic_batch(handler, request.ops, request.encoding, format);
+    }
 
     // Pre-allocate results vector with capacity to avoid reallocations
-    let mut results = Vec::with_capacity(ops.len());
+    let mut results = Vec::with_capacity(request.ops.len());
 
-    for op in ops {
+    for op in request.ops {
         let result = match op {
-            BatchOp::Put { key, value } => {
-                let value_bytes = value.into_bytes();
-                let hash = Hash::compute(&value_bytes);
-                let hash_str = hash.to_hex_string();
-                let compressed = handler.compressor().compress(&value_bytes)?;
-                let size = value_bytes.len() as u64;
+            BatchOp::Put { key, value, encoding, ttl, if_match, if_none_match, expected_hash, content_md5, digest } => {
+                let precondition = match validate_new_key(&key).and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref())) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        results.push(BatchResult::Error { key, error: e.to_string() });
+                        continue;
+                    }
+                };
+                let put = value.decode(encoding.unwrap_or(request.encoding))
+                    .and_then(|value| {
+                        let checks = ExpectedChecksums::parse(expected_hash.as_deref(), content_md5.as_deref(), digest.as_deref())?;
+                        prepare_put(handler, &value, ttl, checks)
+                    });
+                let put = match put {
+                    Ok(put) => put,
+                    Err(e) => {
+                        results.push(BatchResult::Error { key, error: e.to_string() });
+                        continue;
+                    }
+                };
 
                 // Store or update using atomic transaction
-                let tx_manager = crate::storage::TransactionManager::new(handler.db().clone());
-                match tx_manager.update_key_atomic(&key, &compressed, &hash, size) {
-                    Ok(old_hash) => {
+                let tx_manager = crate::storage::TransactionManager::new(handler.db().clone())
+                    .with_hash_algorithm(put.algorithm)
+                    .with_versioning(handler.versioning().clone())
+                    .with_dedup_verify(Some(handler.compressor().clone()));
+                match tx_manager.update_key_if(&key, &put.compressed, &put.hash, put.size, put.expires_at, &precondition) {
+                    Ok((old_hash, _)) => {
                         handler.metrics().inc_puts();
                         let created = old_hash.is_none();
-                        BatchResult::Put { key, hash: hash_str, created }
+                        BatchResult::Put { key, hash: put.hash.to_hex_string(), created }
+                    }
+                    Err(e) => BatchResult::Error { key, error: e.to_string() }
+                }
+            }
+            BatchOp::Link { key, hash, ttl, if_match, if_none_match } => {
+                let precondition = match validate_new_key(&key).and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref())) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        results.push(BatchResult::Error { key, error: e.to_string() });
+                        continue;
+                    }
+                };
+                let (hash, algorithm, expires_at) = match prepare_link(handler, &hash, ttl) {
+                    Ok(link) => link,
+                    Err(e) => {
+                        results.push(BatchResult::Error { key, error: e.to_string() });
+                        continue;
+                    }
+                };
+                let tx_manager = crate::storage::TransactionManager::new(handler.db().clone())
+                    .with_hash_algorithm(algorithm)
+                    .with_versioning(handler.versioning().clone());
+                match tx_manager.link_key_if(&key, &hash, expires_at, &precondition) {
+                    Ok((old_hash, _)) => {
+                        handler.metrics().inc_puts();
+                        handler.metrics().inc_dedup_hits();
+                        BatchResult::Put { key, hash: hash.to_hex_string(), created: old_hash.is_none() }
                     }
                     Err(e) => BatchResult::Error { key, error: e.to_string() }
                 }
             }
-            BatchOp::Get { key } => {
+            BatchOp::Copy { source, destination, overwrite } => {
+                match copy::copy_key(handler, &source, &destination, overwrite, false) {
+                    Ok((meta, old_hash)) => BatchResult::Copy {
+                        key: destination,
+                        source,
+                        hash: meta.hash.to_hex_string(),
+                        created: old_hash.is_none(),
+                    },
+                    Err(e) => BatchResult::Error { key: destination, error: e.to_string() }
+                }
+            }
+            BatchOp::Rename { source, destination, overwrite } => {
+                match copy::copy_key(handler, &source, &destination, overwrite, true) {
+                    Ok((meta, old_hash)) => BatchResult::Rename {
+                        key: destination,
+                        source,
+                        hash: meta.hash.to_hex_string(),
+                        created: old_hash.is_none(),
+                    },
+                    Err(e) => BatchResult::Error { key: destination, error: e.to_string() }
+                }
+            }
+            BatchOp::Get { key, encoding } => {
                 match handler.db().keys_tree().get(key.as_bytes()) {
                     Ok(Some(meta_bytes)) => {
-                        let meta: crate::storage::KeyMeta = bincode::deserialize(&meta_bytes)?;
+                        // Undecodable metadata fails this op, not the batch
+                        let meta = match KeyMeta::from_bytes(&meta_bytes) {
+                            Ok(meta) => meta,
+                            Err(e) => {
+                                results.push(BatchResult::Error { key, error: e.to_string() });
+                                continue;
+                            }
+                        };
+                        if meta.is_expired(unix_now()) {
+                            results.push(BatchResult::Get { key, value: None, found: false });
+                            continue;
+                        }
                         match handler.db().objects_tree().get(meta.hash.as_bytes()) {
                             Ok(Some(compressed)) => {
-                                match handler.compressor().decompress(&compressed) {
+                                let data = scrub::check_known_corrupt(handler.db(), &meta.hash, &compressed)
+                                    .and_then(|()| handler.compressor().decompress(&compressed));
+                                match data {
                                     Ok(data) => {
                                         handler.metrics().inc_gets();
-                                        let value = String::from_utf8_lossy(&data).to_string();
+                                        let value = BatchValue::encode(&data, format, encoding.unwrap_or(request.encoding));
                                         BatchResult::Get { key, value: Some(value), found: true }
                                     }
                                     Err(e) => BatchResult::Error { key, error: e.to_string() }
@@ -143,9 +528,45 @@ pub async fn handle_batch(
                     Err(e) => BatchResult::Error { key, error: e.to_string() }
                 }
             }
-            BatchOp::Delete { key } => {
-                let tx_manager = crate::storage::TransactionManager::new(handler.db().clone());
-                match tx_manager.delete_key_atomic(&key) {
+            BatchOp::Head { key } => {
+                match live_meta(handler, &key) {
+                    Ok(Some(meta)) => match head_info(handler, &meta) {
+                        Ok(info) => BatchResult::Head { key, found: true, meta: Some(info) },
+                        Err(e) => BatchResult::Error { key, error: e.to_string() }
+                    },
+                    Ok(None) => BatchResult::Head { key, found: false, meta: None },
+                    Err(e) => BatchResult::Error { key, error: e.to_string() }
+                }
+            }
+            BatchOp::Exists { key } => {
+                match live_meta(handler, &key) {
+                    Ok(meta) => BatchResult::Exists { key, exists: meta.is_some() },
+                    Err(e) => BatchResult::Error { key, error: e.to_string() }
+                }
+            }
+            BatchOp::Cas { key, value, encoding, expected, ttl } => {
+                match compare_and_swap(handler, &key, value.decode(encoding.unwrap_or(request.encoding)), expected.as_deref(), ttl) {
+                    Ok((swapped, hash)) => BatchResult::Cas { key, swapped, hash: hash.map(|h| h.to_hex_string()) },
+                    Err(e) => BatchResult::Error { key, error: e.to_string() }
+                }
+            }
+            BatchOp::List { prefix, start_after, limit } => {
+                match list_keys(handler, &prefix, start_after, limit) {
+                    Ok((keys, more)) => BatchResult::List { prefix, keys, more },
+                    Err(e) => BatchResult::Error { key: prefix, error: e.to_string() }
+                }
+            }
+            BatchOp::Delete { key, if_match, if_none_match } => {
+                let precondition = match validate_key(&key).and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref())) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        results.push(BatchResult::Error { key, error: e.to_string() });
+                        continue;
+                    }
+                };
+                let tx_manager = crate::storage::TransactionManager::new(handler.db().clone())
+                    .with_trash(handler.trash_retention() > 0);
+                match tx_manager.delete_key_if(&key, &precondition) {
                     Ok(_) => {
                         handler.metrics().inc_deletes();
                         BatchResult::Delete { key, deleted: true }
@@ -158,18 +579,301 @@ pub async fn handle_batch(
         results.push(result);
     }
 
-    let response = BatchResponse { results };
-    // Use custom serialization for consistent JSON format
-    let results_json: Vec<serde_json::Value> = response.results.iter()
-        .map(|r| r.to_json_result())
+    format.respond(StatusCode::OK, &BatchResponse { results })
+}
+
+/// Run the writes in `ops` as one transaction: all of them commit or none
+/// does. A failure answers with the status of its error and the op that
+/// caused it.
+fn handle_atomic_batch(
+    handler: &Handler,
+    ops: Vec<BatchOp>,
+    default_encoding: ValueEncoding,
+    format: BatchFormat,
+) -> Result<Response<ResponseBody>, Error> {
+    let mut writes = Vec::with_capacity(ops.len());
+    for (index, op) in ops.into_iter().enumerate() {
+        let write = match op {
+            BatchOp::Put { key, value, encoding, ttl, if_match, if_none_match, expected_hash, content_md5, digest } => {
+                validate_new_key(&key)
+                    .and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref()))
+                    .and_then(|precondition| {
+                        let value = value.decode(encoding.unwrap_or(default_encoding))?;
+                        let checks = ExpectedChecksums::parse(expected_hash.as_deref(), content_md5.as_deref(), digest.as_deref())?;
+                        let put = prepare_put(handler, &value, ttl, checks)?;
+                        Ok(BatchWrite::Put {
+                            key: key.clone(),
+                            object: put.compressed,
+                            hash: put.hash,
+                            size: put.size,
+                            algorithm: put.algorithm,
+                            expires_at: put.expires_at,
+                            precondition,
+                        })
+                    })
+                    .map_err(|e| (key, e))
+            }
+            BatchOp::Link { key, hash, ttl, if_match, if_none_match } => {
+                validate_new_key(&key)
+                    .and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref()))
+                    .and_then(|precondition| {
+                        let (hash, algorithm, expires_at) = prepare_link(handler, &hash, ttl)?;
+                        Ok(BatchWrite::Link { key: key.clone(), hash, algorithm, expires_at, precondition })
+                    })
+                    .map_err(|e| (key, e))
+            }
+            BatchOp::Delete { key, if_match, if_none_match } => {
+                validate_key(&key)
+                    .and_then(|()| precondition_from(if_match.as_deref(), if_none_match.as_deref()))
+                    .map(|precondition| BatchWrite::Delete { key: key.clone(), precondition })
+                    .map_err(|e| (key, e))
+            }
+            BatchOp::Get { key, .. } | BatchOp::Head { key } | BatchOp::Exists { key } | BatchOp::Cas { key, .. }
+            | BatchOp::List { prefix: key, .. }
+            | BatchOp::Copy { destination: key, .. } | BatchOp::Rename { destination: key, .. } => {
+                Err((key, Error::InvalidRequest(
+                    "Only put, link and delete operations can be part of an atomic batch".to_string()
+                )))
+            }
+        };
+        match write {
+            Ok(write) => writes.push(write),
+            Err((key, error)) => return atomic_failure(format, Some(index), Some(key), error),
+        }
+    }
+
+    let tx_manager = TransactionManager::new(handler.db().clone())
+        .with_versioning(handler.versioning().clone())
+        .with_trash(handler.trash_retention() > 0)
+        .with_dedup_verify(Some(handler.compressor().clone()));
+    let previous = match tx_manager.apply_batch(&writes) {
+        Ok(previous) => previous,
+        Err(failure) => {
+            let key = failure.index.and_then(|index| writes.get(index)).map(|write| write.key().to_string());
+            return atomic_failure(format, failure.index, key, failure.error);
+        }
+    };
+
+    let results = writes.into_iter().zip(previous)
+        .map(|(write, old_hash)| match write {
+            BatchWrite::Put { key, hash, .. } => {
+                handler.metrics().inc_puts();
+                BatchResult::Put { key, hash: hash.to_hex_string(), created: old_hash.is_none() }
+            }
+            BatchWrite::Link { key, hash, .. } => {
+                handler.metrics().inc_puts();
+                handler.metrics().inc_dedup_hits();
+                BatchResult::Put { key, hash: hash.to_hex_string(), created: old_hash.is_none() }
+            }
+            BatchWrite::Delete { key, .. } => {
+                handler.metrics().inc_deletes();
+                BatchResult::Delete { key, deleted: true }
+            }
+        })
         .collect();
+    format.respond(StatusCode::OK, &BatchResponse { results })
+}
+
+/// Live metadata of `key`, `None` if it is missing or expired.
+fn live_meta(handler: &Handler, key: &str) -> Result<Option<KeyMeta>, Error> {
+    validate_key(key)?;
+    let meta = handler.db().keys_tree().get(key.as_bytes())?
+        .map(|bytes| KeyMeta::from_bytes(&bytes))
+        .transpose()?;
+    Ok(meta.filter(|meta| !meta.is_expired(unix_now())))
+}
+
+fn head_info(handler: &Handler, meta: &KeyMeta) -> Result<HeadInfo, Error> {
+    Ok(HeadInfo {
+        hash: meta.hash.to_hex_string(),
+        hash_algorithm: meta.hash_algorithm.name().to_string(),
+        size: meta.size,
+        refs: handler.db().live_ref_count(&meta.hash)?,
+        created_at: meta.created_at,
+        modified_at: meta.modified_at,
+        expires_at: meta.expires_at,
+    })
+}
+
+/// Write `value` to `key` if it holds the `expected` hash, or is absent when
+/// there is none. Returns whether it was written and the hash the key holds
+/// afterwards.
+fn compare_and_swap(
+    handler: &Handler,
+    key: &str,
+    value: Result<Vec<u8>, Error>,
+    expected: Option<&str>,
+    ttl: Option<u64>,
+) -> Result<(bool, Option<Hash>), Error> {
+    validate_new_key(key)?;
+    let precondition = match expected {
+        Some(hex) => Precondition::HashMatches(vec![
+            Hash::from_hex(hex).ok_or_else(|| Error::InvalidRequest(format!("Invalid hash: {}", hex)))?
+        ]),
+        None => Precondition::Absent,
+    };
+    let put = prepare_put(handler, &value?, ttl, ExpectedChecksums::default())?;
+
+    let tx_manager = TransactionManager::new(handler.db().clone())
+        .with_hash_algorithm(put.algorithm)
+        .with_versioning(handler.versioning().clone())
+        .with_dedup_verify(Some(handler.compressor().clone()));
+    match tx_manager.update_key_if(key, &put.compressed, &put.hash, put.size, put.expires_at, &precondition) {
+        Ok(_) => {
+            handler.metrics().inc_puts();
+            Ok((true, Some(put.hash)))
+        }
+        Err(Error::PreconditionFailed(_)) => Ok((false, live_meta(handler, key)?.map(|meta| meta.hash))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Up to `limit` live keys under `prefix` after `start_after`, and whether
+/// more follow.
+fn list_keys(
+    handler: &Handler,
+    prefix: &str,
+    start_after: Option<String>,
+    limit: Option<usize>,
+) -> Result<(Vec<ListedKey>, bool), Error> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let range = KeyRange {
+        prefix: Some(prefix.as_bytes().to_vec()),
+        start_after: start_after.map(String::into_bytes),
+        ..Default::default()
+    };
+
+    let now = unix_now();
+    let mut keys = Vec::new();
+    for item in handler.db().scan_keys(&range) {
+        let (key, meta_bytes) = item?;
+        let meta = KeyMeta::from_bytes(&meta_bytes)?;
+        if meta.is_expired(now) {
+            continue;
+        }
+        if keys.len() == limit {
+            return Ok((keys, true));
+        }
+        keys.push(ListedKey {
+            key: String::from_utf8_lossy(&key).into_owned(),
+            hash: meta.hash.to_hex_string(),
+            size: meta.size,
+            modified_at: meta.modified_at,
+            expires_at: meta.expires_at,
+        });
+    }
+    Ok((keys, false))
+}
+
+/// Validate, encode and address the value of a batch put.
+fn prepare_put(
+    handler: &Handler,
+    value: &[u8],
+    ttl: Option<u64>,
+    checks: ExpectedChecksums,
+) -> Result<PreparedPut, Error> {
+    handler.check_value_size(value.len() as u64)?;
+    let expires_at = ttl.map(|t| expiry_from_ttl(t, unix_now())).transpose()?;
+    let hash = handler.hash_algorithm().compute(value);
+    let mut verifier = checks.verifier();
+    verifier.update(value);
+    verifier.verify(&hash)?;
 
-    let json = serde_json::to_string_pretty(&serde_json::json!({ "results": results_json }))
-        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+    let compressed = handler.compressor().compress(value)?;
+    let (hash, algorithm) = dedup::resolve_address(
+        handler.db(),
+        handler.compressor(),
+        &compressed,
+        hash,
+        handler.hash_algorithm(),
+    )?;
+    Ok(PreparedPut { compressed, hash, algorithm, size: value.len() as u64, expires_at })
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .body(Full::new(Bytes::from(json)))
-        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+/// Parse the target and expiry of a batch link.
+fn prepare_link(handler: &Handler, hash: &str, ttl: Option<u64>) -> Result<(Hash, HashAlgorithm, Option<u64>), Error> {
+    let parsed = Hash::from_hex(hash)
+        .ok_or_else(|| Error::InvalidRequest(format!("Invalid hash: {}", hash)))?;
+    let expires_at = ttl.map(|t| expiry_from_ttl(t, unix_now())).transpose()?;
+    let algorithm = dedup::stored_algorithm(handler.db(), &parsed)?
+        .unwrap_or(handler.hash_algorithm());
+    Ok((parsed, algorithm, expires_at))
+}
+
+fn atomic_failure(
+    format: BatchFormat,
+    index: Option<usize>,
+    key: Option<String>,
+    error: Error,
+) -> Result<Response<ResponseBody>, Error> {
+    let body = AtomicFailure { error: error.to_string(), index, key };
+    format.respond(error.status_code(), &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_values() {
+        let request = BatchFormat::Json.parse(br#"{"encoding": "base64", "ops": [
+            {"op": "put", "key": "a", "value": "AP8="},
+            {"op": "put", "key": "b", "value": "text", "encoding": "utf8"}
+        ]}"#).unwrap();
+        let values: Vec<_> = request.ops.into_iter()
+            .map(|op| match op {
+                BatchOp::Put { value, encoding, .. } => value.decode(encoding.unwrap_or(request.encoding)).unwrap(),
+                other => panic!("unexpected op {:?}", other),
+            })
+            .collect();
+        assert_eq!(values, vec![vec![0x00, 0xFF], b"text".to_vec()]);
+        assert!(BatchValue::Text("not base64!".to_string()).decode(ValueEncoding::Base64).is_err());
+
+        // CBOR carries raw bytes, whatever the encoding says
+        let mut cbor = Vec::new();
+        let op = ciborium::Value::Map(vec![
+            ("op".into(), "put".into()),
+            ("key".into(), "bin".into()),
+            ("value".into(), ciborium::Value::Bytes(vec![0x00, 0xFF])),
+        ]);
+        ciborium::into_writer(&ciborium::Value::Array(vec![op]), &mut cbor).unwrap();
+        let request = BatchFormat::Cbor.parse(&cbor).unwrap();
+        match request.ops.into_iter().next().unwrap() {
+            BatchOp::Put { value, .. } => assert_eq!(value.decode(ValueEncoding::Base64).unwrap(), vec![0x00, 0xFF]),
+            other => panic!("unexpected op {:?}", other),
+        }
+
+        assert_eq!(BatchValue::encode(&[0xFF], BatchFormat::Json, ValueEncoding::Base64), BatchValue::Text("/w==".to_string()));
+        assert_eq!(BatchValue::encode(&[0xFF], BatchFormat::Cbor, ValueEncoding::Utf8), BatchValue::Bytes(vec![0xFF]));
+    }
+
+    #[test]
+    fn test_batch_read_ops() {
+        let request = BatchFormat::Json.parse(br#"[
+            {"op": "compare_and_swap", "key": "a", "value": "v"},
+            {"op": "list", "prefix": "p/", "limit": 2}
+        ]"#).unwrap();
+        assert!(matches!(&request.ops[0], BatchOp::Cas { expected: None, .. }));
+        assert!(matches!(&request.ops[1], BatchOp::List { limit: Some(2), start_after: None, .. }));
+
+        // Head metadata sits beside the key, and is left out for a missing key
+        let found = serde_json::to_value(BatchResult::Head {
+            key: "a".to_string(),
+            found: true,
+            meta: Some(HeadInfo {
+                hash: "ab".to_string(),
+                hash_algorithm: "xxhash3".to_string(),
+                size: 1,
+                refs: 1,
+                created_at: 10,
+                modified_at: 20,
+                expires_at: None,
+            }),
+        }).unwrap();
+        assert_eq!(found["head"]["key"], "a");
+        assert_eq!(found["head"]["size"], 1);
+        assert!(found["head"].get("expires_at").is_none());
+        let missing = serde_json::to_value(BatchResult::Head { key: "b".to_string(), found: false, meta: None }).unwrap();
+        assert_eq!(missing, serde_json::json!({"head": {"key": "b", "found": false}}));
+    }
 }
@@ -3,13 +3,16 @@
 //! This module provides shared functionality to reduce code duplication
 //! across different request handlers.
 
-use hyper::{Response, StatusCode};
-use http_body_util::Full;
-use hyper::body::Bytes;
+use hyper::{HeaderMap, Response, StatusCode};
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, empty};
 use crate::server::Handler;
-use crate::storage::KeyMeta;
+use crate::server::handlers::objects::{parse_keys_path, parse_object_path};
+use crate::storage::{KeyMeta, Precondition};
+use crate::util::hash::Hash;
+use crate::util::time::unix_now;
+use std::time::{Duration, UNIX_EPOCH};
 
 /// Maximum allowed key length (256KB) to prevent DoS
 const MAX_KEY_LENGTH: usize = 256 * 1024;
@@ -49,21 +52,211 @@ pub fn validate_key(key: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Validates a key about to be written.
+///
+/// On top of [`validate_key`], rejects keys whose `GET` path is served by
+/// another route, `trash` and `objects/{hash}`, so a key is never written
+/// that could not be read back. Existing keys with such names can still be
+/// deleted.
+///
+/// # Errors
+/// Returns `Error::InvalidRequest` if validation fails.
+pub fn validate_new_key(key: &str) -> Result<(), Error> {
+    validate_key(key)?;
+
+    let path = format!("/{}", key);
+    if key == "trash" || parse_object_path(&path).is_some() || parse_keys_path(&path).is_some() {
+        return Err(Error::InvalidRequest(format!(
+            "Key '{}' is reserved for another endpoint", key
+        )));
+    }
+
+    Ok(())
+}
+
 /// Retrieves key metadata from the database.
 ///
+/// Keys whose TTL has elapsed are reported as missing even before the
+/// background sweeper removes them. `refs` is set to the number of keys
+/// currently sharing the object.
+///
 /// # Arguments
 /// * `handler` - The handler containing the database reference
 /// * `key` - The key to look up
 ///
 /// # Errors
-/// Returns `Error::NotFound` if the key does not exist.
+/// Returns `Error::NotFound` if the key does not exist or has expired.
 pub fn get_key_meta(handler: &Handler, key: &str) -> Result<KeyMeta, Error> {
     let keys_tree = handler.db().keys_tree();
     let meta_bytes = keys_tree
         .get(key.as_bytes())?
         .ok_or_else(|| Error::NotFound(format!("Key '{}' not found", key)))?;
 
-    Ok(bincode::deserialize(&meta_bytes)?)
+    let mut meta = KeyMeta::from_bytes(&meta_bytes)?;
+    if meta.is_expired(unix_now()) {
+        return Err(Error::NotFound(format!("Key '{}' not found", key)));
+    }
+    meta.refs = handler.db().live_ref_count(&meta.hash)?;
+
+    Ok(meta)
+}
+
+/// Parses the expiry requested by a PUT.
+///
+/// `X-TTL` is a lifetime in seconds, `X-Expires-At` an absolute Unix timestamp.
+/// At most one of them may be set.
+///
+/// # Errors
+/// Returns `Error::InvalidRequest` for malformed, zero or past values.
+pub fn parse_expiry(headers: &HeaderMap, now: u64) -> Result<Option<u64>, Error> {
+    let header_u64 = |name: &str| -> Result<Option<u64>, Error> {
+        headers
+            .get(name)
+            .map(|v| {
+                v.to_str()
+                    .ok()
+                    .and_then(|s| s.trim().parse::<u64>().ok())
+                    .ok_or_else(|| Error::InvalidRequest(format!("Invalid {} header", name)))
+            })
+            .transpose()
+    };
+
+    match (header_u64("X-TTL")?, header_u64("X-Expires-At")?) {
+        (Some(_), Some(_)) => Err(Error::InvalidRequest(
+            "X-TTL and X-Expires-At are mutually exclusive".to_string()
+        )),
+        (Some(ttl), None) => expiry_from_ttl(ttl, now).map(Some),
+        (None, Some(at)) if at <= now => Err(Error::InvalidRequest(
+            "X-Expires-At is in the past".to_string()
+        )),
+        (None, at) => Ok(at),
+    }
+}
+
+/// Converts a TTL in seconds into an absolute expiry timestamp.
+///
+/// # Errors
+/// Returns `Error::InvalidRequest` if the TTL is zero.
+pub fn expiry_from_ttl(ttl: u64, now: u64) -> Result<u64, Error> {
+    if ttl == 0 {
+        return Err(Error::InvalidRequest("TTL must be greater than 0".to_string()));
+    }
+    Ok(now.saturating_add(ttl))
+}
+
+/// Parses `If-Match` / `If-None-Match` write preconditions from request headers.
+///
+/// # Errors
+/// Returns `Error::InvalidRequest` for malformed headers.
+pub fn parse_precondition(headers: &HeaderMap) -> Result<Precondition, Error> {
+    let header_str = |name: &str| -> Result<Option<&str>, Error> {
+        headers
+            .get(name)
+            .map(|v| v.to_str().map_err(|_| Error::InvalidRequest(format!("Invalid {} header", name))))
+            .transpose()
+    };
+
+    precondition_from(header_str("If-Match")?, header_str("If-None-Match")?)
+}
+
+/// Builds a write precondition from `If-Match` / `If-None-Match` values.
+///
+/// Both accept `*` or a comma-separated list of hashes in `X-Hash` format,
+/// optionally quoted as entity tags.
+///
+/// # Errors
+/// Returns `Error::InvalidRequest` if both are given or a hash is malformed.
+pub fn precondition_from(if_match: Option<&str>, if_none_match: Option<&str>) -> Result<Precondition, Error> {
+    match (if_match, if_none_match) {
+        (Some(_), Some(_)) => Err(Error::InvalidRequest(
+            "If-Match and If-None-Match are mutually exclusive".to_string()
+        )),
+        (Some(value), None) => Ok(match parse_entity_tags(value, false)? {
+            None => Precondition::Present,
+            Some(hashes) => Precondition::HashMatches(hashes),
+        }),
+        (None, Some(value)) => Ok(match parse_entity_tags(value, true)? {
+            None => Precondition::Absent,
+            Some(hashes) => Precondition::HashDiffers(hashes),
+        }),
+        (None, None) => Ok(Precondition::Unconditional),
+    }
+}
+
+/// Parses an entity-tag list. Returns `None` for the `*` wildcard.
+///
+/// `If-Match` uses the strong comparison (RFC 9110 §13.1.1), so unless
+/// `weak` is set, weak tags are validated but left out: they never match.
+fn parse_entity_tags(value: &str, weak: bool) -> Result<Option<Vec<Hash>>, Error> {
+    if value.trim() == "*" {
+        return Ok(None);
+    }
+
+    let mut hashes = Vec::new();
+    for tag in value.split(',') {
+        let tag = tag.trim();
+        let (is_weak, tag) = match tag.strip_prefix("W/") {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let tag = tag.trim_matches('"');
+        let hash = Hash::from_hex(tag)
+            .ok_or_else(|| Error::InvalidRequest(format!("Invalid entity tag '{}'", tag)))?;
+        if weak || !is_weak {
+            hashes.push(hash);
+        }
+    }
+    Ok(Some(hashes))
+}
+
+/// Formats the content hash as a strong entity tag.
+#[inline]
+pub fn etag(meta: &KeyMeta) -> String {
+    format!("\"{}\"", meta.hash.to_hex_string())
+}
+
+/// Formats the key's modification time as an HTTP date.
+#[inline]
+pub fn last_modified(meta: &KeyMeta) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(meta.modified_at))
+}
+
+/// Evaluates `If-None-Match` / `If-Modified-Since` for a GET or HEAD.
+///
+/// `If-None-Match` takes precedence; `If-Modified-Since` is only consulted
+/// when it is absent. Unparseable values never match, so the full response
+/// is sent.
+pub fn is_not_modified(headers: &HeaderMap, meta: &KeyMeta) -> bool {
+    if let Some(value) = headers.get("If-None-Match") {
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        let current = meta.hash.to_hex_string();
+        return value.split(',').any(|tag| {
+            let tag = tag.trim();
+            let tag = tag.strip_prefix("W/").unwrap_or(tag);
+            tag == "*" || tag.trim_matches('"').eq_ignore_ascii_case(&current)
+        });
+    }
+
+    headers
+        .get("If-Modified-Since")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .and_then(|since| since.duration_since(UNIX_EPOCH).ok())
+        .is_some_and(|since| meta.modified_at <= since.as_secs())
+}
+
+/// Builds a `304 Not Modified` response carrying the validators.
+pub fn build_not_modified_response(meta: &KeyMeta) -> Result<Response<ResponseBody>, Error> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header("ETag", etag(meta))
+        .header("Last-Modified", last_modified(meta))
+        .header("X-Hash", meta.hash.to_hex_string())
+        .header("X-Hash-Algorithm", meta.hash_algorithm.name())
+        .body(empty())
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
 
 /// Builds a response with hash-related headers.
@@ -80,20 +273,29 @@ pub fn build_hash_response(
     status: StatusCode,
     meta: &KeyMeta,
     include_extra_headers: bool,
-) -> Result<Response<Full<Bytes>>, Error> {
+) -> Result<Response<ResponseBody>, Error> {
     let mut builder = Response::builder()
         .status(status)
         .header("Content-Type", "application/octet-stream")
         .header("X-Hash", meta.hash.to_hex_string())
-        .header("X-Hash-Algorithm", "xxhash3");
+        .header("X-Hash-Algorithm", meta.hash_algorithm.name())
+        .header("ETag", etag(meta));
 
     if include_extra_headers {
         builder = builder
+            .header("Accept-Ranges", "bytes")
+            .header("Last-Modified", last_modified(meta))
             .header("X-Created-At", meta.created_at.to_string())
             .header("X-Refs", meta.refs.to_string());
+
+        if let (Some(at), Some(ttl)) = (meta.expires_at, meta.ttl_remaining(unix_now())) {
+            builder = builder
+                .header("X-Expires-At", at.to_string())
+                .header("X-TTL", ttl.to_string());
+        }
     }
 
-    builder.body(Full::new(Bytes::new()))
+    builder.body(empty())
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
 
@@ -102,7 +304,8 @@ pub fn build_hash_response(
 /// # Arguments
 /// * `status` - The HTTP status code
 /// * `meta` - The key metadata containing hash information
-/// * `body` - The response body bytes
+/// * `body` - The response body, buffered or streamed
+/// * `content_length` - Length of `body` in bytes
 ///
 /// # Errors
 /// Returns `Error::Internal` if response building fails.
@@ -110,15 +313,19 @@ pub fn build_hash_response(
 pub fn build_hash_response_with_body(
     status: StatusCode,
     meta: &KeyMeta,
-    body: Bytes,
-) -> Result<Response<Full<Bytes>>, Error> {
+    body: ResponseBody,
+    content_length: u64,
+) -> Result<Response<ResponseBody>, Error> {
     Response::builder()
         .status(status)
         .header("Content-Type", "application/octet-stream")
-        .header("Content-Length", body.len().to_string())
+        .header("Content-Length", content_length.to_string())
+        .header("Accept-Ranges", "bytes")
         .header("X-Hash", meta.hash.to_hex_string())
-        .header("X-Hash-Algorithm", "xxhash3")
-        .body(Full::new(body))
+        .header("X-Hash-Algorithm", meta.hash_algorithm.name())
+        .header("ETag", etag(meta))
+        .header("Last-Modified", last_modified(meta))
+        .body(body)
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
 
@@ -179,8 +386,127 @@ mod tests {
         assert!(validate_key("key\rreturn").is_err());
     }
 
+    #[test]
+    fn test_validate_new_key_rejects_object_paths() {
+        let hex = "9f86d081884c7d659a2feaa0c55ad015";
+        assert!(validate_new_key(&format!("objects/{}", hex)).is_err());
+        assert!(validate_new_key(&format!("objects/{}/keys", hex)).is_err());
+        // Only well-formed hashes are routed elsewhere
+        assert!(validate_new_key("objects/report").is_ok());
+        assert!(validate_new_key(&format!("objects/{}/other", hex)).is_ok());
+        // Existing keys with reserved names can still be addressed
+        assert!(validate_key(&format!("objects/{}", hex)).is_ok());
+    }
+
+    #[test]
+    fn test_validate_new_key_rejects_trash() {
+        assert!(validate_new_key("trash").is_err());
+        assert!(validate_new_key("trash/restore").is_ok());
+        assert!(validate_new_key("trash2").is_ok());
+        assert!(validate_key("trash").is_ok());
+    }
+
     #[test]
     fn test_validate_key_allows_tab() {
         assert!(validate_key("key\twith\ttabs").is_ok());
     }
+
+    #[test]
+    fn test_parse_expiry() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_expiry(&headers, 1000).unwrap(), None);
+
+        headers.insert("X-TTL", "60".parse().unwrap());
+        assert_eq!(parse_expiry(&headers, 1000).unwrap(), Some(1060));
+
+        headers.insert("X-Expires-At", "2000".parse().unwrap());
+        assert!(parse_expiry(&headers, 1000).is_err());
+
+        headers.remove("X-TTL");
+        assert_eq!(parse_expiry(&headers, 1000).unwrap(), Some(2000));
+        assert!(parse_expiry(&headers, 2000).is_err());
+    }
+
+    #[test]
+    fn test_precondition_from() {
+        let hash = Hash::compute(b"value");
+        let hex = hash.to_hex_string();
+
+        assert_eq!(precondition_from(None, None).unwrap(), Precondition::Unconditional);
+        assert_eq!(precondition_from(None, Some("*")).unwrap(), Precondition::Absent);
+        assert_eq!(precondition_from(Some("*"), None).unwrap(), Precondition::Present);
+        assert_eq!(
+            precondition_from(Some(&hex), None).unwrap(),
+            Precondition::HashMatches(vec![hash])
+        );
+        // If-Match compares strongly, so a weak tag never matches
+        assert_eq!(
+            precondition_from(Some(&format!("\"{}\", W/\"{}\"", hex, hex)), None).unwrap(),
+            Precondition::HashMatches(vec![hash])
+        );
+        assert_eq!(
+            precondition_from(Some(&format!("W/\"{}\"", hex)), None).unwrap(),
+            Precondition::HashMatches(vec![])
+        );
+        assert_eq!(
+            precondition_from(None, Some(&hex)).unwrap(),
+            Precondition::HashDiffers(vec![hash])
+        );
+        assert_eq!(
+            precondition_from(None, Some(&format!("W/\"{}\"", hex))).unwrap(),
+            Precondition::HashDiffers(vec![hash])
+        );
+    }
+
+    #[test]
+    fn test_precondition_from_rejects_invalid() {
+        assert!(precondition_from(Some("*"), Some("*")).is_err());
+        assert!(precondition_from(Some("not-a-hash"), None).is_err());
+    }
+
+    #[test]
+    fn test_is_not_modified_etag() {
+        let meta = KeyMeta::new(Hash::compute(b"value"), 5);
+        let matching = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("If-None-Match", value.parse().unwrap());
+            is_not_modified(&headers, &meta)
+        };
+
+        assert!(matching(&etag(&meta)));
+        assert!(matching(&format!("\"other\", W/{}", etag(&meta))));
+        assert!(matching("*"));
+        assert!(!matching("\"deadbeef\""));
+        assert!(!is_not_modified(&HeaderMap::new(), &meta));
+    }
+
+    #[test]
+    fn test_is_not_modified_since() {
+        let mut meta = KeyMeta::new(Hash::compute(b"value"), 5);
+        meta.modified_at = 1_700_000_000;
+        let since = |secs: u64| {
+            let mut headers = HeaderMap::new();
+            let date = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+            headers.insert("If-Modified-Since", date.parse().unwrap());
+            headers
+        };
+
+        assert!(is_not_modified(&since(1_700_000_000), &meta));
+        assert!(!is_not_modified(&since(1_699_999_999), &meta));
+
+        // If-None-Match wins over If-Modified-Since
+        let mut headers = since(1_700_000_000);
+        headers.insert("If-None-Match", "\"deadbeef\"".parse().unwrap());
+        assert!(!is_not_modified(&headers, &meta));
+    }
+
+    #[test]
+    fn test_parse_expiry_rejects_invalid() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-TTL", "0".parse().unwrap());
+        assert!(parse_expiry(&headers, 1000).is_err());
+
+        headers.insert("X-TTL", "soon".parse().unwrap());
+        assert!(parse_expiry(&headers, 1000).is_err());
+    }
 }
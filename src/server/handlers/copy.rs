@@ -0,0 +1,86 @@
+use hyper::{Request, Response, StatusCode, body::Incoming};
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, read_body_to_bytes};
+use crate::server::body::{ResponseBody, full};
+use crate::server::Handler;
+use crate::server::handlers::common::{validate_key, validate_new_key};
+use crate::storage::{KeyMeta, Precondition, TransactionManager};
+use crate::util::hash::Hash;
+
+#[derive(Deserialize)]
+struct CopyRequest {
+    source: String,
+    destination: String,
+    /// Replace an existing destination, `true` unless given
+    #[serde(default)]
+    overwrite: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct CopyResponse {
+    source: String,
+    destination: String,
+    hash: String,
+    created: bool,
+}
+
+/// `POST /copy` and `POST /rename`: point `destination` at the object of
+/// `source` in one transaction, without reading the value.
+pub async fn handle_copy(
+    handler: &Handler,
+    req: Request<Incoming>,
+    rename: bool,
+) -> Result<Response<ResponseBody>, Error> {
+    let data = read_body_to_bytes(req.into_body()).await?;
+    let request: CopyRequest = serde_json::from_slice(&data)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+
+    let (meta, old_hash) = copy_key(handler, &request.source, &request.destination, request.overwrite, rename)?;
+
+    let created = old_hash.is_none();
+    let response = CopyResponse {
+        source: request.source,
+        destination: request.destination,
+        hash: meta.hash.to_hex_string(),
+        created,
+    };
+    let json = serde_json::to_string(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(if created { StatusCode::CREATED } else { StatusCode::OK })
+        .header("Content-Type", "application/json")
+        .header("X-Hash", meta.hash.to_hex_string())
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// Copy or rename a key, failing with `412` if `overwrite` is `Some(false)`
+/// and the destination exists. Shared with the batch ops.
+pub fn copy_key(
+    handler: &Handler,
+    source: &str,
+    destination: &str,
+    overwrite: Option<bool>,
+    rename: bool,
+) -> Result<(KeyMeta, Option<Hash>), Error> {
+    validate_key(source)?;
+    validate_new_key(destination)?;
+    let precondition = if overwrite.unwrap_or(true) {
+        Precondition::Unconditional
+    } else {
+        Precondition::Absent
+    };
+
+    let tx_manager = TransactionManager::new(handler.db().clone())
+        .with_versioning(handler.versioning().clone());
+    let written = if rename {
+        tx_manager.rename_key_if(source, destination, &precondition)?
+    } else {
+        tx_manager.copy_key_if(source, destination, &precondition)?
+    };
+    handler.metrics().inc_puts();
+    Ok(written)
+}
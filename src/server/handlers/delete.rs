@@ -1,30 +1,130 @@
-use hyper::{Response, StatusCode};
-use http_body_util::Full;
+use hyper::{Request, Response, StatusCode, body::Incoming};
 use hyper::body::Bytes;
 
 use crate::error::Error;
+use crate::server::body::{self, ResponseBody, empty, full};
 use crate::server::Handler;
-use crate::server::handlers::common::{validate_key, get_key_meta};
+use crate::server::handlers::common::{validate_key, get_key_meta, parse_precondition};
+use crate::storage::bulk::{self, PrefixDeleteReport};
+use crate::storage::Precondition;
+
+/// Largest `chunk_size` accepted, bounding the size of one transaction
+const MAX_CHUNK_SIZE: usize = 10_000;
 
 pub async fn handle_delete(
     handler: &Handler,
     key: &str,
-) -> Result<Response<Full<Bytes>>, Error> {
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
     validate_key(key)?;
 
-    // Get metadata first for size tracking
-    let meta = get_key_meta(handler, key)?;
-    let size = meta.size;
+    let precondition = parse_precondition(req.headers())?;
+
+    // Unconditional deletes of missing or expired keys are plain 404s;
+    // conditional ones are decided inside the transaction
+    if precondition == Precondition::Unconditional {
+        get_key_meta(handler, key)?;
+    }
 
     // Delete atomically
-    let tx_manager = crate::storage::TransactionManager::new(handler.db().clone());
-    tx_manager.delete_key_atomic(key)?;
+    let tx_manager = crate::storage::TransactionManager::new(handler.db().clone())
+        .with_trash(handler.trash_retention() > 0);
+    tx_manager.delete_key_if(key, &precondition)?;
 
     handler.metrics().inc_deletes();
-    handler.metrics().sub_bytes(size);
 
     Response::builder()
         .status(StatusCode::NO_CONTENT)
-        .body(Full::new(Bytes::new()))
+        .body(empty())
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// `DELETE /keys?prefix=...`: delete every key under a prefix in chunked
+/// transactions. `dry_run=true` only reports what would be deleted and
+/// freed; `progress=true` streams a JSON line per chunk, ending with the
+/// final report.
+pub async fn handle_delete_prefix(
+    handler: &Handler,
+    query: Option<&str>,
+) -> Result<Response<ResponseBody>, Error> {
+    let mut prefix = None;
+    let mut dry_run = false;
+    let mut progress = false;
+    let mut chunk_size = bulk::DEFAULT_CHUNK_SIZE;
+    for (k, v) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        match k.as_ref() {
+            "prefix" => prefix = Some(v.into_owned()),
+            "dry_run" => dry_run = parse_flag("dry_run", &v)?,
+            "progress" => progress = parse_flag("progress", &v)?,
+            "chunk_size" => {
+                chunk_size = v.parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=MAX_CHUNK_SIZE).contains(n))
+                    .ok_or_else(|| Error::InvalidRequest(format!("Invalid chunk_size: {}", v)))?;
+            }
+            _ => {}
+        }
+    }
+    // An empty prefix would wipe the whole store
+    let prefix = prefix
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| Error::InvalidRequest("A non-empty prefix is required".to_string()))?;
+
+    let db = handler.db().clone();
+    let trash = handler.trash_retention() > 0;
+    let metrics = handler.metrics().clone();
+    let record = move |report: &PrefixDeleteReport| {
+        if !report.dry_run {
+            metrics.add_deletes(report.keys);
+        }
+    };
+
+    if !progress {
+        let report = tokio::task::spawn_blocking(move || {
+            bulk::delete_prefix(&db, prefix.as_bytes(), dry_run, trash, chunk_size, |_| {})
+        })
+            .await
+            .map_err(|e| Error::Internal(format!("Prefix delete task failed: {}", e)))??;
+        record(&report);
+        return json_response("application/json", full(Bytes::from(report_json(&report)?)));
+    }
+
+    let (tx, body) = body::channel();
+    tokio::task::spawn_blocking(move || {
+        let result = bulk::delete_prefix(&db, prefix.as_bytes(), dry_run, trash, chunk_size, |report| {
+            if let Ok(line) = report_json(report) {
+                let _ = tx.blocking_send(Ok(Bytes::from(line + "\n")));
+            }
+        });
+        match result {
+            Ok(report) => record(&report),
+            Err(e) => {
+                tracing::error!("Prefix delete of '{}' failed: {}", prefix, e);
+                let line = serde_json::json!({ "error": e.to_string() }).to_string();
+                let _ = tx.blocking_send(Ok(Bytes::from(line + "\n")));
+            }
+        }
+    });
+    json_response("application/x-ndjson", body)
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "" | "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(Error::InvalidRequest(format!("Invalid {} value: {}", name, value))),
+    }
+}
+
+fn report_json(report: &PrefixDeleteReport) -> Result<String, Error> {
+    serde_json::to_string(report)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))
+}
+
+fn json_response(content_type: &str, body: ResponseBody) -> Result<Response<ResponseBody>, Error> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", content_type)
+        .body(body)
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
@@ -1,42 +1,97 @@
-use hyper::{Response, StatusCode};
-use http_body_util::Full;
-use hyper::body::Bytes;
+use hyper::{Request, Response, StatusCode, body::Incoming};
+use hyper::header::HeaderValue;
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, full, stream_reader};
 use crate::server::Handler;
-use crate::server::handlers::common::{validate_key, get_key_meta, build_hash_response_with_body};
+use crate::storage::{scrub, KeyMeta};
+use crate::server::handlers::common::{validate_key, get_key_meta, is_not_modified, build_not_modified_response, build_hash_response_with_body};
+use crate::server::handlers::versions::{self, VersionQuery};
+use crate::server::handlers::range::{RangeRequest, range_request, build_partial_response, build_single_range_response, build_streamed_multipart_response, build_range_not_satisfiable_response};
+
+/// Objects larger than this (compressed) are streamed instead of decompressed up front
+const INLINE_DECOMPRESS_LIMIT: usize = 64 * 1024;
 
 pub async fn handle_get(
     handler: &Handler,
     key: &str,
-) -> Result<Response<Full<Bytes>>, Error> {
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
     validate_key(key)?;
 
-    // Get key metadata
-    let meta = get_key_meta(handler, key)?;
+    let version = match versions::parse_version_query(req.uri().query())? {
+        Some(VersionQuery::List) => return versions::handle_list_versions(handler, key),
+        Some(VersionQuery::Version(version)) => Some(version),
+        None => None,
+    };
+    let meta = match version {
+        Some(version) => versions::version_meta(handler, key, version)?,
+        None => get_key_meta(handler, key)?,
+    };
+
+    let mut response = serve(handler, &meta, req).await?;
+    if let Some(version) = version {
+        response.headers_mut().insert("X-Version", HeaderValue::from(version));
+    }
+    Ok(response)
+}
+
+/// Respond with the value `meta` describes, honouring conditional and range
+/// headers.
+async fn serve(
+    handler: &Handler,
+    meta: &KeyMeta,
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
+    // The ETag is the content hash, so a cached copy can be revalidated
+    // without touching the object tree
+    if is_not_modified(req.headers(), meta) {
+        return build_not_modified_response(meta);
+    }
+
+    let range = range_request(req.headers(), meta);
+    if range == RangeRequest::Unsatisfiable {
+        return build_range_not_satisfiable_response(meta);
+    }
 
     // Get object data using hash bytes
     let hash_bytes = meta.hash.as_bytes();
     let objects_tree = handler.db().objects_tree();
     let compressed = objects_tree.get(hash_bytes)?
         .ok_or_else(|| Error::NotFound("Object data not found".to_string()))?;
-
-    // Decompress - use blocking task only for larger payloads
-    let data = if compressed.len() > 64 * 1024 {
-        // Large payload - use blocking task to avoid blocking async runtime
-        tokio::task::spawn_blocking({
-            let compressor = handler.compressor().clone();
-            let compressed = compressed.to_vec();
-            move || compressor.decompress(&compressed)
-        })
-        .await
-        .map_err(|e| Error::Internal(format!("Decompression task failed: {}", e)))??
-    } else {
-        // Small payload - decompress inline (faster due to no task spawn overhead)
-        handler.compressor().decompress(&compressed)?
-    };
+    scrub::check_known_corrupt(handler.db(), &meta.hash, &compressed)?;
 
     handler.metrics().inc_gets();
 
-    build_hash_response_with_body(StatusCode::OK, &meta, Bytes::from(data))
+    // Large payload - decompress on a blocking task and stream the output, so
+    // memory stays bounded by the stored object plus a few chunks
+    if compressed.len() > INLINE_DECOMPRESS_LIMIT {
+        let reader = handler.compressor().reader(compressed)?;
+        return match &range {
+            RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+                let body = stream_reader(reader, ranges[0].clone());
+                build_single_range_response(meta, &ranges[0], body)
+            }
+            RangeRequest::Partial(ranges) => build_streamed_multipart_response(meta, ranges, reader),
+            _ => {
+                let body = stream_reader(reader, 0..meta.size);
+                build_hash_response_with_body(StatusCode::OK, meta, body, meta.size)
+            }
+        };
+    }
+
+    // Small payload - decompress inline (faster due to no task spawn
+    // overhead), but only up to the furthest requested byte
+    let data = match range.prefix_len() {
+        Some(len) => handler.compressor().decompress_prefix(&compressed, len)?,
+        None => handler.compressor().decompress(&compressed)?,
+    };
+
+    match range {
+        RangeRequest::Partial(ranges) => build_partial_response(meta, &ranges, data.into()),
+        _ => {
+            let len = data.len() as u64;
+            build_hash_response_with_body(StatusCode::OK, meta, full(data), len)
+        }
+    }
 }
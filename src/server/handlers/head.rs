@@ -1,20 +1,33 @@
-use hyper::{Response, StatusCode};
-use http_body_util::Full;
-use hyper::body::Bytes;
+use hyper::{Request, Response, StatusCode, body::Incoming};
 use hyper::header::HeaderValue;
 
 use crate::error::Error;
+use crate::server::body::ResponseBody;
 use crate::server::Handler;
-use crate::server::handlers::common::{validate_key, get_key_meta, build_hash_response};
+use crate::server::handlers::versions::{self, VersionQuery};
+use crate::server::handlers::common::{validate_key, get_key_meta, is_not_modified, build_not_modified_response, build_hash_response};
 
 pub async fn handle_head(
     handler: &Handler,
     key: &str,
-) -> Result<Response<Full<Bytes>>, Error> {
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
     validate_key(key)?;
 
-    // Get key metadata
-    let meta = get_key_meta(handler, key)?;
+    let version = match versions::parse_version_query(req.uri().query())? {
+        Some(VersionQuery::Version(version)) => Some(version),
+        _ => None,
+    };
+    let meta = match version {
+        Some(version) => versions::version_meta(handler, key, version)?,
+        None => get_key_meta(handler, key)?,
+    };
+
+    // The ETag is the content hash, so a cached copy can be revalidated
+    // without touching the object tree
+    if is_not_modified(req.headers(), &meta) {
+        return build_not_modified_response(&meta);
+    }
 
     let mut response = build_hash_response(StatusCode::OK, &meta, true)?;
     // Override content-length to be the actual size, not 0
@@ -24,6 +37,9 @@ pub async fn handle_head(
         HeaderValue::from_str(&meta.size.to_string())
             .map_err(|e| Error::Internal(format!("Invalid header value: {}", e)))?
     );
+    if let Some(version) = version {
+        headers.insert("X-Version", HeaderValue::from(version));
+    }
 
     Ok(response)
 }
@@ -1,10 +1,12 @@
 use hyper::{Response, StatusCode};
-use http_body_util::Full;
 use hyper::body::Bytes;
 use serde::Serialize;
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, full};
 use crate::server::Handler;
+use crate::storage::{KeyMeta, KeyRange};
+use crate::util::time::unix_now;
 
 #[derive(Serialize)]
 struct KeyInfo {
@@ -14,25 +16,37 @@ struct KeyInfo {
     hash_algorithm: String,
     refs: u64,
     created_at: u64,
+    modified_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
 }
 
 #[derive(Serialize)]
 struct ListResponse {
     keys: Vec<KeyInfo>,
     total: usize,
+    /// Rolled-up key groups, present when listing with a delimiter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    common_prefixes: Option<Vec<String>>,
+    /// Opaque cursor for the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
 }
 
-const DEFAULT_LIMIT: usize = 100;
-const MAX_LIMIT: usize = 1000;
+pub(crate) const DEFAULT_LIMIT: usize = 100;
+pub(crate) const MAX_LIMIT: usize = 1000;
 const MAX_OFFSET: usize = 1_000_000;
 
 pub fn handle_list(
     handler: &Handler,
     query: Option<&str>,
-) -> Result<Response<Full<Bytes>>, Error> {
+) -> Result<Response<ResponseBody>, Error> {
     // Parse query parameters with proper validation
     let mut offset = 0usize;
     let mut limit = DEFAULT_LIMIT;
+    let mut range = KeyRange::default();
+    let mut cursor = None;
+    let mut delimiter = None;
 
     if let Some(q) = query {
         for (k, v) in url::form_urlencoded::parse(q.as_bytes()) {
@@ -51,40 +65,120 @@ pub fn handle_list(
                     }
                     // Invalid values default to DEFAULT_LIMIT
                 }
+                "prefix" => range.prefix = Some(v.into_owned().into_bytes()),
+                "start_after" => range.start_after = Some(v.into_owned().into_bytes()),
+                "cursor" => cursor = Some(decode_cursor(&v)?),
+                "end" => range.end = Some(v.into_owned().into_bytes()),
+                "reverse" => range.reverse = v == "true" || v == "1",
+                "delimiter" if !v.is_empty() => delimiter = Some(v.into_owned().into_bytes()),
                 _ => {}
             }
         }
     }
 
-    // Get keys
-    let key_pairs = handler.db().list_tree_paginated("keys", offset, limit)?;
-    let total = handler.db().count_tree("keys")?;
-
-    let keys: Vec<KeyInfo> = key_pairs
-        .into_iter()
-        .map(|(key, meta_bytes)| {
-            let meta: crate::storage::KeyMeta = bincode::deserialize(&meta_bytes)
-                .map_err(|e| Error::Internal(format!("Failed to deserialize metadata: {}", e)))?;
-            let key_str = String::from_utf8(key)
-                .map_err(|e| Error::Internal(format!("Invalid key UTF-8: {}", e)))?;
-            Ok::<KeyInfo, Error>(KeyInfo {
-                key: key_str,
-                size: meta.size,
-                hash: meta.hash.to_hex_string(),
-                hash_algorithm: "xxhash3".to_string(),
-                refs: meta.refs,
-                created_at: meta.created_at,
-            })
-        })
-        .collect::<Result<_, Error>>()?;
-
-    let response = ListResponse { keys, total };
+    if cursor.is_some() {
+        if range.start_after.is_some() {
+            return Err(Error::InvalidRequest(
+                "cursor and start_after are mutually exclusive".to_string()
+            ));
+        }
+        range.start_after = cursor;
+    }
+
+    if delimiter.is_some() && offset > 0 {
+        return Err(Error::InvalidRequest(
+            "offset cannot be combined with delimiter, use cursor".to_string()
+        ));
+    }
+    let prefix_len = range.prefix.as_ref().map_or(0, Vec::len);
+
+    let total = handler.db().stats()?.keys as usize;
+
+    // Expired keys are hidden until the sweeper removes them
+    let now = unix_now();
+    let mut keys: Vec<KeyInfo> = Vec::with_capacity(limit.min(DEFAULT_LIMIT));
+    let mut common_prefixes = Vec::new();
+    let mut scan: Box<dyn Iterator<Item = _>> = Box::new(handler.db().scan_keys(&range).skip(offset));
+    // Where the next page resumes, and whether anything is left after it
+    let mut resume_after = None;
+    let mut more = false;
+    while let Some(item) = scan.next() {
+        if keys.len() + common_prefixes.len() == limit {
+            more = true;
+            break;
+        }
+        let (key, meta_bytes) = item?;
+
+        if let Some(group) = delimiter.as_deref().and_then(|d| common_prefix(&key, prefix_len, d)) {
+            common_prefixes.push(String::from_utf8_lossy(group).into_owned());
+
+            // Seek past the whole group instead of walking it. Keys are UTF-8,
+            // which never contains 0xFF, so `group + 0xFF` sorts after all of them.
+            let skip_to = if range.reverse { group.to_vec() } else { [group, &[0xFF]].concat() };
+            range.start_after = Some(skip_to.clone());
+            resume_after = Some(skip_to);
+            scan = Box::new(handler.db().scan_keys(&range));
+            continue;
+        }
+
+        resume_after = Some(key.to_vec());
+        let meta = KeyMeta::from_bytes(&meta_bytes)
+            .map_err(|e| Error::Internal(format!("Failed to deserialize metadata: {}", e)))?;
+        if meta.is_expired(now) {
+            continue;
+        }
+        let key_str = String::from_utf8(key.to_vec())
+            .map_err(|e| Error::Internal(format!("Invalid key UTF-8: {}", e)))?;
+        keys.push(KeyInfo {
+            key: key_str,
+            size: meta.size,
+            hash: meta.hash.to_hex_string(),
+            hash_algorithm: meta.hash_algorithm.name().to_string(),
+            refs: handler.db().live_ref_count(&meta.hash)?,
+            created_at: meta.created_at,
+            modified_at: meta.modified_at,
+            expires_at: meta.expires_at,
+        });
+    }
+
+    // Only hand out a cursor when something is left to read
+    let next_cursor = resume_after.filter(|_| more).map(hex::encode);
+    let common_prefixes = delimiter.map(|_| common_prefixes);
+
+    let response = ListResponse { keys, total, common_prefixes, next_cursor };
     let json = serde_json::to_string_pretty(&response)
         .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
 
     Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
-        .body(Full::new(Bytes::from(json)))
+        .body(full(Bytes::from(json)))
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
+
+/// Returns the part of `key` up to and including the first `delimiter` after
+/// the listing prefix, if there is one.
+fn common_prefix<'a>(key: &'a [u8], prefix_len: usize, delimiter: &[u8]) -> Option<&'a [u8]> {
+    key.get(prefix_len..)?
+        .windows(delimiter.len())
+        .position(|window| window == delimiter)
+        .map(|pos| &key[..prefix_len + pos + delimiter.len()])
+}
+
+/// Cursors are the hex-encoded resume point of the previous page
+pub(crate) fn decode_cursor(cursor: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(cursor).map_err(|_| Error::InvalidRequest("Invalid cursor".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(b"tenant/project/file.bin", 0, b"/"), Some(&b"tenant/"[..]));
+        assert_eq!(common_prefix(b"tenant/project/file.bin", 7, b"/"), Some(&b"tenant/project/"[..]));
+        assert_eq!(common_prefix(b"tenant/project/file.bin", 15, b"/"), None);
+        assert_eq!(common_prefix(b"a::b::c", 0, b"::"), Some(&b"a::"[..]));
+    }
+}
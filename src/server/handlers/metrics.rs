@@ -1,23 +1,26 @@
 use hyper::{Response, StatusCode};
-use http_body_util::Full;
 use hyper::body::Bytes;
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, full};
 use crate::server::Handler;
+use crate::storage::scrub;
 
-pub fn handle_metrics(handler: &Handler) -> Result<Response<Full<Bytes>>, Error> {
-    // Update current stats
-    let keys_count = handler.db().count_tree("keys")?;
-    let objects_count = handler.db().count_tree("objects")?;
+pub fn handle_metrics(handler: &Handler) -> Result<Response<ResponseBody>, Error> {
+    // Update gauges from the persistent counters
+    let stats = handler.db().stats()?;
 
-    handler.metrics().set_keys(keys_count as u64);
-    handler.metrics().set_objects(objects_count as u64);
+    handler.metrics().set_keys(stats.keys);
+    handler.metrics().set_objects(stats.objects);
+    handler.metrics().set_bytes(stats.stored_bytes);
+    handler.metrics().set_logical_bytes(stats.logical_bytes);
+    handler.metrics().set_scrub_corrupt_objects(scrub::corrupt_count(handler.db()) as u64);
 
     let metrics_text = handler.metrics().to_prometheus();
 
     Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "text/plain; version=0.0.4")
-        .body(Full::new(Bytes::from(metrics_text)))
+        .body(full(Bytes::from(metrics_text)))
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
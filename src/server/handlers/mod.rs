@@ -4,7 +4,13 @@ pub mod get;
 pub mod delete;
 pub mod head;
 pub mod list;
+pub mod objects;
+pub mod range;
 pub mod batch;
+pub mod copy;
+pub mod versions;
+pub mod trash;
 pub mod metrics;
+pub mod admin;
 
-pub use common::{validate_key, get_key_meta, build_hash_response, build_hash_response_with_body};
+pub use common::{validate_key, validate_new_key, get_key_meta, parse_expiry, parse_precondition, build_hash_response, build_hash_response_with_body};
@@ -0,0 +1,225 @@
+use hyper::{Request, Response, StatusCode, body::Incoming};
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, read_body_to_bytes};
+use crate::server::body::{ResponseBody, empty, full, stream_reader};
+use crate::server::Handler;
+use crate::storage::{dedup, scrub};
+use crate::util::compression::ObjectHeader;
+use crate::util::hash::Hash;
+
+const OBJECTS_PREFIX: &str = "/objects/";
+const KEYS_SUFFIX: &str = "/keys";
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Objects larger than this (stored) are streamed instead of decoded up front
+const INLINE_DECOMPRESS_LIMIT: usize = 64 * 1024;
+
+/// Most hashes accepted by one `POST /objects/missing`
+const MAX_HAVE_HASHES: usize = 10_000;
+
+#[derive(Deserialize)]
+struct MissingRequest {
+    hashes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MissingResponse {
+    /// The requested hashes the server holds no object for, in request order
+    missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ObjectKeysResponse {
+    hash: String,
+    /// Number of keys sharing the object
+    refs: u64,
+    keys: Vec<String>,
+    /// Opaque cursor for the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// The hash in `/objects/{hash}`, if `path` has that shape.
+///
+/// As with [`parse_keys_path`], only well-formed hashes match.
+pub fn parse_object_path(path: &str) -> Option<Hash> {
+    path.strip_prefix(OBJECTS_PREFIX).and_then(Hash::from_hex)
+}
+
+/// The hash in `/objects/{hash}/keys`, if `path` has that shape.
+///
+/// Only well-formed hashes match, so keys such as `objects/report/keys`
+/// keep working as plain keys.
+pub fn parse_keys_path(path: &str) -> Option<Hash> {
+    path.strip_prefix(OBJECTS_PREFIX)?
+        .strip_suffix(KEYS_SUFFIX)
+        .and_then(Hash::from_hex)
+}
+
+/// Serve an object by its hash, or only its headers for `HEAD`.
+pub async fn handle_get_object(
+    handler: &Handler,
+    path: &str,
+    head_only: bool,
+) -> Result<Response<ResponseBody>, Error> {
+    let hash = parse_object_path(path)
+        .ok_or_else(|| Error::NotFound("Path not found".to_string()))?;
+    let stored = handler.db().objects_tree().get(hash)?
+        .ok_or_else(|| Error::NotFound(format!("Object '{}' not found", hash.to_hex_string())))?;
+    scrub::check_known_corrupt(handler.db(), &hash, &stored)?;
+    let refs = handler.db().live_ref_count(&hash)?;
+    let algorithm = dedup::stored_algorithm(handler.db(), &hash)?;
+
+    // Objects without a header predate it and have to be decoded for their size
+    let size = match ObjectHeader::parse(&stored) {
+        Some(header) => header.original_len,
+        None => handler.compressor().decompress(&stored)?.len() as u64,
+    };
+
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/octet-stream")
+        .header("Content-Length", size.to_string())
+        .header("X-Hash", hash.to_hex_string())
+        .header("ETag", format!("\"{}\"", hash.to_hex_string()))
+        .header("X-Refs", refs.to_string())
+        .header("X-Stored-Size", stored.len().to_string());
+    // Unknown for an object nothing references any more
+    if let Some(algorithm) = algorithm {
+        builder = builder.header("X-Hash-Algorithm", algorithm.name());
+    }
+
+    let body = if head_only {
+        empty()
+    } else {
+        handler.metrics().inc_gets();
+        if stored.len() > INLINE_DECOMPRESS_LIMIT {
+            stream_reader(handler.compressor().reader(stored)?, 0..size)
+        } else {
+            full(handler.compressor().decompress(&stored)?)
+        }
+    };
+
+    builder.body(body)
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// Report which of the listed hashes the server has no object for, so a
+/// client can skip uploading content that is already stored.
+pub async fn handle_missing_objects(
+    handler: &Handler,
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
+    let data = read_body_to_bytes(req.into_body()).await?;
+    let request: MissingRequest = serde_json::from_slice(&data)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+    if request.hashes.len() > MAX_HAVE_HASHES {
+        return Err(Error::InvalidRequest(format!(
+            "At most {} hashes per request", MAX_HAVE_HASHES
+        )));
+    }
+
+    let objects_tree = handler.db().objects_tree();
+    let mut missing = Vec::new();
+    for hex in request.hashes {
+        let hash = Hash::from_hex(&hex)
+            .ok_or_else(|| Error::InvalidRequest(format!("Invalid hash: {}", hex)))?;
+        if !objects_tree.contains_key(hash)? {
+            missing.push(hex);
+        }
+    }
+
+    let json = serde_json::to_string(&MissingResponse { missing })
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// List the keys pointing at an object, from the `refs` reverse index.
+pub fn handle_object_keys(
+    handler: &Handler,
+    path: &str,
+    query: Option<&str>,
+) -> Result<Response<ResponseBody>, Error> {
+    let hash = parse_keys_path(path)
+        .ok_or_else(|| Error::NotFound("Path not found".to_string()))?;
+
+    let mut limit = DEFAULT_LIMIT;
+    let mut cursor = None;
+    if let Some(q) = query {
+        for (k, v) in url::form_urlencoded::parse(q.as_bytes()) {
+            match k.as_ref() {
+                "limit" => {
+                    if let Ok(val) = v.parse::<usize>() {
+                        limit = val.clamp(1, MAX_LIMIT);
+                    }
+                }
+                "cursor" => {
+                    cursor = Some(hex::decode(v.as_bytes())
+                        .map_err(|_| Error::InvalidRequest("Invalid cursor".to_string()))?);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut keys = Vec::with_capacity(limit.min(DEFAULT_LIMIT));
+    let mut next_cursor = None;
+    for item in handler.db().scan_refs(&hash, cursor.as_deref()) {
+        let key = item?;
+        if keys.len() == limit {
+            next_cursor = keys.last().map(hex::encode);
+            break;
+        }
+        keys.push(String::from_utf8(key.to_vec())
+            .map_err(|e| Error::Internal(format!("Invalid key UTF-8: {}", e)))?);
+    }
+
+    let refs = handler.db().live_ref_count(&hash)?;
+    if refs == 0 && keys.is_empty() && cursor.is_none() {
+        return Err(Error::NotFound(format!("Object '{}' not found", hash.to_hex_string())));
+    }
+
+    let response = ObjectKeysResponse { hash: hash.to_hex_string(), refs, keys, next_cursor };
+    let json = serde_json::to_string_pretty(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys_path() {
+        let hash = Hash::compute(b"value");
+        let path = format!("/objects/{}/keys", hash.to_hex_string());
+        assert_eq!(parse_keys_path(&path), Some(hash));
+
+        assert_eq!(parse_keys_path("/objects/report/keys"), None);
+        assert_eq!(parse_keys_path(&format!("/objects/{}", hash.to_hex_string())), None);
+        assert_eq!(parse_keys_path(&format!("/other/{}/keys", hash.to_hex_string())), None);
+    }
+
+    #[test]
+    fn test_parse_object_path() {
+        let hash = Hash::compute(b"value");
+        assert_eq!(parse_object_path(&format!("/objects/{}", hash.to_hex_string())), Some(hash));
+        assert_eq!(parse_object_path(&format!("/objects/{}/keys", hash.to_hex_string())), None);
+        assert_eq!(parse_object_path("/objects/missing"), None);
+        assert_eq!(parse_object_path("/objects/abc"), None);
+    }
+}
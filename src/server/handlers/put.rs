@@ -1,55 +1,103 @@
-use hyper::{Request, Response, StatusCode, body::Incoming};
-use http_body_util::Full;
+use hyper::{Request, Response, StatusCode, body::{Body, Incoming}};
+use http_body_util::BodyExt;
+use tokio::sync::mpsc;
 use hyper::body::Bytes;
 
 use crate::error::Error;
+use crate::server::body::{ResponseBody, full, STREAM_CHANNEL_DEPTH};
 use crate::server::Handler;
-use crate::util::hash::Hash;
+use crate::util::checksum::{ChecksumVerifier, ExpectedChecksums};
+use crate::storage::{dedup, Precondition, TransactionManager};
+use crate::util::hash::{Hash, HashAlgorithm};
 use crate::error::read_body_to_bytes;
-use crate::server::handlers::common::validate_key;
+use crate::server::handlers::common::{validate_new_key, parse_expiry, parse_precondition};
+use crate::util::time::unix_now;
 
-/// PUT handler with xxHash3-128 for performance and 128-bit collision resistance
-/// Compression is done inline for small payloads, blocking task for large ones.
+/// Bodies up to this size (per `Content-Length`) are buffered and compressed inline
+const INLINE_BODY_LIMIT: u64 = 64 * 1024;
+
+/// PUT handler; objects are addressed by the configured content hash
+/// (xxHash3-128 by default). Compression is done inline for small payloads; large or unsized bodies are
+/// compressed chunk by chunk on a blocking task as they arrive.
+///
+/// An object is stored as a single value, so the whole encoded object is held
+/// in memory until it is committed; for incompressible data that is the raw
+/// value itself. Memory per request is therefore bounded by the value size,
+/// which `with_max_value_size` caps: larger bodies are refused with `413`,
+/// before reading when `Content-Length` announces them and otherwise as soon
+/// as the limit is crossed.
 pub async fn handle_put(
     handler: &Handler,
     key: &str,
     req: Request<Incoming>,
-) -> Result<Response<Full<Bytes>>, Error> {
-    validate_key(key)?;
-
-    // Read entire body
-    let data = read_body_to_bytes(req.into_body()).await?;
-
-    // Compute hash using xxHash3-128 (128-bit for better collision resistance)
-    let hash = Hash::compute(&data);
-    let size = data.len() as u64;
-    let data_len = data.len();
-
-    // Compress data - use blocking task only for larger payloads
-    let compressed = if data_len > 64 * 1024 {
-        // Large payload (>64KB) - use blocking task to avoid blocking async runtime
-        tokio::task::spawn_blocking({
-            let compressor = handler.compressor().clone();
-            let data = data.to_vec();
-            move || compressor.compress(&data)
+) -> Result<Response<ResponseBody>, Error> {
+    validate_new_key(key)?;
+
+    // Optional TTL from X-TTL / X-Expires-At
+    let expires_at = parse_expiry(req.headers(), unix_now())?;
+
+    // Optional If-Match / If-None-Match, checked inside the write transaction
+    let precondition = parse_precondition(req.headers())?;
+
+    // Optional X-Expected-Hash / Content-MD5 / Digest, checked before committing
+    let checksums = ExpectedChecksums::from_headers(req.headers())?;
+
+    // X-Content-Hash links the key to an object the server already holds
+    if let Some(value) = req.headers().get("X-Content-Hash") {
+        let hash = value.to_str().ok()
+            .and_then(Hash::from_hex)
+            .ok_or_else(|| Error::InvalidRequest("Invalid X-Content-Hash".to_string()))?;
+        if !checksums.is_empty() {
+            return Err(Error::InvalidRequest(
+                "Checksum headers cannot be combined with X-Content-Hash".to_string()
+            ));
+        }
+        ensure_empty_body(req.into_body()).await?;
+        return link_existing(handler, key, &hash, expires_at, &precondition);
+    }
+    let verifier = checksums.verifier();
+
+    let body = req.into_body();
+    handler.check_value_size(body.size_hint().lower())?;
+    let algorithm = handler.hash_algorithm();
+
+    // Bodies known to be small are buffered and compressed inline; anything
+    // else is hashed and compressed chunk by chunk as it arrives
+    let (compressed, hash, size) = match body.size_hint().upper() {
+        Some(len) if len <= INLINE_BODY_LIMIT => {
+            let data = read_body_to_bytes(body).await?;
+            handler.check_value_size(data.len() as u64)?;
+
+            let hash = algorithm.compute(&data);
+            let mut verifier = verifier;
+            verifier.update(&data);
+            verifier.verify(&hash)?;
+            (handler.compressor().compress(&data)?, hash, data.len() as u64)
+        }
+        _ => receive_streaming(handler, algorithm, verifier, body).await?,
+    };
+
+    // A fast-hash hit on different bytes is re-addressed by SHA-256, and a
+    // SHA-256 one refused; the object moves to the check and back rather
+    // than being copied
+    let (compressed, hash, algorithm) = {
+        let db = handler.db().clone();
+        let compressor = handler.compressor().clone();
+        tokio::task::spawn_blocking(move || {
+            dedup::resolve_address(&db, &compressor, &compressed, hash, algorithm)
+                .map(|(hash, algorithm)| (compressed, hash, algorithm))
         })
         .await
-        .map_err(|e| Error::Internal(format!("Compression task failed: {}", e)))??
-    } else {
-        // Small payload - compress inline (faster due to no task spawn overhead)
-        handler.compressor().compress(&data)?
+        .map_err(|e| Error::Internal(format!("Dedup check failed: {}", e)))??
     };
 
-    // Get hash bytes for storage lookup
-    let hash_bytes = hash.as_bytes();
-
-    // Check for deduplication
-    let objects_tree = handler.db().objects_tree();
-    let object_exists = objects_tree.contains_key(hash_bytes)?;
-
-    // Store object atomically (create or update)
-    let tx_manager = crate::storage::TransactionManager::new(handler.db().clone());
-    let old_hash = tx_manager.update_key_atomic(key, &compressed, &hash, size)?;
+    // Store object atomically (create or update); the transaction reports
+    // whether the object was already stored
+    let tx_manager = TransactionManager::new(handler.db().clone())
+        .with_hash_algorithm(algorithm)
+        .with_versioning(handler.versioning().clone())
+        .with_dedup_verify(Some(handler.compressor().clone()));
+    let (old_hash, deduplicated) = tx_manager.update_key_if(key, &compressed, &hash, size, expires_at, &precondition)?;
 
     handler.metrics().inc_puts();
 
@@ -60,22 +108,111 @@ pub async fn handle_put(
         StatusCode::CREATED
     };
 
-    let deduplicated = object_exists;
     if deduplicated {
         handler.metrics().inc_dedup_hits();
     }
 
-    build_dedup_response(status, &hash, deduplicated)
+    build_dedup_response(status, &hash, algorithm, deduplicated)
+}
+
+/// Point `key` at the object stored under `hash`, keeping the algorithm the
+/// object was addressed with. Fails with `404` if the object is unknown.
+fn link_existing(
+    handler: &Handler,
+    key: &str,
+    hash: &Hash,
+    expires_at: Option<u64>,
+    precondition: &Precondition,
+) -> Result<Response<ResponseBody>, Error> {
+    let algorithm = dedup::stored_algorithm(handler.db(), hash)?
+        .unwrap_or(handler.hash_algorithm());
+    let tx_manager = TransactionManager::new(handler.db().clone())
+        .with_hash_algorithm(algorithm)
+        .with_versioning(handler.versioning().clone());
+    let (old_hash, _) = tx_manager.link_key_if(key, hash, expires_at, precondition)?;
+
+    handler.metrics().inc_puts();
+    handler.metrics().inc_dedup_hits();
+
+    let status = if old_hash.is_some() { StatusCode::OK } else { StatusCode::CREATED };
+    build_dedup_response(status, hash, algorithm, true)
+}
+
+/// A PUT by reference carries no value; reject any body rather than ignore it.
+async fn ensure_empty_body(mut body: Incoming) -> Result<(), Error> {
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| Error::InvalidRequest(format!("Failed to read body: {}", e)))?;
+        if frame.data_ref().is_some_and(|data| !data.is_empty()) {
+            return Err(Error::InvalidRequest(
+                "A PUT with X-Content-Hash must have an empty body".to_string()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Hash and compress a request body on a blocking task as it arrives.
+///
+/// Chunks are handed over through a bounded channel, so a fast client cannot
+/// get ahead of compression. Reading stops once the body is over the value
+/// size limit. The body is checked against `verifier` once complete. Returns
+/// the compressed value, its hash and the uncompressed size.
+async fn receive_streaming(
+    handler: &Handler,
+    algorithm: HashAlgorithm,
+    mut verifier: ChecksumVerifier,
+    mut body: Incoming,
+) -> Result<(Vec<u8>, Hash, u64), Error> {
+    let compressor = handler.compressor().clone();
+    let (tx, mut rx) = mpsc::channel::<Bytes>(STREAM_CHANNEL_DEPTH);
+
+    let worker = tokio::task::spawn_blocking(move || {
+        let mut hasher = algorithm.hasher();
+        let mut stream = compressor.stream();
+        let mut size = 0u64;
+        while let Some(chunk) = rx.blocking_recv() {
+            hasher.update(&chunk);
+            verifier.update(&chunk);
+            stream.write(&chunk)?;
+            size += chunk.len() as u64;
+        }
+        let hash = hasher.finalize();
+        verifier.verify(&hash)?;
+        Ok::<_, Error>((stream.finish()?, hash, size))
+    });
+
+    let mut received = 0u64;
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| Error::InvalidRequest(format!("Failed to read body: {}", e)))?;
+        if let Ok(chunk) = frame.into_data() {
+            received += chunk.len() as u64;
+            handler.check_value_size(received)?;
+            // A closed channel means the worker failed; its error is returned below
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    }
+    drop(tx);
+
+    worker
+        .await
+        .map_err(|e| Error::Internal(format!("Compression task failed: {}", e)))?
 }
 
 /// Build a PUT response with hash headers
 #[inline]
-fn build_dedup_response(status: StatusCode, hash: &Hash, deduplicated: bool) -> Result<Response<Full<Bytes>>, Error> {
+fn build_dedup_response(
+    status: StatusCode,
+    hash: &Hash,
+    algorithm: HashAlgorithm,
+    deduplicated: bool,
+) -> Result<Response<ResponseBody>, Error> {
     Response::builder()
         .status(status)
         .header("X-Hash", hash.to_hex_string())
-        .header("X-Hash-Algorithm", "xxhash3")
+        .header("X-Hash-Algorithm", algorithm.name())
         .header("X-Deduplicated", if deduplicated { "true" } else { "false" })
-        .body(Full::new(Bytes::from(format!("{}\n", hash.to_hex_string()))))
+        .body(full(Bytes::from(format!("{}\n", hash.to_hex_string()))))
         .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
 }
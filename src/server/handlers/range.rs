@@ -0,0 +1,356 @@
+//! HTTP range request support
+//!
+//! Parses `Range: bytes=` headers for GET and builds `206 Partial Content`
+//! responses, using `multipart/byteranges` when several ranges are requested.
+//!
+//! Overlapping and adjacent ranges are merged, and a request whose ranges
+//! add up to more than the value is served in full, so a response never
+//! carries more than the value's own bytes. Parts are sent as they are
+//! produced rather than joined into one buffer.
+
+use hyper::{HeaderMap, Response, StatusCode};
+use hyper::body::Bytes;
+use std::io::Read;
+use std::ops::Range;
+
+use crate::error::Error;
+use crate::server::body::{ResponseBody, chunks, empty, full, stream_reader_parts};
+use crate::server::handlers::common::{etag, last_modified};
+use crate::storage::KeyMeta;
+
+/// Requests with more ranges than this are answered with the full value
+const MAX_RANGES: usize = 64;
+
+/// Outcome of evaluating `Range` / `If-Range` against a value.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header: send the whole value
+    Full,
+    /// Satisfiable half-open byte ranges, ascending and disjoint
+    Partial(Vec<Range<u64>>),
+    /// No requested range overlaps the value
+    Unsatisfiable,
+}
+
+impl RangeRequest {
+    /// Number of leading bytes needed to serve the request, `None` for the whole value
+    pub fn prefix_len(&self) -> Option<u64> {
+        match self {
+            RangeRequest::Partial(ranges) => ranges.iter().map(|r| r.end).max(),
+            _ => None,
+        }
+    }
+}
+
+/// Evaluates the request's `Range` header, honouring `If-Range`.
+///
+/// Per RFC 9110, an `If-Range` validator that no longer matches the current
+/// ETag or `Last-Modified` date turns the request into a full GET.
+pub fn range_request(headers: &HeaderMap, meta: &KeyMeta) -> RangeRequest {
+    let Some(range) = headers.get("Range").and_then(|v| v.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    if let Some(if_range) = headers.get("If-Range") {
+        let current = if_range
+            .to_str()
+            .is_ok_and(|v| v.trim() == etag(meta) || v.trim() == last_modified(meta));
+        if !current {
+            return RangeRequest::Full;
+        }
+    }
+
+    parse_range(range, meta.size)
+}
+
+/// Parses a `Range` header value against a value of `size` bytes.
+///
+/// Syntactically invalid headers and units other than `bytes` are ignored,
+/// which serves the full value as RFC 9110 allows. So are requests for more
+/// bytes in total than the value holds, which only overlapping ranges can
+/// make; otherwise overlapping and adjacent ranges are merged.
+pub fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    let mut seen = 0usize;
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        seen += 1;
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let range = if start.is_empty() {
+            // Suffix range: the last N bytes
+            let Ok(len) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            size.saturating_sub(len)..size
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                size
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end.saturating_add(1).min(size),
+                    _ => return RangeRequest::Full,
+                }
+            };
+            start..end
+        };
+
+        if !range.is_empty() {
+            ranges.push(range);
+        }
+    }
+
+    let requested = ranges.iter().fold(0u64, |total, r| total.saturating_add(r.end - r.start));
+    if seen == 0 || seen > MAX_RANGES || requested > size {
+        RangeRequest::Full
+    } else if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(coalesce(ranges))
+    }
+}
+
+/// Sorts `ranges` and merges those that overlap or touch.
+fn coalesce(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Builds a `206 Partial Content` response from a buffered value.
+///
+/// `data` must hold at least the first `prefix_len()` bytes of the value.
+/// The parts are slices of `data`, so nothing is copied.
+///
+/// # Errors
+/// Returns `Error::Internal` if `data` is shorter than a requested range or
+/// response building fails.
+pub fn build_partial_response(
+    meta: &KeyMeta,
+    ranges: &[Range<u64>],
+    data: Bytes,
+) -> Result<Response<ResponseBody>, Error> {
+    let slice = |range: &Range<u64>| {
+        if range.end > data.len() as u64 {
+            return Err(Error::Internal("Stored object is shorter than its metadata".to_string()));
+        }
+        Ok(data.slice(range.start as usize..range.end as usize))
+    };
+
+    if let [range] = ranges {
+        return build_single_range_response(meta, range, full(slice(range)?));
+    }
+
+    let multipart = Multipart::new(meta, ranges);
+    let mut body = Vec::with_capacity(ranges.len() * 2 + 1);
+    for (header, range) in &multipart.parts {
+        body.push(header.clone());
+        body.push(slice(range)?);
+    }
+    body.push(multipart.trailer.clone());
+    multipart.respond(meta, chunks(body))
+}
+
+/// Builds a multi-range `206 Partial Content` response whose parts are
+/// streamed from `reader`, which yields the whole value.
+///
+/// # Errors
+/// Returns `Error::Internal` if response building fails.
+pub fn build_streamed_multipart_response<R>(
+    meta: &KeyMeta,
+    ranges: &[Range<u64>],
+    reader: R,
+) -> Result<Response<ResponseBody>, Error>
+where
+    R: Read + Send + 'static,
+{
+    let multipart = Multipart::new(meta, ranges);
+    let body = stream_reader_parts(reader, multipart.parts.clone(), multipart.trailer.clone());
+    multipart.respond(meta, body)
+}
+
+/// Layout of a `multipart/byteranges` body: a header before each range and
+/// a closing delimiter.
+struct Multipart {
+    boundary: String,
+    parts: Vec<(Bytes, Range<u64>)>,
+    trailer: Bytes,
+}
+
+impl Multipart {
+    fn new(meta: &KeyMeta, ranges: &[Range<u64>]) -> Self {
+        // The content hash is a stable boundary that is vanishingly unlikely
+        // to appear in the parts
+        let boundary = format!("kv-storage-{}", meta.hash.to_hex_string());
+        let parts = ranges
+            .iter()
+            .map(|range| {
+                let header = format!(
+                    "\r\n--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: {}\r\n\r\n",
+                    boundary,
+                    content_range(meta, range)
+                );
+                (Bytes::from(header), range.clone())
+            })
+            .collect();
+        let trailer = Bytes::from(format!("\r\n--{}--\r\n", boundary));
+        Multipart { boundary, parts, trailer }
+    }
+
+    fn content_length(&self) -> u64 {
+        let parts: u64 = self.parts.iter().map(|(header, range)| header.len() as u64 + range.end - range.start).sum();
+        parts + self.trailer.len() as u64
+    }
+
+    fn respond(&self, meta: &KeyMeta, body: ResponseBody) -> Result<Response<ResponseBody>, Error> {
+        partial_response_builder(meta)
+            .header("Content-Type", format!("multipart/byteranges; boundary={}", self.boundary))
+            .header("Content-Length", self.content_length().to_string())
+            .body(body)
+            .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+    }
+}
+
+/// Builds a single-range `206 Partial Content` response around a buffered or
+/// streamed body holding exactly `range`.
+///
+/// # Errors
+/// Returns `Error::Internal` if response building fails.
+pub fn build_single_range_response(
+    meta: &KeyMeta,
+    range: &Range<u64>,
+    body: ResponseBody,
+) -> Result<Response<ResponseBody>, Error> {
+    partial_response_builder(meta)
+        .header("Content-Type", "application/octet-stream")
+        .header("Content-Range", content_range(meta, range))
+        .header("Content-Length", (range.end - range.start).to_string())
+        .body(body)
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+fn partial_response_builder(meta: &KeyMeta) -> hyper::http::response::Builder {
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header("Accept-Ranges", "bytes")
+        .header("X-Hash", meta.hash.to_hex_string())
+        .header("X-Hash-Algorithm", meta.hash_algorithm.name())
+        .header("ETag", etag(meta))
+        .header("Last-Modified", last_modified(meta))
+}
+
+fn content_range(meta: &KeyMeta, range: &Range<u64>) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, meta.size)
+}
+
+/// Builds a `416 Range Not Satisfiable` response.
+///
+/// # Errors
+/// Returns `Error::Internal` if response building fails.
+pub fn build_range_not_satisfiable_response(meta: &KeyMeta) -> Result<Response<ResponseBody>, Error> {
+    Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Range", format!("bytes */{}", meta.size))
+        .body(empty())
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hash::Hash;
+    use http_body_util::BodyExt;
+
+    fn partial(ranges: &[Range<u64>]) -> RangeRequest {
+        RangeRequest::Partial(ranges.to_vec())
+    }
+
+    fn single(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(vec![Range { start, end }])
+    }
+
+    #[test]
+    fn test_parse_range_single() {
+        assert_eq!(parse_range("bytes=0-9", 100), single(0, 10));
+        assert_eq!(parse_range("bytes=90-", 100), single(90, 100));
+        assert_eq!(parse_range("bytes=-10", 100), single(90, 100));
+        assert_eq!(parse_range("bytes=50-500", 100), single(50, 100));
+        assert_eq!(parse_range("bytes=-500", 100), single(0, 100));
+    }
+
+    #[test]
+    fn test_parse_range_multi() {
+        assert_eq!(
+            parse_range("bytes=0-0, -1, 200-300", 100),
+            partial(&[0..1, 99..100])
+        );
+    }
+
+    #[test]
+    fn test_parse_range_coalesces() {
+        assert_eq!(parse_range("bytes=50-59, 0-9, 5-19", 100), partial(&[0..20, 50..60]));
+        assert_eq!(parse_range("bytes=0-9, 10-19", 100), single(0, 20));
+
+        // Overlaps asking for more than the value itself are served in full
+        assert_eq!(parse_range("bytes=0-, 0-", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=0-59, 40-99", 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_parse_range_unsatisfiable_and_ignored() {
+        assert_eq!(parse_range("bytes=100-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+
+        assert_eq!(parse_range("items=0-1", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=5-1", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_range_request_if_range() {
+        let meta = KeyMeta::new(Hash::compute(b"value"), 5);
+        let mut headers = HeaderMap::new();
+        headers.insert("Range", "bytes=0-1".parse().unwrap());
+        assert_eq!(range_request(&headers, &meta), single(0, 2));
+
+        headers.insert("If-Range", etag(&meta).parse().unwrap());
+        assert_eq!(range_request(&headers, &meta), single(0, 2));
+
+        headers.insert("If-Range", "\"stale\"".parse().unwrap());
+        assert_eq!(range_request(&headers, &meta), RangeRequest::Full);
+    }
+
+    #[tokio::test]
+    async fn test_build_partial_response_multipart() {
+        let meta = KeyMeta::new(Hash::compute(b"0123456789"), 10);
+        let response = build_partial_response(&meta, &[0..2, 8..10], Bytes::from_static(b"0123456789")).unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let length: usize = response.headers()["Content-Length"].to_str().unwrap().parse().unwrap();
+
+        let content_type = response.headers()["Content-Type"].to_str().unwrap().to_string();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), length);
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+    }
+}
@@ -0,0 +1,128 @@
+use hyper::{Request, Response, StatusCode, body::Incoming};
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, read_body_to_bytes};
+use crate::server::body::{ResponseBody, full};
+use crate::server::Handler;
+use crate::server::handlers::common::validate_key;
+use crate::server::handlers::list::{decode_cursor, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::storage::{trash, TransactionManager};
+
+#[derive(Deserialize)]
+struct RestoreDeletedRequest {
+    key: String,
+}
+
+#[derive(Serialize)]
+struct RestoreDeletedResponse {
+    key: String,
+    hash: String,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct TrashInfo {
+    key: String,
+    hash: String,
+    size: u64,
+    deleted_at: u64,
+    /// When the background purge drops the key for good
+    purge_at: u64,
+}
+
+#[derive(Serialize)]
+struct TrashResponse {
+    items: Vec<TrashInfo>,
+    /// Opaque cursor for the next page, absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// `GET /trash`: deleted keys awaiting purge, in key order.
+pub fn handle_list_trash(
+    handler: &Handler,
+    query: Option<&str>,
+) -> Result<Response<ResponseBody>, Error> {
+    let mut prefix = String::new();
+    let mut limit = DEFAULT_LIMIT;
+    let mut start_after = None;
+    for (k, v) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        match k.as_ref() {
+            "prefix" => prefix = v.into_owned(),
+            "limit" => {
+                if let Ok(val) = v.parse::<usize>() {
+                    limit = val.clamp(1, MAX_LIMIT);
+                }
+            }
+            "cursor" => {
+                let cursor = decode_cursor(&v)?;
+                start_after = Some(String::from_utf8(cursor)
+                    .map_err(|_| Error::InvalidRequest("Invalid cursor".to_string()))?);
+            }
+            _ => {}
+        }
+    }
+
+    // One extra entry tells whether another page follows
+    let mut entries = trash::list(handler.db(), &prefix, start_after.as_deref(), limit + 1)?;
+    let more = entries.len() > limit;
+    entries.truncate(limit);
+    let next_cursor = entries.last()
+        .filter(|_| more)
+        .map(|entry| hex::encode(&entry.key));
+
+    let retention = handler.trash_retention();
+    let items = entries.into_iter()
+        .map(|entry| TrashInfo {
+            hash: entry.meta.hash.to_hex_string(),
+            size: entry.meta.size,
+            deleted_at: entry.deleted_at,
+            purge_at: entry.deleted_at.saturating_add(retention),
+            key: entry.key,
+        })
+        .collect();
+
+    let response = TrashResponse { items, next_cursor };
+    let json = serde_json::to_string_pretty(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+/// `POST /trash/restore`: bring a deleted key back under its own name.
+pub async fn handle_restore_deleted(
+    handler: &Handler,
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
+    let data = read_body_to_bytes(req.into_body()).await?;
+    let request: RestoreDeletedRequest = serde_json::from_slice(&data)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+    validate_key(&request.key)?;
+
+    let tx_manager = TransactionManager::new(handler.db().clone());
+    let meta = tx_manager.restore_deleted(&request.key)?;
+    handler.metrics().inc_puts();
+
+    let response = RestoreDeletedResponse {
+        key: request.key,
+        hash: meta.hash.to_hex_string(),
+        size: meta.size,
+        expires_at: meta.expires_at,
+    };
+    let json = serde_json::to_string(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("X-Hash", meta.hash.to_hex_string())
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
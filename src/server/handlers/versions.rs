@@ -0,0 +1,173 @@
+use hyper::{Request, Response, StatusCode, body::Incoming};
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, read_body_to_bytes};
+use crate::server::body::{ResponseBody, full};
+use crate::server::Handler;
+use crate::server::handlers::common::{validate_key, get_key_meta};
+use crate::storage::versions;
+use crate::storage::KeyMeta;
+use crate::util::time::unix_now;
+
+/// What a `?version=N` or `?versions` query asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionQuery {
+    /// The history of the key
+    List,
+    /// One version of the key, live or archived
+    Version(u64),
+}
+
+#[derive(Deserialize)]
+struct RestoreRequest {
+    key: String,
+    version: u64,
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    key: String,
+    /// Number of the restored value as the key's live version
+    version: u64,
+    restored_from: u64,
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+    version: u64,
+    hash: String,
+    size: u64,
+    modified_at: u64,
+    /// When the value was replaced, absent for the live one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archived_at: Option<u64>,
+    current: bool,
+}
+
+#[derive(Serialize)]
+struct VersionsResponse {
+    key: String,
+    /// Newest first
+    versions: Vec<VersionInfo>,
+}
+
+/// The version query in a GET or HEAD query string, if any.
+pub fn parse_version_query(query: Option<&str>) -> Result<Option<VersionQuery>, Error> {
+    let mut requested = None;
+    for (k, v) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        match k.as_ref() {
+            "versions" => requested = Some(VersionQuery::List),
+            "version" => {
+                let version = v.parse::<u64>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| Error::InvalidRequest(format!("Invalid version: {}", v)))?;
+                requested = Some(VersionQuery::Version(version));
+            }
+            _ => {}
+        }
+    }
+    Ok(requested)
+}
+
+/// Metadata of version `version` of `key`, the live value included.
+pub fn version_meta(handler: &Handler, key: &str, version: u64) -> Result<KeyMeta, Error> {
+    if version == versions::current_version(handler.db(), key)? {
+        return get_key_meta(handler, key);
+    }
+    let mut meta = versions::get(handler.db(), key, version)?
+        .ok_or_else(|| Error::NotFound(format!("Version {} of key '{}' not found", version, key)))?
+        .meta;
+    meta.refs = handler.db().live_ref_count(&meta.hash)?;
+    Ok(meta)
+}
+
+/// `GET /{key}?versions`: the live value and retained versions, newest first.
+pub fn handle_list_versions(handler: &Handler, key: &str) -> Result<Response<ResponseBody>, Error> {
+    let mut history = versions::list(handler.db(), key)?;
+    history.reverse();
+
+    let mut listed = Vec::with_capacity(history.len() + 1);
+    let live = handler.db().keys_tree().get(key.as_bytes())?
+        .map(|bytes| KeyMeta::from_bytes(&bytes))
+        .transpose()?
+        .filter(|meta| !meta.is_expired(unix_now()));
+    if let Some(meta) = live {
+        let version = versions::current_version(handler.db(), key)?;
+        listed.push(version_info(version, &meta, None, true));
+    }
+    for entry in &history {
+        listed.push(version_info(entry.version, &entry.meta, entry.archived_at, false));
+    }
+    if listed.is_empty() {
+        return Err(Error::NotFound(format!("Key '{}' not found", key)));
+    }
+
+    let response = VersionsResponse { key: key.to_string(), versions: listed };
+    let json = serde_json::to_string_pretty(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+fn version_info(version: u64, meta: &KeyMeta, archived_at: Option<u64>, current: bool) -> VersionInfo {
+    VersionInfo {
+        version,
+        hash: meta.hash.to_hex_string(),
+        size: meta.size,
+        modified_at: meta.modified_at,
+        archived_at,
+        current,
+    }
+}
+
+/// `POST /restore`: make a retained version the key's live value again.
+pub async fn handle_restore(
+    handler: &Handler,
+    req: Request<Incoming>,
+) -> Result<Response<ResponseBody>, Error> {
+    let data = read_body_to_bytes(req.into_body()).await?;
+    let request: RestoreRequest = serde_json::from_slice(&data)
+        .map_err(|e| Error::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+    validate_key(&request.key)?;
+
+    let (hash, version) = versions::restore(handler.db(), handler.versioning(), &request.key, request.version)?;
+    handler.metrics().inc_puts();
+
+    let response = RestoreResponse {
+        key: request.key,
+        version,
+        restored_from: request.version,
+        hash: hash.to_hex_string(),
+    };
+    let json = serde_json::to_string(&response)
+        .map_err(|e| Error::Internal(format!("JSON serialization error: {}", e)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("X-Hash", hash.to_hex_string())
+        .body(full(Bytes::from(json)))
+        .map_err(|e| Error::Internal(format!("Failed to build response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_query() {
+        assert_eq!(parse_version_query(None).unwrap(), None);
+        assert_eq!(parse_version_query(Some("versions")).unwrap(), Some(VersionQuery::List));
+        assert_eq!(parse_version_query(Some("version=3")).unwrap(), Some(VersionQuery::Version(3)));
+        assert_eq!(parse_version_query(Some("other=1")).unwrap(), None);
+        assert!(parse_version_query(Some("version=0")).is_err());
+        assert!(parse_version_query(Some("version=latest")).is_err());
+    }
+}
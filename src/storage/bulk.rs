@@ -0,0 +1,162 @@
+//! Removal of every key under a prefix
+//!
+//! Keys are deleted a chunk at a time, each chunk in its own transaction, so
+//! a large prefix never holds the write lock for long and normal traffic
+//! keeps flowing between chunks. Objects left without references are removed
+//! in the same transaction as their last key; with trash enabled the keys
+//! move to the trash instead, as for a single delete, and nothing is freed
+//! until it is purged.
+
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::storage::{KeyMeta, StorageDb, TransactionManager};
+use crate::util::hash::Hash;
+use crate::util::time::unix_now;
+
+/// Keys deleted per transaction
+pub const DEFAULT_CHUNK_SIZE: usize = 1000;
+
+/// Progress, and finally the outcome, of a prefix delete
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PrefixDeleteReport {
+    /// Keys deleted, or that would be for a dry run
+    pub keys: u64,
+    /// Logical size of those keys
+    pub bytes: u64,
+    /// Objects freed because no key outside the prefix shares them
+    pub freed_objects: u64,
+    /// Stored (compressed) bytes of the freed objects
+    pub freed_bytes: u64,
+    pub dry_run: bool,
+    /// Set on the final report
+    pub done: bool,
+}
+
+/// Delete every key starting with `prefix`, calling `progress` after each
+/// chunk of `chunk_size` keys. With `trash`, live keys are moved to the trash.
+///
+/// With `dry_run` nothing is written; the report gives what a real run would
+/// delete and free at this moment. Keys written under the prefix while a
+/// real run is in progress may or may not be deleted.
+pub fn delete_prefix<F>(
+    db: &StorageDb,
+    prefix: &[u8],
+    dry_run: bool,
+    trash: bool,
+    chunk_size: usize,
+    mut progress: F,
+) -> Result<PrefixDeleteReport, Error>
+where
+    F: FnMut(&PrefixDeleteReport),
+{
+    let chunk_size = chunk_size.max(1);
+    let mut report = if dry_run {
+        count_prefix(db, prefix, trash, chunk_size, &mut progress)?
+    } else {
+        delete_chunks(db, prefix, trash, chunk_size, &mut progress)?
+    };
+    report.done = true;
+    progress(&report);
+    Ok(report)
+}
+
+fn delete_chunks<F>(
+    db: &StorageDb,
+    prefix: &[u8],
+    trash: bool,
+    chunk_size: usize,
+    progress: &mut F,
+) -> Result<PrefixDeleteReport, Error>
+where
+    F: FnMut(&PrefixDeleteReport),
+{
+    let tx_manager = TransactionManager::new(db.clone()).with_trash(trash);
+    let mut report = PrefixDeleteReport::default();
+    let mut after: Option<Vec<u8>> = None;
+
+    loop {
+        // Resume after the last key of the previous chunk rather than at the
+        // prefix, so a key that could not be removed is not retried forever
+        let lower = match &after {
+            Some(key) => Bound::Excluded(key.clone()),
+            None => Bound::Included(prefix.to_vec()),
+        };
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for item in db.keys_tree().range::<Vec<u8>, _>((lower, Bound::Unbounded)).keys() {
+            let key = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            chunk.push(String::from_utf8(key.to_vec())
+                .map_err(|e| Error::Internal(format!("Invalid key UTF-8: {}", e)))?);
+            if chunk.len() == chunk_size {
+                break;
+            }
+        }
+        let Some(last) = chunk.last() else {
+            break;
+        };
+        after = Some(last.as_bytes().to_vec());
+
+        let delta = tx_manager.delete_keys_atomic(&chunk)?;
+        report.keys += delta.keys.unsigned_abs();
+        report.bytes += delta.logical_bytes.unsigned_abs();
+        report.freed_objects += delta.objects.unsigned_abs();
+        report.freed_bytes += delta.stored_bytes.unsigned_abs();
+
+        if chunk.len() < chunk_size {
+            break;
+        }
+        progress(&report);
+    }
+
+    Ok(report)
+}
+
+fn count_prefix<F>(
+    db: &StorageDb,
+    prefix: &[u8],
+    trash: bool,
+    chunk_size: usize,
+    progress: &mut F,
+) -> Result<PrefixDeleteReport, Error>
+where
+    F: FnMut(&PrefixDeleteReport),
+{
+    let now = unix_now();
+    let mut report = PrefixDeleteReport { dry_run: true, ..Default::default() };
+    // Keys under the prefix per object; an object is freed if that covers
+    // all of its references
+    let mut refs_under: HashMap<Hash, u64> = HashMap::new();
+
+    for item in db.keys_tree().scan_prefix(prefix) {
+        let (_, value) = item?;
+        let meta = KeyMeta::from_bytes(&value)?;
+        report.keys += 1;
+        report.bytes += meta.size;
+        // A trashed key keeps its reference; expired keys bypass the trash
+        if !trash || meta.is_expired(now) {
+            *refs_under.entry(meta.hash).or_default() += 1;
+        }
+
+        if report.keys.is_multiple_of(chunk_size as u64) {
+            progress(&report);
+        }
+    }
+
+    for (hash, count) in refs_under {
+        if db.ref_count(&hash)? > count {
+            continue;
+        }
+        if let Some(data) = db.objects_tree().get(hash)? {
+            report.freed_objects += 1;
+            report.freed_bytes += data.len() as u64;
+        }
+    }
+
+    Ok(report)
+}
@@ -1,14 +1,113 @@
 use crate::error::Error;
+use crate::storage::{objects, refcounts};
+use crate::storage::stats::{self, StorageStats};
+use crate::util::hash::Hash;
 use sled::{Db as SledDb, Tree, IVec, Mode};
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
 use std::path::Path;
 
 const KEYS_TREE: &str = "keys";
 const OBJECTS_TREE: &str = "objects";
 const REFS_TREE: &str = "refs";
+const EXPIRY_TREE: &str = "expiry";
+const STATS_TREE: &str = "stats";
+const REFCOUNTS_TREE: &str = "refcounts";
+const CORRUPT_TREE: &str = "corrupt";
+const VERSIONS_TREE: &str = "versions";
+const TRASH_TREE: &str = "trash";
 
 const DEFAULT_CACHE_CAPACITY: usize = 1_024_000_000; // 1GB
 
+/// Lower and upper bound of a key scan, in key order
+type KeyBounds = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/// Bounds for a key scan.
+///
+/// `start_after` and `end` are exclusive and follow the scan direction: a
+/// reverse scan starts below `start_after` and stops above `end`.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRange {
+    pub prefix: Option<Vec<u8>>,
+    pub start_after: Option<Vec<u8>>,
+    pub end: Option<Vec<u8>>,
+    pub reverse: bool,
+}
+
+impl KeyRange {
+    /// `None` if the range is empty
+    fn bounds(&self) -> Option<KeyBounds> {
+        let (after, before) = if self.reverse {
+            (&self.end, &self.start_after)
+        } else {
+            (&self.start_after, &self.end)
+        };
+
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        if let Some(prefix) = &self.prefix {
+            lower = Bound::Included(prefix.clone());
+            if let Some(next) = prefix_successor(prefix) {
+                upper = Bound::Excluded(next);
+            }
+        }
+        if let Some(after) = after {
+            lower = tighter(lower, Bound::Excluded(after.clone()), Ordering::Greater);
+        }
+        if let Some(before) = before {
+            upper = tighter(upper, Bound::Excluded(before.clone()), Ordering::Less);
+        }
+
+        let empty = match (&lower, &upper) {
+            (Bound::Included(l), Bound::Excluded(u)) | (Bound::Excluded(l), Bound::Excluded(u)) => l >= u,
+            _ => false,
+        };
+        (!empty).then_some((lower, upper))
+    }
+}
+
+fn scan_range<'a>(tree: &'a Tree, range: &KeyRange) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>> + 'a> {
+    let Some(bounds) = range.bounds() else {
+        return Box::new(std::iter::empty());
+    };
+
+    let iter = tree.range::<Vec<u8>, _>(bounds);
+    if range.reverse {
+        Box::new(iter.rev().map(|item| item.map_err(Error::from)))
+    } else {
+        Box::new(iter.map(|item| item.map_err(Error::from)))
+    }
+}
+
+/// Smallest key greater than every key starting with `prefix`
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut next = prefix.to_vec();
+    while let Some(last) = next.pop() {
+        if last < u8::MAX {
+            next.push(last + 1);
+            return Some(next);
+        }
+    }
+    None
+}
+
+/// Picks the more restrictive of two bounds; `wins` is the ordering a key
+/// must have relative to the other to be the tighter one
+fn tighter(current: Bound<Vec<u8>>, candidate: Bound<Vec<u8>>, wins: Ordering) -> Bound<Vec<u8>> {
+    match (&current, &candidate) {
+        (Bound::Unbounded, _) => candidate,
+        (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) => {
+            match b.cmp(a) {
+                Ordering::Equal if matches!(candidate, Bound::Excluded(_)) => candidate,
+                ord if ord == wins => candidate,
+                _ => current,
+            }
+        }
+        (_, Bound::Unbounded) => current,
+    }
+}
+
 #[derive(Clone)]
 pub struct DbWrapper {
     db: Arc<SledDb>,
@@ -16,6 +115,14 @@ pub struct DbWrapper {
     keys_tree: Arc<Tree>,
     objects_tree: Arc<Tree>,
     refs_tree: Arc<Tree>,
+    expiry_tree: Arc<Tree>,
+    stats_tree: Arc<Tree>,
+    refcounts_tree: Arc<Tree>,
+    corrupt_tree: Arc<Tree>,
+    versions_tree: Arc<Tree>,
+    trash_tree: Arc<Tree>,
+    // Shared by writes, taken exclusively by repairs
+    maintenance: Arc<RwLock<()>>,
 }
 
 impl DbWrapper {
@@ -44,12 +151,35 @@ impl DbWrapper {
         let keys_tree = Arc::new(db.open_tree(KEYS_TREE)?);
         let objects_tree = Arc::new(db.open_tree(OBJECTS_TREE)?);
         let refs_tree = Arc::new(db.open_tree(REFS_TREE)?);
+        let expiry_tree = Arc::new(db.open_tree(EXPIRY_TREE)?);
+        let stats_tree = Arc::new(db.open_tree(STATS_TREE)?);
+        let refcounts_tree = Arc::new(db.open_tree(REFCOUNTS_TREE)?);
+        let corrupt_tree = Arc::new(db.open_tree(CORRUPT_TREE)?);
+        let versions_tree = Arc::new(db.open_tree(VERSIONS_TREE)?);
+        let trash_tree = Arc::new(db.open_tree(TRASH_TREE)?);
+
+        // One-time full scans for databases written before the object header
+        // and the counters existed. Adding headers changes the stored sizes.
+        if objects::upgrade_legacy_objects(&db, &objects_tree)? > 0 {
+            stats::recount(&keys_tree, &objects_tree, &stats_tree)?;
+        } else {
+            stats::ensure_initialized(&keys_tree, &objects_tree, &stats_tree)?;
+        }
+        refcounts::ensure_initialized(&db, &refs_tree, &refcounts_tree)?;
+        refcounts::ensure_algorithms_recorded(&db, &keys_tree, &versions_tree, &trash_tree, &refcounts_tree)?;
 
         Ok(Self {
             db,
             keys_tree,
             objects_tree,
             refs_tree,
+            expiry_tree,
+            stats_tree,
+            refcounts_tree,
+            corrupt_tree,
+            versions_tree,
+            trash_tree,
+            maintenance: Arc::new(RwLock::new(())),
         })
     }
 
@@ -68,6 +198,67 @@ impl DbWrapper {
         &self.refs_tree
     }
 
+    /// Expiry index: big-endian expires_at + key -> empty
+    #[inline]
+    pub fn expiry_tree(&self) -> &Tree {
+        &self.expiry_tree
+    }
+
+    /// Persistent counters, see [`stats`]
+    #[inline]
+    pub fn stats_tree(&self) -> &Tree {
+        &self.stats_tree
+    }
+
+    /// Object hash -> number of referencing keys, see [`refcounts`]
+    #[inline]
+    pub fn refcounts_tree(&self) -> &Tree {
+        &self.refcounts_tree
+    }
+
+    /// Object hash -> damage found by the scrubber, see [`scrub`](crate::storage::scrub)
+    #[inline]
+    pub fn corrupt_tree(&self) -> &Tree {
+        &self.corrupt_tree
+    }
+
+    /// History of overwritten keys, see [`versions`](crate::storage::versions)
+    #[inline]
+    pub fn versions_tree(&self) -> &Tree {
+        &self.versions_tree
+    }
+
+    /// Deleted keys awaiting purge, see [`trash`](crate::storage::trash)
+    #[inline]
+    pub fn trash_tree(&self) -> &Tree {
+        &self.trash_tree
+    }
+
+    /// Held for the duration of every write transaction.
+    ///
+    /// Fails with `Error::Unavailable` while a repair holds
+    /// [`exclusive_access`](Self::exclusive_access), rather than blocking the
+    /// caller for the length of a full scan.
+    pub fn write_access(&self) -> Result<RwLockReadGuard<'_, ()>, Error> {
+        match self.maintenance.try_read() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(e)) => Ok(e.into_inner()),
+            Err(TryLockError::WouldBlock) => {
+                Err(Error::Unavailable("Database is being repaired, retry later".to_string()))
+            }
+        }
+    }
+
+    /// Wait for in-flight writes and refuse new ones until the guard drops.
+    pub fn exclusive_access(&self) -> RwLockWriteGuard<'_, ()> {
+        self.maintenance.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Current key/object/byte totals, O(1)
+    pub fn stats(&self) -> Result<StorageStats, Error> {
+        stats::read(&self.stats_tree)
+    }
+
     #[inline]
     pub fn inner(&self) -> &SledDb {
         &self.db
@@ -124,6 +315,56 @@ impl DbWrapper {
         Ok(result)
     }
 
+    /// Iterate over the keys tree within `range`, in key order or reversed.
+    ///
+    /// Built on sled range scans, so resuming from a cursor is O(log n)
+    /// regardless of how deep into the keyspace it points.
+    pub fn scan_keys(&self, range: &KeyRange) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>> + '_> {
+        scan_range(&self.keys_tree, range)
+    }
+
+    /// Iterate over the trash tree within `range`, seeking to a cursor as
+    /// [`scan_keys`](Self::scan_keys) does.
+    pub fn scan_trash(&self, range: &KeyRange) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>> + '_> {
+        scan_range(&self.trash_tree, range)
+    }
+
+    /// References holding object `hash`: live keys, retained versions and
+    /// trash entries, O(1)
+    pub fn ref_count(&self, hash: &Hash) -> Result<u64, Error> {
+        refcounts::read(&self.refcounts_tree, hash)
+    }
+
+    /// Number of live keys sharing object `hash`, counted from the `refs`
+    /// index. Unlike [`ref_count`](Self::ref_count), versions and trash
+    /// entries are left out.
+    pub fn live_ref_count(&self, hash: &Hash) -> Result<u64, Error> {
+        let mut count = 0;
+        for item in self.scan_refs(hash, None) {
+            item?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Keys pointing at object `hash`, in key order, from the `refs` index.
+    /// `start_after` resumes a previous scan.
+    pub fn scan_refs(&self, hash: &Hash, start_after: Option<&[u8]>) -> impl Iterator<Item = Result<IVec, Error>> + '_ {
+        let prefix = hash.as_bytes().to_vec();
+        let lower = match start_after {
+            Some(key) => Bound::Excluded([prefix.as_slice(), key].concat()),
+            None => Bound::Included(prefix.clone()),
+        };
+        self.refs_tree
+            .range::<Vec<u8>, _>((lower, Bound::Unbounded))
+            .keys()
+            .take_while(move |item| item.as_ref().map_or(true, |ref_key| ref_key.starts_with(&prefix)))
+            .map(|item| {
+                let ref_key = item?;
+                Ok(ref_key.subslice(16, ref_key.len() - 16))
+            })
+    }
+
     pub fn count_keys(&self) -> usize {
         self.keys_tree.len()
     }
@@ -173,6 +414,43 @@ mod tests {
         assert_eq!(result.unwrap(), b"value1");
     }
 
+    fn scan(db: &DbWrapper, range: KeyRange) -> Vec<String> {
+        db.scan_keys(&range)
+            .map(|item| String::from_utf8(item.unwrap().0.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_keys_bounds() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db = DbWrapper::open(temp_dir.path().join("test")).unwrap();
+        for key in ["a/1", "a/2", "a/3", "b/1", "c"] {
+            db.keys_tree().insert(key, b"").unwrap();
+        }
+        let range = |prefix: Option<&str>, start_after: Option<&str>, end: Option<&str>, reverse| KeyRange {
+            prefix: prefix.map(|p| p.as_bytes().to_vec()),
+            start_after: start_after.map(|s| s.as_bytes().to_vec()),
+            end: end.map(|e| e.as_bytes().to_vec()),
+            reverse,
+        };
+
+        assert_eq!(scan(&db, range(None, None, None, false)), ["a/1", "a/2", "a/3", "b/1", "c"]);
+        assert_eq!(scan(&db, range(Some("a/"), None, None, false)), ["a/1", "a/2", "a/3"]);
+        assert_eq!(scan(&db, range(Some("a/"), Some("a/1"), None, false)), ["a/2", "a/3"]);
+        assert_eq!(scan(&db, range(None, Some("a/3"), Some("c"), false)), ["b/1"]);
+        assert_eq!(scan(&db, range(Some("a/"), None, None, true)), ["a/3", "a/2", "a/1"]);
+        assert_eq!(scan(&db, range(Some("a/"), Some("a/3"), Some("a/1"), true)), ["a/2"]);
+        assert!(scan(&db, range(None, Some("c"), Some("a"), false)).is_empty());
+        assert!(scan(&db, range(Some("z"), None, None, false)).is_empty());
+    }
+
+    #[test]
+    fn test_prefix_successor() {
+        assert_eq!(prefix_successor(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_successor(b"a\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_successor(b"\xff\xff"), None);
+    }
+
     #[test]
     fn test_list_tree_paginated_empty() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -0,0 +1,148 @@
+//! Collision-safe deduplication
+//!
+//! xxHash3 is fast but an attacker can craft a value with the same hash as
+//! someone else's, and SHA-256 addresses are truncated to 128 bits, so
+//! finding a collision takes about 2^64 work (the birthday bound), not 2^128.
+//! Neither address is trusted on its own: every dedup hit is byte-compared
+//! against the stored object. A value that only collides on xxHash3 is
+//! addressed by SHA-256 instead of aliasing the existing object; one that
+//! collides on its SHA-256 address has nowhere else to go and is refused.
+//!
+//! [`resolve_address`] runs before the write transaction; the transaction
+//! repeats the comparison with [`check_stored`], so a colliding object stored
+//! in between makes the write fail rather than alias it.
+
+use std::io::Read;
+
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+use tracing::warn;
+
+use crate::error::Error;
+use crate::storage::{refcounts, StorageDb};
+use crate::util::compression::{Compressor, ObjectHeader};
+use crate::util::hash::{Hash, HashAlgorithm};
+
+const COMPARE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Address for a new value, given its encoded object and its hash under
+/// `algorithm`.
+///
+/// Returns the hash and algorithm the value should be stored under.
+///
+/// # Errors
+/// Returns `Error::Conflict` if the value collides with a different object
+/// on its SHA-256 address.
+pub fn resolve_address(
+    db: &StorageDb,
+    compressor: &Compressor,
+    encoded: &[u8],
+    hash: Hash,
+    algorithm: HashAlgorithm,
+) -> Result<(Hash, HashAlgorithm), Error> {
+    let Some(existing) = db.objects_tree().get(hash)? else {
+        return Ok((hash, algorithm));
+    };
+    if same_content(compressor, &existing, encoded)? {
+        return Ok((hash, algorithm));
+    }
+    if algorithm.is_cryptographic() {
+        return Err(collision(hash, algorithm));
+    }
+
+    warn!("{} collision on {}; storing value under SHA-256", algorithm.name(), hash.to_hex_string());
+    let hash = hash_object(compressor, encoded, HashAlgorithm::Sha256)?;
+    resolve_address(db, compressor, encoded, hash, HashAlgorithm::Sha256)
+}
+
+/// Inside a write transaction, check that the object stored under `hash`,
+/// if any, holds the same value as `encoded`.
+///
+/// # Errors
+/// Aborts with `Error::Conflict` if a different object is stored there,
+/// e.g. one written since [`resolve_address`] looked.
+pub fn check_stored(
+    objects_tree: &TransactionalTree,
+    compressor: &Compressor,
+    hash: &Hash,
+    algorithm: HashAlgorithm,
+    encoded: &[u8],
+) -> Result<(), ConflictableTransactionError<Error>> {
+    let Some(existing) = objects_tree.get(hash.as_ref())? else {
+        return Ok(());
+    };
+    if same_content(compressor, &existing, encoded).map_err(ConflictableTransactionError::Abort)? {
+        return Ok(());
+    }
+    Err(ConflictableTransactionError::Abort(collision(*hash, algorithm)))
+}
+
+fn collision(hash: Hash, algorithm: HashAlgorithm) -> Error {
+    warn!("{} collision on {}; refusing to alias the stored object", algorithm.name(), hash.to_hex_string());
+    Error::Conflict(format!(
+        "A different object is stored under {} hash {} - please retry",
+        algorithm.name(),
+        hash.to_hex_string()
+    ))
+}
+
+/// Algorithm that addressed the object stored under `hash`, as recorded
+/// with its reference count, `None` if nothing references it. O(1).
+pub fn stored_algorithm(db: &StorageDb, hash: &Hash) -> Result<Option<HashAlgorithm>, Error> {
+    refcounts::read_algorithm(db.refcounts_tree(), hash)
+}
+
+/// Hash the decoded contents of a stored object under `algorithm`.
+pub fn hash_object(compressor: &Compressor, encoded: &[u8], algorithm: HashAlgorithm) -> Result<Hash, Error> {
+    let mut hasher = algorithm.hasher();
+    let mut reader = compressor.reader(encoded.to_vec())?;
+    let mut chunk = vec![0u8; COMPARE_CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&chunk[..n]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Whether two stored objects decode to the same bytes.
+///
+/// Compared chunk by chunk, so memory stays bounded for large values.
+pub fn same_content(compressor: &Compressor, a: &[u8], b: &[u8]) -> Result<bool, Error> {
+    if a == b {
+        return Ok(true);
+    }
+    if let (Some(ha), Some(hb)) = (ObjectHeader::parse(a), ObjectHeader::parse(b)) {
+        if ha.original_len != hb.original_len {
+            return Ok(false);
+        }
+    }
+
+    let mut ra = compressor.reader(a.to_vec())?;
+    let mut rb = compressor.reader(b.to_vec())?;
+    let mut ca = vec![0u8; COMPARE_CHUNK_SIZE];
+    let mut cb = vec![0u8; COMPARE_CHUNK_SIZE];
+    loop {
+        let na = read_full(&mut ra, &mut ca)?;
+        let nb = read_full(&mut rb, &mut cb)?;
+        if na != nb || ca[..na] != cb[..nb] {
+            return Ok(false);
+        }
+        if na == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buf` as far as the reader allows; short only at end of input.
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,33 @@
+use crate::error::Error;
+use crate::storage::{StorageDb, TransactionManager};
+
+/// Remove keys whose TTL has elapsed.
+///
+/// Walks the expiry index in timestamp order up to `now` and deletes at most
+/// `limit` keys through `TransactionManager::delete_expired_key_atomic`, so
+/// unreferenced objects are garbage-collected like on a regular DELETE.
+/// Returns the number of keys removed.
+pub fn sweep_expired(db: &StorageDb, now: u64, limit: usize) -> Result<usize, Error> {
+    let tx_manager = TransactionManager::new(db.clone());
+    let expiry_tree = db.expiry_tree();
+    let upper = (now.saturating_add(1)).to_be_bytes();
+
+    let due: Vec<_> = expiry_tree
+        .range(..upper.as_slice())
+        .take(limit)
+        .collect::<Result<_, _>>()?;
+
+    let mut removed = 0;
+    for (index_key, _) in due {
+        let key = String::from_utf8_lossy(&index_key[8..]).to_string();
+
+        if tx_manager.delete_expired_key_atomic(&key, now)?.is_some() {
+            removed += 1;
+        } else {
+            // Key was refreshed or deleted concurrently; drop the stale index entry
+            expiry_tree.remove(&index_key)?;
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,399 @@
+//! Consistency check across the storage trees
+//!
+//! Verifies that every key, retained version and deleted key in the trash
+//! points at an existing, intact object, that the `refs` index matches the
+//! keys, that every object decodes and hashes to its address, and that the
+//! reference counts and counters agree with what is actually stored. With
+//! `repair`, damage is fixed in place: keys, versions and trash entries whose
+//! value is lost are removed, the indexes are rewritten, orphaned and corrupt
+//! objects are deleted and the counts are rebuilt.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::storage::transactions::expiry_index_key;
+use crate::storage::{refcounts, scrub, stats, KeyMeta, StorageDb, StorageStats};
+use crate::util::compression::Compressor;
+use crate::util::hash::Hash;
+
+/// Examples kept per kind of problem; counts are always exact
+const MAX_LISTED: usize = 100;
+
+/// Problems of one kind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Findings<T> {
+    pub count: u64,
+    /// The first `MAX_LISTED` occurrences
+    pub examples: Vec<T>,
+}
+
+impl<T> Default for Findings<T> {
+    fn default() -> Self {
+        Self { count: 0, examples: Vec::new() }
+    }
+}
+
+impl<T> Findings<T> {
+    fn record(&mut self, item: T) {
+        self.count += 1;
+        if self.examples.len() < MAX_LISTED {
+            self.examples.push(item);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorruptObject {
+    pub hash: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DanglingRef {
+    pub hash: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LostVersion {
+    pub key: String,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RefcountMismatch {
+    pub hash: String,
+    pub recorded: u64,
+    pub actual: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatsMismatch {
+    pub recorded: StorageStats,
+    pub actual: StorageStats,
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FsckReport {
+    pub keys_checked: u64,
+    pub refs_checked: u64,
+    pub objects_checked: u64,
+    pub versions_checked: u64,
+    pub trash_checked: u64,
+    /// Keys whose metadata cannot be decoded
+    pub unreadable_keys: Findings<String>,
+    /// Keys whose object does not exist
+    pub missing_objects: Findings<String>,
+    /// Keys whose object is corrupt
+    pub damaged_keys: Findings<String>,
+    /// Objects that fail to decode or do not hash to their address
+    pub corrupt_objects: Findings<CorruptObject>,
+    /// Retained versions that cannot be decoded or whose object is missing
+    /// or corrupt
+    pub lost_versions: Findings<LostVersion>,
+    /// Deleted keys in the trash that cannot be decoded or whose object is
+    /// missing or corrupt
+    pub lost_tombstones: Findings<String>,
+    /// Keys without their `refs` entry
+    pub missing_refs: Findings<String>,
+    /// `refs` entries with no matching key
+    pub dangling_refs: Findings<DanglingRef>,
+    /// Objects no key, version or deleted key points at
+    pub orphan_objects: Findings<String>,
+    pub refcount_mismatches: Findings<RefcountMismatch>,
+    pub stats_mismatch: Option<StatsMismatch>,
+    /// Whether the problems found were fixed
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /// Whether no problem of any kind was found
+    pub fn is_clean(&self) -> bool {
+        self.unreadable_keys.count == 0
+            && self.missing_objects.count == 0
+            && self.damaged_keys.count == 0
+            && self.lost_versions.count == 0
+            && self.lost_tombstones.count == 0
+            && self.corrupt_objects.count == 0
+            && self.missing_refs.count == 0
+            && self.dangling_refs.count == 0
+            && self.orphan_objects.count == 0
+            && self.refcount_mismatches.count == 0
+            && self.stats_mismatch.is_none()
+    }
+}
+
+/// Check every tree, fixing what is found if `repair` is set.
+///
+/// A plain check runs alongside normal traffic, so on a busy server it may
+/// report a write that was in flight; re-run to confirm. A repair holds
+/// [`StorageDb::exclusive_access`] throughout, so writes fail with `503`
+/// until it finishes.
+pub fn check(db: &StorageDb, compressor: &Compressor, repair: bool) -> Result<FsckReport, Error> {
+    let _exclusive = repair.then(|| db.exclusive_access());
+    let mut report = FsckReport::default();
+
+    let corrupt = check_objects(db, compressor, &mut report)?;
+    let mut referenced = check_keys(db, &corrupt, repair, &mut report)?;
+    check_versions(db, &corrupt, repair, &mut referenced, &mut report)?;
+    check_trash(db, &corrupt, repair, &mut referenced, &mut report)?;
+    check_refs(db, repair, &mut report)?;
+    check_orphans(db, &referenced, repair, &mut report)?;
+    let actual = check_refcounts(db, &referenced, repair, &mut report)?;
+
+    let recorded = db.stats()?;
+    if recorded != actual {
+        report.stats_mismatch = Some(StatsMismatch { recorded, actual });
+        if repair {
+            stats::recount(db.keys_tree(), db.objects_tree(), db.stats_tree())?;
+        }
+    }
+
+    report.repaired = repair && !report.is_clean();
+    Ok(report)
+}
+
+/// Decode every object and check it hashes to its address under one of the
+/// supported algorithms. Returns the corrupt ones.
+fn check_objects(db: &StorageDb, compressor: &Compressor, report: &mut FsckReport) -> Result<HashSet<Hash>, Error> {
+    let mut corrupt = HashSet::new();
+
+    for item in db.objects_tree().iter() {
+        let (hash_bytes, data) = item?;
+        report.objects_checked += 1;
+
+        let problem = match Hash::from_slice(&hash_bytes) {
+            None => Some("Object address is not a 16-byte hash".to_string()),
+            Some(hash) => scrub::verify_object(compressor, &data, &hash).err().map(|e| e.to_string()),
+        };
+        if let Some(error) = problem {
+            report.corrupt_objects.record(CorruptObject { hash: hex::encode(&hash_bytes), error });
+            if let Some(hash) = Hash::from_slice(&hash_bytes) {
+                corrupt.insert(hash);
+            }
+        }
+    }
+
+    Ok(corrupt)
+}
+
+/// Check each key's metadata, object and `refs` entry. Returns how many
+/// keys point at each object, not counting keys removed by a repair.
+fn check_keys(
+    db: &StorageDb,
+    corrupt: &HashSet<Hash>,
+    repair: bool,
+    report: &mut FsckReport,
+) -> Result<HashMap<Hash, u64>, Error> {
+    let mut referenced: HashMap<Hash, u64> = HashMap::new();
+
+    for item in db.keys_tree().iter() {
+        let (key, value) = item?;
+        report.keys_checked += 1;
+        let key_str = String::from_utf8_lossy(&key).into_owned();
+
+        let Ok(meta) = KeyMeta::from_bytes(&value) else {
+            report.unreadable_keys.record(key_str);
+            if repair {
+                db.keys_tree().remove(&key)?;
+            }
+            continue;
+        };
+
+        let lost = if corrupt.contains(&meta.hash) {
+            report.damaged_keys.record(key_str.clone());
+            true
+        } else if !db.objects_tree().contains_key(meta.hash)? {
+            report.missing_objects.record(key_str.clone());
+            true
+        } else {
+            false
+        };
+        if lost && repair {
+            // The value cannot be served any more; its refs entry goes with
+            // the dangling ones below
+            db.keys_tree().remove(&key)?;
+            if let Some(at) = meta.expires_at {
+                db.expiry_tree().remove(expiry_index_key(at, &key_str))?;
+            }
+            continue;
+        }
+
+        let ref_key = ref_key(&meta.hash, &key);
+        if !db.refs_tree().contains_key(&ref_key)? {
+            report.missing_refs.record(key_str);
+            if repair {
+                db.refs_tree().insert(ref_key, b"1")?;
+            }
+        }
+        *referenced.entry(meta.hash).or_default() += 1;
+    }
+
+    Ok(referenced)
+}
+
+/// Check that each retained version's object is intact, adding the versions
+/// to the reference counts.
+fn check_versions(
+    db: &StorageDb,
+    corrupt: &HashSet<Hash>,
+    repair: bool,
+    referenced: &mut HashMap<Hash, u64>,
+    report: &mut FsckReport,
+) -> Result<(), Error> {
+    for item in db.versions_tree().iter() {
+        let (tree_key, value) = item?;
+        // Skip the per-key head entries, which hold no reference
+        let Some(key_len) = tree_key.get(..4).and_then(|len| len.try_into().ok()).map(u32::from_be_bytes) else {
+            continue;
+        };
+        let Some(version) = tree_key.get(4 + key_len as usize..).and_then(|v| v.try_into().ok()).map(u64::from_be_bytes) else {
+            continue;
+        };
+        report.versions_checked += 1;
+        let key = String::from_utf8_lossy(&tree_key[4..4 + key_len as usize]).into_owned();
+
+        let hash = KeyMeta::from_stamped_bytes(&value).ok().map(|(_, meta)| meta.hash);
+        let intact = match hash {
+            Some(hash) => !corrupt.contains(&hash) && db.objects_tree().contains_key(hash)?,
+            None => false,
+        };
+        match hash {
+            Some(hash) if intact => *referenced.entry(hash).or_default() += 1,
+            _ => {
+                report.lost_versions.record(LostVersion { key, version });
+                if repair {
+                    db.versions_tree().remove(&tree_key)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that each deleted key's object is intact, adding the trash to the
+/// reference counts.
+fn check_trash(
+    db: &StorageDb,
+    corrupt: &HashSet<Hash>,
+    repair: bool,
+    referenced: &mut HashMap<Hash, u64>,
+    report: &mut FsckReport,
+) -> Result<(), Error> {
+    for item in db.trash_tree().iter() {
+        let (key, value) = item?;
+        report.trash_checked += 1;
+
+        let hash = KeyMeta::from_stamped_bytes(&value).ok().map(|(_, meta)| meta.hash);
+        let intact = match hash {
+            Some(hash) => !corrupt.contains(&hash) && db.objects_tree().contains_key(hash)?,
+            None => false,
+        };
+        match hash {
+            Some(hash) if intact => *referenced.entry(hash).or_default() += 1,
+            _ => {
+                report.lost_tombstones.record(String::from_utf8_lossy(&key).into_owned());
+                if repair {
+                    db.trash_tree().remove(&key)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check each `refs` entry against the key it names.
+fn check_refs(db: &StorageDb, repair: bool, report: &mut FsckReport) -> Result<(), Error> {
+    for item in db.refs_tree().iter().keys() {
+        let ref_key = item?;
+        report.refs_checked += 1;
+
+        let (hash_bytes, key) = ref_key.split_at(ref_key.len().min(16));
+        let points_here = match db.keys_tree().get(key)? {
+            Some(value) => KeyMeta::from_bytes(&value).is_ok_and(|meta| meta.hash.as_bytes() == hash_bytes),
+            None => false,
+        };
+        if !points_here {
+            report.dangling_refs.record(DanglingRef {
+                hash: hex::encode(hash_bytes),
+                key: String::from_utf8_lossy(key).into_owned(),
+            });
+            if repair {
+                db.refs_tree().remove(&ref_key)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find objects no surviving key, version or deleted key points at, corrupt
+/// ones included.
+fn check_orphans(
+    db: &StorageDb,
+    referenced: &HashMap<Hash, u64>,
+    repair: bool,
+    report: &mut FsckReport,
+) -> Result<(), Error> {
+    for item in db.objects_tree().iter().keys() {
+        let hash_bytes = item?;
+        let is_referenced = Hash::from_slice(&hash_bytes).is_some_and(|hash| referenced.contains_key(&hash));
+        if !is_referenced {
+            report.orphan_objects.record(hex::encode(&hash_bytes));
+            if repair {
+                db.objects_tree().remove(&hash_bytes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compare the `refcounts` tree with the counted references, and return the
+/// totals the `stats` counters should hold.
+fn check_refcounts(
+    db: &StorageDb,
+    referenced: &HashMap<Hash, u64>,
+    repair: bool,
+    report: &mut FsckReport,
+) -> Result<StorageStats, Error> {
+    let refcounts_tree = db.refcounts_tree();
+    for (hash, &actual) in referenced {
+        let recorded = refcounts::read(refcounts_tree, hash)?;
+        if recorded != actual {
+            report.refcount_mismatches.record(RefcountMismatch { hash: hash.to_hex_string(), recorded, actual });
+        }
+    }
+    for item in refcounts_tree.iter() {
+        let (hash_bytes, value) = item?;
+        let stale = Hash::from_slice(&hash_bytes).is_none_or(|hash| !referenced.contains_key(&hash));
+        if stale {
+            let recorded = refcounts::decode_count(&value);
+            report.refcount_mismatches.record(RefcountMismatch { hash: hex::encode(&hash_bytes), recorded, actual: 0 });
+        }
+    }
+    if repair && report.refcount_mismatches.count > 0 {
+        refcounts::replace_all(refcounts_tree, referenced)?;
+        refcounts::record_algorithms(db.keys_tree(), db.versions_tree(), db.trash_tree(), refcounts_tree)?;
+    }
+
+    let mut actual = StorageStats::default();
+    for item in db.keys_tree().iter().values() {
+        if let Ok(meta) = KeyMeta::from_bytes(&item?) {
+            actual.keys += 1;
+            actual.logical_bytes += meta.size;
+        }
+    }
+    for item in db.objects_tree().iter().values() {
+        actual.objects += 1;
+        actual.stored_bytes += item?.len() as u64;
+    }
+    Ok(actual)
+}
+
+fn ref_key(hash: &Hash, key: &[u8]) -> Vec<u8> {
+    let mut ref_key = hash.as_ref().to_vec();
+    ref_key.extend_from_slice(key);
+    ref_key
+}
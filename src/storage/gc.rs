@@ -0,0 +1,46 @@
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::storage::{refcounts, StorageDb, TransactionManager};
+use crate::util::hash::Hash;
+
+/// Outcome of an orphan sweep
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    /// Objects examined
+    pub scanned: u64,
+    /// Objects removed because no key referenced them
+    pub reclaimed_objects: u64,
+    /// Stored (compressed) bytes freed
+    pub reclaimed_bytes: u64,
+}
+
+/// Remove objects that no key references.
+///
+/// Overwrites and deletes release their own objects, so this only finds
+/// objects leaked by older versions. Each candidate is removed through
+/// `TransactionManager::gc_object`, which re-checks the reference count in
+/// the same transaction.
+pub fn sweep_orphans(db: &StorageDb) -> Result<GcReport, Error> {
+    let tx_manager = TransactionManager::new(db.clone());
+    let refcounts_tree = db.refcounts_tree();
+    let mut report = GcReport::default();
+
+    for item in db.objects_tree().iter().keys() {
+        let hash_bytes = item?;
+        report.scanned += 1;
+
+        let Some(hash) = Hash::from_slice(&hash_bytes) else {
+            continue;
+        };
+        if refcounts::read(refcounts_tree, &hash)? > 0 {
+            continue;
+        }
+        if let Some(bytes) = tx_manager.gc_object(&hash)? {
+            report.reclaimed_objects += 1;
+            report.reclaimed_bytes += bytes;
+        }
+    }
+
+    Ok(report)
+}
@@ -1,5 +1,6 @@
 use crate::error::Error;
-use crate::util::hash::Hash;
+use crate::util::hash::{Hash, HashAlgorithm};
+use crate::util::time::unix_now;
 use serde::{Deserialize, Serialize};
 use sled::IVec;
 use std::ops::Deref;
@@ -8,21 +9,166 @@ use std::ops::Deref;
 pub struct KeyMeta {
     pub hash: Hash,
     pub size: u64,
+    /// Keys sharing the object. Not maintained on disk, where the count lives
+    /// in the `refcounts` tree; handlers fill it in when reporting a key.
     pub refs: u64,
     pub created_at: u64,
+    /// Unix time (seconds) after which the key is treated as deleted
+    pub expires_at: Option<u64>,
+    /// Unix time (seconds) of the last write to this key
+    pub modified_at: u64,
+    /// Algorithm that produced `hash`
+    pub hash_algorithm: HashAlgorithm,
+}
+
+/// On-disk layout written before configurable hash algorithms, always xxHash3
+#[derive(Deserialize)]
+struct KeyMetaV3 {
+    hash: Hash,
+    size: u64,
+    refs: u64,
+    created_at: u64,
+    expires_at: Option<u64>,
+    modified_at: u64,
+}
+
+/// On-disk layout written before `modified_at` was introduced
+#[derive(Deserialize)]
+struct KeyMetaV2 {
+    hash: Hash,
+    size: u64,
+    refs: u64,
+    created_at: u64,
+    expires_at: Option<u64>,
+}
+
+/// On-disk layout written before per-key TTL was introduced
+#[derive(Deserialize)]
+struct KeyMetaV1 {
+    hash: Hash,
+    size: u64,
+    refs: u64,
+    created_at: u64,
+}
+
+impl From<KeyMetaV3> for KeyMeta {
+    fn from(old: KeyMetaV3) -> Self {
+        Self {
+            hash: old.hash,
+            size: old.size,
+            refs: old.refs,
+            created_at: old.created_at,
+            expires_at: old.expires_at,
+            modified_at: old.modified_at,
+            hash_algorithm: HashAlgorithm::Xxh3,
+        }
+    }
+}
+
+// Older layouts overwrote `created_at` on every update, so it is really the
+// last modification time.
+impl From<KeyMetaV2> for KeyMeta {
+    fn from(old: KeyMetaV2) -> Self {
+        Self {
+            hash: old.hash,
+            size: old.size,
+            refs: old.refs,
+            created_at: old.created_at,
+            expires_at: old.expires_at,
+            modified_at: old.created_at,
+            hash_algorithm: HashAlgorithm::Xxh3,
+        }
+    }
+}
+
+impl From<KeyMetaV1> for KeyMeta {
+    fn from(old: KeyMetaV1) -> Self {
+        Self {
+            hash: old.hash,
+            size: old.size,
+            refs: old.refs,
+            created_at: old.created_at,
+            expires_at: None,
+            modified_at: old.created_at,
+            hash_algorithm: HashAlgorithm::Xxh3,
+        }
+    }
 }
 
 impl KeyMeta {
     pub fn new(hash: Hash, size: u64) -> Self {
+        let now = unix_now();
         Self {
             hash,
             size,
             refs: 1,
-            created_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            created_at: now,
+            expires_at: None,
+            modified_at: now,
+            hash_algorithm: HashAlgorithm::Xxh3,
+        }
+    }
+
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Keep the creation time of the key this metadata replaces
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    pub fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    #[inline]
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(at) if at <= now)
+    }
+
+    /// Seconds left until expiry, `None` if the key never expires
+    #[inline]
+    pub fn ttl_remaining(&self, now: u64) -> Option<u64> {
+        self.expires_at.map(|at| at.saturating_sub(now))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Decode metadata, falling back to older layouts for existing databases.
+    /// Layouts only ever append fields, so they are tried newest first.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bincode::deserialize::<KeyMeta>(bytes) {
+            Ok(meta) => Ok(meta),
+            Err(e) => bincode::deserialize::<KeyMetaV3>(bytes)
+                .map(Into::into)
+                .or_else(|_| bincode::deserialize::<KeyMetaV2>(bytes).map(Into::into))
+                .or_else(|_| bincode::deserialize::<KeyMetaV1>(bytes).map(Into::into))
+                .map_err(|_| e.into()),
+        }
+    }
+
+    /// Encode metadata that is no longer live together with the time it was
+    /// retired, as kept for old versions and deleted keys
+    pub fn to_stamped_bytes(&self, at: u64) -> Result<Vec<u8>, Error> {
+        let mut bytes = at.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.to_bytes()?);
+        Ok(bytes)
+    }
+
+    /// Decode what [`to_stamped_bytes`](Self::to_stamped_bytes) wrote
+    pub fn from_stamped_bytes(bytes: &[u8]) -> Result<(u64, Self), Error> {
+        if bytes.len() < 8 {
+            return Err(Error::Internal("Truncated metadata record".to_string()));
         }
+        let (at, meta) = bytes.split_at(8);
+        let at = at.try_into().map(u64::from_be_bytes).unwrap_or(0);
+        Ok((at, Self::from_bytes(meta)?))
     }
 
     pub fn increment_ref(&mut self) {
@@ -54,7 +200,7 @@ impl KeyStore {
     }
 
     pub fn set(&self, key: &str, meta: &KeyMeta) -> Result<(), Error> {
-        let data = bincode::serialize(meta)?;
+        let data = meta.to_bytes()?;
         self.tree.insert(key.as_bytes(), data)?;
         Ok(())
     }
@@ -101,7 +247,7 @@ impl KeyStore {
     }
 
     fn deserialize_meta(&self, ivec: &IVec) -> Result<KeyMeta, Error> {
-        bincode::deserialize(ivec.deref()).map_err(Into::into)
+        KeyMeta::from_bytes(ivec.deref())
     }
 
     pub fn update_ref_count(&self, key: &str, delta: i32) -> Result<Option<KeyMeta>, Error> {
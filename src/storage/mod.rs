@@ -1,12 +1,24 @@
+pub mod bulk;
 pub mod db;
+pub mod dedup;
+pub mod expiry;
+pub mod fsck;
+pub mod gc;
 pub mod keys;
 pub mod objects;
+pub mod refcounts;
+pub mod rehash;
+pub mod scrub;
+pub mod stats;
 pub mod transactions;
+pub mod trash;
+pub mod versions;
 
 #[cfg(test)]
 mod tests;
 
-pub use db::{DbWrapper, StorageDb};
+pub use db::{DbWrapper, KeyRange, StorageDb};
 pub use keys::{KeyMeta, KeyStore};
 pub use objects::{ObjectStore};
-pub use transactions::TransactionManager;
+pub use stats::StorageStats;
+pub use transactions::{BatchFailure, BatchWrite, Precondition, TransactionManager};
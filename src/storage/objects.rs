@@ -1,8 +1,33 @@
 use crate::error::Error;
-use crate::util::compression::Compressor;
+use crate::util::compression::{self, Compressor};
 use crate::util::hash::Hash;
 use std::sync::Arc;
 
+/// Marker in sled's default tree, set once every object has a header
+const OBJECTS_UPGRADED: &[u8] = b"objects_upgraded";
+
+/// Rewrite objects stored before the object header existed, keeping their
+/// payloads. A no-op once done. Returns the number of objects rewritten.
+///
+/// Must not race with writers; it runs when the database is opened.
+pub fn upgrade_legacy_objects(meta_tree: &sled::Tree, objects_tree: &sled::Tree) -> Result<u64, Error> {
+    if meta_tree.contains_key(OBJECTS_UPGRADED)? {
+        return Ok(0);
+    }
+
+    let mut upgraded = 0;
+    for item in objects_tree.iter() {
+        let (hash, data) = item?;
+        if let Some(framed) = compression::upgrade_legacy(&data)? {
+            objects_tree.insert(hash, framed)?;
+            upgraded += 1;
+        }
+    }
+
+    meta_tree.insert(OBJECTS_UPGRADED, Vec::new())?;
+    Ok(upgraded)
+}
+
 pub struct ObjectStore {
     tree: sled::Tree,
     refs_tree: sled::Tree,
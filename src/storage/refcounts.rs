@@ -0,0 +1,194 @@
+//! Per-object reference counts
+//!
+//! The `refcounts` tree maps an object hash to the number of keys, retained
+//! versions and trash entries pointing at it, and to the algorithm that
+//! addressed the object, so linking a key to the hash needs no search for
+//! one of its references. Counts change inside the same sled transaction as
+//! the key write, so a PUT that deduplicates onto an object and a DELETE that
+//! releases it are serialized: an object is removed exactly when its count
+//! drops to zero.
+//!
+//! Layout: hash -> count (u64, big-endian) + algorithm id (u8). Records
+//! written before the algorithm was kept lack the last byte until the
+//! database is next opened.
+
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::storage::{versions, KeyMeta};
+use crate::util::hash::{Hash, HashAlgorithm};
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use sled::Tree;
+
+/// Marker in sled's default tree, set once counts have been built from `refs`
+const INITIALIZED: &[u8] = b"refcounts_initialized";
+
+/// Marker in sled's default tree, set once every record holds its algorithm
+const ALGORITHMS_RECORDED: &[u8] = b"refcounts_algorithms_recorded";
+
+/// Current count for `hash`, 0 if it has no record.
+pub fn get(tree: &TransactionalTree, hash: &Hash) -> Result<u64, UnabortableTransactionError> {
+    Ok(tree.get(hash)?.map_or(0, |v| decode_count(&v)))
+}
+
+/// Add a reference and return the new count. A new record takes
+/// `algorithm`; an existing one keeps the algorithm it has.
+pub fn increment(tree: &TransactionalTree, hash: &Hash, algorithm: HashAlgorithm) -> Result<u64, UnabortableTransactionError> {
+    let record = tree.get(hash)?;
+    let count = record.as_deref().map_or(0, decode_count).saturating_add(1);
+    let algorithm = record.as_deref().and_then(decode_algorithm).unwrap_or(algorithm);
+    tree.insert(hash.as_bytes(), encode(count, Some(algorithm)))?;
+    Ok(count)
+}
+
+/// Drop a reference and return the new count. The record is removed at zero.
+pub fn decrement(tree: &TransactionalTree, hash: &Hash) -> Result<u64, UnabortableTransactionError> {
+    let record = tree.get(hash)?;
+    let count = record.as_deref().map_or(0, decode_count).saturating_sub(1);
+    if count == 0 {
+        tree.remove(hash.as_bytes())?;
+    } else {
+        tree.insert(hash.as_bytes(), encode(count, record.as_deref().and_then(decode_algorithm)))?;
+    }
+    Ok(count)
+}
+
+/// Build counts from the `refs` tree for databases created before they
+/// existed. A no-op once done.
+pub fn ensure_initialized(meta_tree: &Tree, refs_tree: &Tree, refcounts_tree: &Tree) -> Result<(), Error> {
+    if meta_tree.contains_key(INITIALIZED)? {
+        return Ok(());
+    }
+    rebuild(refs_tree, refcounts_tree)?;
+    meta_tree.insert(INITIALIZED, Vec::new())?;
+    Ok(())
+}
+
+/// Recompute every count from the `refs` tree, which is ordered by hash.
+///
+/// Must not race with writers; it is meant for startup and offline repair.
+pub fn rebuild(refs_tree: &Tree, refcounts_tree: &Tree) -> Result<(), Error> {
+    let mut batch = sled::Batch::default();
+    for item in refcounts_tree.iter().keys() {
+        batch.remove(item?);
+    }
+
+    let mut current: Option<(Hash, u64)> = None;
+    for item in refs_tree.iter().keys() {
+        let ref_key = item?;
+        let Some(hash) = ref_key.get(..16).and_then(Hash::from_slice) else {
+            continue;
+        };
+        current = match current {
+            Some((h, count)) if h == hash => Some((h, count + 1)),
+            Some((h, count)) => {
+                batch.insert(h.as_bytes(), count.to_be_bytes().to_vec());
+                Some((hash, 1))
+            }
+            None => Some((hash, 1)),
+        };
+    }
+    if let Some((h, count)) = current {
+        batch.insert(h.as_bytes(), count.to_be_bytes().to_vec());
+    }
+
+    refcounts_tree.apply_batch(batch)?;
+    Ok(())
+}
+
+/// Replace every count with `counts`, keeping the algorithms recorded.
+///
+/// Must not race with writers; it is meant for offline repair.
+pub fn replace_all(refcounts_tree: &Tree, counts: &HashMap<Hash, u64>) -> Result<(), Error> {
+    let mut batch = sled::Batch::default();
+    for item in refcounts_tree.iter().keys() {
+        let hash = item?;
+        if !Hash::from_slice(&hash).is_some_and(|h| counts.contains_key(&h)) {
+            batch.remove(hash);
+        }
+    }
+    for (hash, count) in counts {
+        let algorithm = refcounts_tree.get(hash)?.as_deref().and_then(decode_algorithm);
+        batch.insert(hash.as_bytes(), encode(*count, algorithm));
+    }
+    refcounts_tree.apply_batch(batch)?;
+    Ok(())
+}
+
+/// Record the algorithm of every referenced object for databases written
+/// before records held it. A no-op once done.
+pub fn ensure_algorithms_recorded(
+    meta_tree: &Tree,
+    keys_tree: &Tree,
+    versions_tree: &Tree,
+    trash_tree: &Tree,
+    refcounts_tree: &Tree,
+) -> Result<(), Error> {
+    if meta_tree.contains_key(ALGORITHMS_RECORDED)? {
+        return Ok(());
+    }
+    record_algorithms(keys_tree, versions_tree, trash_tree, refcounts_tree)?;
+    meta_tree.insert(ALGORITHMS_RECORDED, Vec::new())?;
+    Ok(())
+}
+
+/// Give every record lacking an algorithm the one a key, version or trash
+/// entry referencing the object was written with.
+///
+/// Must not race with writers; it is meant for startup and offline repair.
+pub fn record_algorithms(
+    keys_tree: &Tree,
+    versions_tree: &Tree,
+    trash_tree: &Tree,
+    refcounts_tree: &Tree,
+) -> Result<(), Error> {
+    let record = |meta: KeyMeta| -> Result<(), Error> {
+        if let Some(value) = refcounts_tree.get(meta.hash)? {
+            if decode_algorithm(&value).is_none() {
+                refcounts_tree.insert(meta.hash.as_bytes(), encode(decode_count(&value), Some(meta.hash_algorithm)))?;
+            }
+        }
+        Ok(())
+    };
+    for item in keys_tree.iter().values() {
+        record(KeyMeta::from_bytes(&item?)?)?;
+    }
+    for item in versions_tree.iter() {
+        let (tree_key, value) = item?;
+        if !versions::is_head_entry(&tree_key) {
+            record(KeyMeta::from_stamped_bytes(&value)?.1)?;
+        }
+    }
+    for item in trash_tree.iter().values() {
+        record(KeyMeta::from_stamped_bytes(&item?)?.1)?;
+    }
+    Ok(())
+}
+
+/// Count for `hash` outside a transaction.
+pub fn read(tree: &Tree, hash: &Hash) -> Result<u64, Error> {
+    Ok(tree.get(hash)?.map_or(0, |v| decode_count(&v)))
+}
+
+/// Algorithm recorded for `hash` outside a transaction, `None` if nothing
+/// references the object.
+pub fn read_algorithm(tree: &Tree, hash: &Hash) -> Result<Option<HashAlgorithm>, Error> {
+    Ok(tree.get(hash)?.as_deref().and_then(decode_algorithm))
+}
+
+/// Count held by a record.
+pub fn decode_count(bytes: &[u8]) -> u64 {
+    bytes.get(..8)
+        .and_then(|b| b.try_into().ok())
+        .map_or(0, u64::from_be_bytes)
+}
+
+fn decode_algorithm(bytes: &[u8]) -> Option<HashAlgorithm> {
+    bytes.get(8).copied().and_then(HashAlgorithm::from_id)
+}
+
+fn encode(count: u64, algorithm: Option<HashAlgorithm>) -> Vec<u8> {
+    let mut value = count.to_be_bytes().to_vec();
+    value.extend(algorithm.map(|a| a.id()));
+    value
+}
@@ -0,0 +1,79 @@
+//! Migration between content hash algorithms
+//!
+//! Every key records the algorithm its object was addressed with, so a
+//! database keeps working after `KV_HASH_ALGORITHM` changes: old keys are
+//! read under their old hash and new writes use the new one. A rehash pass
+//! moves the remaining keys over.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::storage::{dedup, KeyMeta, StorageDb, TransactionManager};
+use crate::util::compression::Compressor;
+use crate::util::hash::{Hash, HashAlgorithm};
+
+/// Outcome of a rehash pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RehashReport {
+    /// Keys examined
+    pub scanned: u64,
+    /// Keys moved to the target algorithm
+    pub rehashed: u64,
+    /// Keys changed or deleted by a concurrent write while being rehashed
+    pub skipped: u64,
+}
+
+/// Re-address every key not yet using `algorithm`.
+///
+/// Objects shared by several keys are hashed once. Each key is moved in its
+/// own transaction, so the pass can run alongside normal traffic; a key
+/// written meanwhile is skipped, since the write already used `algorithm`.
+pub fn rehash_keys(
+    db: &StorageDb,
+    compressor: &Compressor,
+    algorithm: HashAlgorithm,
+) -> Result<RehashReport, Error> {
+    let mut report = RehashReport::default();
+    let mut rehashed: HashMap<Hash, (Hash, HashAlgorithm)> = HashMap::new();
+
+    for item in db.keys_tree().iter() {
+        let (key, value) = item?;
+        report.scanned += 1;
+
+        let meta = KeyMeta::from_bytes(&value)?;
+        if meta.hash_algorithm == algorithm {
+            continue;
+        }
+        let Some(data) = db.objects_tree().get(meta.hash)? else {
+            report.skipped += 1;
+            continue;
+        };
+
+        let (new_hash, new_algorithm) = match rehashed.get(&meta.hash) {
+            Some(&target) => target,
+            None => {
+                let hash = dedup::hash_object(compressor, &data, algorithm)?;
+                let target = dedup::resolve_address(db, compressor, &data, hash, algorithm)?;
+                rehashed.insert(meta.hash, target);
+                target
+            }
+        };
+        // Kept on its current address, e.g. a collision already moved to SHA-256
+        if new_hash == meta.hash && new_algorithm == meta.hash_algorithm {
+            continue;
+        }
+
+        let key = String::from_utf8(key.to_vec())
+            .map_err(|e| Error::Internal(format!("Invalid key UTF-8: {}", e)))?;
+        let tx_manager = TransactionManager::new(db.clone()).with_hash_algorithm(new_algorithm);
+        if tx_manager.rehash_key(&key, &meta.hash, &new_hash, &data)? {
+            report.rehashed += 1;
+        } else {
+            report.skipped += 1;
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,168 @@
+//! Background re-verification of stored objects
+//!
+//! The scrubber walks the `objects` tree a slice at a time, decoding each
+//! object and re-hashing it against its address. Damaged objects are
+//! recorded in the `corrupt` tree, which reads consult so that a GET of a
+//! damaged value fails up front instead of streaming bad bytes. The walk
+//! position is kept in sled's default tree, so a restart resumes it.
+
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash3_64;
+
+use crate::error::Error;
+use crate::storage::{dedup, StorageDb};
+use crate::util::compression::{self, Compressor};
+use crate::util::hash::{Hash, HashAlgorithm};
+use crate::util::time::unix_now;
+
+/// Position of the current pass in sled's default tree
+const CURSOR: &[u8] = b"scrub_cursor";
+
+/// Small objects are charged at least this much against the byte budget, so
+/// a step over many tiny objects still ends promptly
+const MIN_OBJECT_COST: u64 = 4096;
+
+/// A damaged object, keyed by its hash in the `corrupt` tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorruptRecord {
+    /// Unix time the damage was found
+    pub detected_at: u64,
+    pub error: String,
+    /// Fingerprint of the damaged bytes. The record only applies while the
+    /// object still holds exactly these bytes, so it goes stale on its own
+    /// once the object is deleted and stored again.
+    pub fingerprint: u64,
+}
+
+/// Work done by one scrub step
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ScrubProgress {
+    pub objects: u64,
+    /// Stored bytes verified
+    pub bytes: u64,
+    /// Newly found corrupt objects
+    pub corrupt: u64,
+    /// Whether this step reached the end of the objects tree
+    pub pass_complete: bool,
+}
+
+/// Check that a stored object decodes and hashes to its address.
+///
+/// Every supported algorithm is tried, since keys written before a change
+/// of `KV_HASH_ALGORITHM` keep their old addresses.
+pub fn verify_object(compressor: &Compressor, data: &[u8], hash: &Hash) -> Result<(), Error> {
+    compression::verify(data)?;
+    for algorithm in [HashAlgorithm::Xxh3, HashAlgorithm::Sha256] {
+        if dedup::hash_object(compressor, data, algorithm)? == *hash {
+            return Ok(());
+        }
+    }
+    Err(Error::Corrupt("Contents do not match the object hash".to_string()))
+}
+
+/// Verify objects from where the last step stopped until about
+/// `byte_budget` stored bytes have been read.
+pub fn scrub_step(db: &StorageDb, compressor: &Compressor, byte_budget: u64) -> Result<ScrubProgress, Error> {
+    let mut progress = ScrubProgress::default();
+    let cursor = db.inner().get(CURSOR)?;
+    let lower = match &cursor {
+        Some(after) => Bound::Excluded(after.to_vec()),
+        None => Bound::Unbounded,
+    };
+
+    let mut spent = 0u64;
+    let mut last = None;
+    for item in db.objects_tree().range::<Vec<u8>, _>((lower, Bound::Unbounded)) {
+        let (hash_bytes, data) = item?;
+        progress.objects += 1;
+        progress.bytes += data.len() as u64;
+        spent += (data.len() as u64).max(MIN_OBJECT_COST);
+
+        match Hash::from_slice(&hash_bytes) {
+            Some(hash) => match verify_object(compressor, &data, &hash) {
+                Ok(()) => {
+                    db.corrupt_tree().remove(hash_bytes.as_ref())?;
+                }
+                Err(e) => {
+                    if record_corrupt(db, &hash, &data, &e.to_string())? {
+                        progress.corrupt += 1;
+                    }
+                }
+            },
+            None => tracing::warn!("Object with malformed address {}", hex::encode(&hash_bytes)),
+        }
+
+        last = Some(hash_bytes);
+        if spent >= byte_budget {
+            break;
+        }
+    }
+
+    match last {
+        Some(last) if spent >= byte_budget => {
+            db.inner().insert(CURSOR, last)?;
+        }
+        _ => {
+            progress.pass_complete = true;
+            db.inner().remove(CURSOR)?;
+            purge_stale(db)?;
+        }
+    }
+    Ok(progress)
+}
+
+/// Record `hash` as damaged. Returns `false` if it already was.
+pub fn record_corrupt(db: &StorageDb, hash: &Hash, data: &[u8], error: &str) -> Result<bool, Error> {
+    let fingerprint = XxHash3_64::oneshot(data);
+    if let Some(existing) = read_record(db, hash)? {
+        if existing.fingerprint == fingerprint {
+            return Ok(false);
+        }
+    }
+
+    tracing::error!("Corrupt object {}: {}", hash.to_hex_string(), error);
+    let record = CorruptRecord { detected_at: unix_now(), error: error.to_string(), fingerprint };
+    db.corrupt_tree().insert(hash, bincode::serialize(&record)?)?;
+    Ok(true)
+}
+
+/// Fail if `data`, the current bytes of object `hash`, are known to be damaged.
+pub fn check_known_corrupt(db: &StorageDb, hash: &Hash, data: &[u8]) -> Result<(), Error> {
+    // Nearly always empty, so this costs one lookup per read
+    let Some(record) = read_record(db, hash)? else {
+        return Ok(());
+    };
+    if record.fingerprint != XxHash3_64::oneshot(data) {
+        return Ok(());
+    }
+    Err(Error::Corrupt(format!(
+        "{} failed verification at {} ({}), refusing to serve it",
+        hash.to_hex_string(), record.detected_at, record.error
+    )))
+}
+
+/// Number of objects currently recorded as damaged.
+pub fn corrupt_count(db: &StorageDb) -> usize {
+    db.corrupt_tree().len()
+}
+
+fn read_record(db: &StorageDb, hash: &Hash) -> Result<Option<CorruptRecord>, Error> {
+    Ok(db.corrupt_tree().get(hash)?.map(|v| bincode::deserialize(&v)).transpose()?)
+}
+
+/// Drop records whose object is gone or no longer holds the damaged bytes.
+fn purge_stale(db: &StorageDb) -> Result<(), Error> {
+    for item in db.corrupt_tree().iter() {
+        let (hash, value) = item?;
+        let stale = match (db.objects_tree().get(&hash)?, bincode::deserialize::<CorruptRecord>(&value)) {
+            (Some(data), Ok(record)) => record.fingerprint != XxHash3_64::oneshot(&data),
+            _ => true,
+        };
+        if stale {
+            db.corrupt_tree().remove(hash)?;
+        }
+    }
+    Ok(())
+}
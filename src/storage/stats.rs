@@ -0,0 +1,115 @@
+//! Persistent storage counters
+//!
+//! Totals live in the `stats` tree and are updated inside the same sled
+//! transactions as the writes they describe, so reading them is O(1) and
+//! they survive restarts.
+
+use crate::error::Error;
+use crate::storage::KeyMeta;
+use serde::Serialize;
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use sled::Tree;
+
+const KEYS: &[u8] = b"keys";
+const OBJECTS: &[u8] = b"objects";
+const LOGICAL_BYTES: &[u8] = b"logical_bytes";
+const STORED_BYTES: &[u8] = b"stored_bytes";
+
+/// Set once the counters have been computed for an existing database
+const INITIALIZED: &[u8] = b"initialized";
+
+/// Current storage totals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StorageStats {
+    /// Entries in the keys tree, including expired keys not yet swept
+    pub keys: u64,
+    /// Unique objects after deduplication
+    pub objects: u64,
+    /// Sum of uncompressed value sizes over all keys
+    pub logical_bytes: u64,
+    /// Sum of stored (compressed) object sizes
+    pub stored_bytes: u64,
+}
+
+/// Signed change to the counters, applied as part of a write transaction
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatsDelta {
+    pub keys: i64,
+    pub objects: i64,
+    pub logical_bytes: i64,
+    pub stored_bytes: i64,
+}
+
+impl StatsDelta {
+    pub fn apply(&self, tree: &TransactionalTree) -> Result<(), UnabortableTransactionError> {
+        for (name, delta) in [
+            (KEYS, self.keys),
+            (OBJECTS, self.objects),
+            (LOGICAL_BYTES, self.logical_bytes),
+            (STORED_BYTES, self.stored_bytes),
+        ] {
+            if delta == 0 {
+                continue;
+            }
+            let current = tree.get(name)?.map_or(0, |v| decode(&v));
+            let updated = current.saturating_add_signed(delta);
+            tree.insert(name, updated.to_be_bytes().to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the current totals.
+pub fn read(tree: &Tree) -> Result<StorageStats, Error> {
+    let get = |name: &[u8]| -> Result<u64, Error> {
+        Ok(tree.get(name)?.map_or(0, |v| decode(&v)))
+    };
+
+    Ok(StorageStats {
+        keys: get(KEYS)?,
+        objects: get(OBJECTS)?,
+        logical_bytes: get(LOGICAL_BYTES)?,
+        stored_bytes: get(STORED_BYTES)?,
+    })
+}
+
+/// Compute the totals for databases created before the counters existed.
+/// A no-op once they have been initialised.
+pub fn ensure_initialized(keys_tree: &Tree, objects_tree: &Tree, stats_tree: &Tree) -> Result<StorageStats, Error> {
+    if stats_tree.contains_key(INITIALIZED)? {
+        return read(stats_tree);
+    }
+    recount(keys_tree, objects_tree, stats_tree)
+}
+
+/// Recompute all totals with a full scan and store them.
+///
+/// Must not race with writers; it is meant for startup and offline repair.
+pub fn recount(keys_tree: &Tree, objects_tree: &Tree, stats_tree: &Tree) -> Result<StorageStats, Error> {
+    let mut stats = StorageStats::default();
+
+    for item in keys_tree.iter() {
+        let (_, value) = item?;
+        stats.keys += 1;
+        stats.logical_bytes += KeyMeta::from_bytes(&value)?.size;
+    }
+    for item in objects_tree.iter() {
+        let (_, value) = item?;
+        stats.objects += 1;
+        stats.stored_bytes += value.len() as u64;
+    }
+
+    let mut batch = sled::Batch::default();
+    batch.insert(KEYS, stats.keys.to_be_bytes().to_vec());
+    batch.insert(OBJECTS, stats.objects.to_be_bytes().to_vec());
+    batch.insert(LOGICAL_BYTES, stats.logical_bytes.to_be_bytes().to_vec());
+    batch.insert(STORED_BYTES, stats.stored_bytes.to_be_bytes().to_vec());
+    batch.insert(INITIALIZED, Vec::new());
+    stats_tree.apply_batch(batch)?;
+
+    Ok(stats)
+}
+
+fn decode(bytes: &[u8]) -> u64 {
+    bytes.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
@@ -1,8 +1,9 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use super::super::*;
     use crate::util::compression::Compressor;
-    use crate::util::hash::Hash;
+    use crate::util::hash::{Hash, HashAlgorithm};
     use std::sync::Arc;
     use tempfile::TempDir;
 
@@ -199,14 +200,20 @@ mod tests {
         let size = data.len() as u64;
 
         // Atomic put
-        let is_new = tx_manager.put_key_atomic("test_key", &compressed, &hash, size).unwrap();
-        assert!(is_new);
+        let (old_hash, deduplicated) = tx_manager.update_key_if("test_key", &compressed, &hash, size, None, &Precondition::Absent).unwrap();
+        assert!(old_hash.is_none());
+        assert!(!deduplicated);
 
         // Verify key exists
         let keys_tree = db.keys_tree();
         let key_store = KeyStore::new(keys_tree);
         let meta = key_store.get("test_key").unwrap();
         assert!(meta.is_some());
+
+        // The same content under another key is reported as a dedup hit
+        let (old_hash, deduplicated) = tx_manager.update_key_if("other_key", &compressed, &hash, size, None, &Precondition::Absent).unwrap();
+        assert!(old_hash.is_none());
+        assert!(deduplicated);
     }
 
     #[test]
@@ -220,11 +227,11 @@ mod tests {
         let size = data.len() as u64;
 
         // First put
-        tx_manager.put_key_atomic("test_key", &compressed, &hash, size).unwrap();
+        tx_manager.update_key_if("test_key", &compressed, &hash, size, None, &Precondition::Absent).unwrap();
 
         // Second put with same key should fail
-        let result = tx_manager.put_key_atomic("test_key", &compressed, &hash, size);
-        assert!(result.is_err());
+        let result = tx_manager.update_key_if("test_key", &compressed, &hash, size, None, &Precondition::Absent);
+        assert!(matches!(result, Err(crate::error::Error::PreconditionFailed(_))));
     }
 
     #[test]
@@ -238,7 +245,7 @@ mod tests {
         let size = data.len() as u64;
 
         // Put key first
-        tx_manager.put_key_atomic("test_key", &compressed, &hash, size).unwrap();
+        tx_manager.update_key_if("test_key", &compressed, &hash, size, None, &Precondition::Absent).unwrap();
 
         // Delete atomically
         let result = tx_manager.delete_key_atomic("test_key").unwrap();
@@ -259,6 +266,1026 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_key_meta_legacy_layout() {
+        #[derive(serde::Serialize)]
+        struct PreTtlKeyMeta {
+            hash: Hash,
+            size: u64,
+            refs: u64,
+            created_at: u64,
+        }
+
+        let legacy = PreTtlKeyMeta { hash: Hash([7u8; 16]), size: 42, refs: 1, created_at: 1_700_000_000 };
+        let bytes = bincode::serialize(&legacy).unwrap();
+
+        let meta = KeyMeta::from_bytes(&bytes).unwrap();
+        assert_eq!(meta.hash, legacy.hash);
+        assert_eq!(meta.size, 42);
+        assert_eq!(meta.created_at, 1_700_000_000);
+        assert_eq!(meta.modified_at, 1_700_000_000);
+        assert!(meta.expires_at.is_none());
+    }
+
+    #[test]
+    fn test_key_meta_pre_modified_at_layout() {
+        #[derive(serde::Serialize)]
+        struct PreModifiedKeyMeta {
+            hash: Hash,
+            size: u64,
+            refs: u64,
+            created_at: u64,
+            expires_at: Option<u64>,
+        }
+
+        for expires_at in [None, Some(1_800_000_000)] {
+            let old = PreModifiedKeyMeta { hash: Hash([8u8; 16]), size: 7, refs: 1, created_at: 1_700_000_000, expires_at };
+            let meta = KeyMeta::from_bytes(&bincode::serialize(&old).unwrap()).unwrap();
+            assert_eq!(meta.hash, old.hash);
+            assert_eq!(meta.expires_at, expires_at);
+            assert_eq!(meta.modified_at, 1_700_000_000);
+        }
+    }
+
+    #[test]
+    fn test_key_meta_pre_hash_algorithm_layout() {
+        #[derive(serde::Serialize)]
+        struct PreAlgorithmKeyMeta {
+            hash: Hash,
+            size: u64,
+            refs: u64,
+            created_at: u64,
+            expires_at: Option<u64>,
+            modified_at: u64,
+        }
+
+        let old = PreAlgorithmKeyMeta { hash: Hash([9u8; 16]), size: 7, refs: 1, created_at: 1, expires_at: None, modified_at: 2 };
+        let meta = KeyMeta::from_bytes(&bincode::serialize(&old).unwrap()).unwrap();
+        assert_eq!((meta.hash, meta.modified_at), (old.hash, 2));
+        assert_eq!(meta.hash_algorithm, HashAlgorithm::Xxh3);
+
+        let current = KeyMeta::new(Hash([9u8; 16]), 7).with_hash_algorithm(HashAlgorithm::Sha256);
+        let decoded = KeyMeta::from_bytes(&current.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.hash_algorithm, HashAlgorithm::Sha256);
+    }
+
+    #[test]
+    fn test_update_preserves_created_at() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let key_store = KeyStore::new(db.keys_tree());
+
+        let mut meta = KeyMeta::new(Hash([9u8; 16]), 1);
+        meta.created_at = 1_000;
+        meta.modified_at = 1_000;
+        key_store.set("stamped", &meta).unwrap();
+
+        tx_manager.update_key_atomic("stamped", b"b", &Hash([10u8; 16]), 1, None).unwrap();
+        let updated = key_store.get("stamped").unwrap().unwrap();
+        assert_eq!(updated.created_at, 1_000);
+        assert!(updated.modified_at > 1_000);
+    }
+
+    #[test]
+    fn test_key_meta_expiry() {
+        let meta = KeyMeta::new(Hash([1u8; 16]), 10).with_expires_at(Some(100));
+        assert!(!meta.is_expired(99));
+        assert!(meta.is_expired(100));
+        assert_eq!(meta.ttl_remaining(40), Some(60));
+        assert_eq!(meta.ttl_remaining(200), Some(0));
+
+        let bytes = meta.to_bytes().unwrap();
+        assert_eq!(KeyMeta::from_bytes(&bytes).unwrap().expires_at, Some(100));
+    }
+
+    #[test]
+    fn test_sweep_expired_keys() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+
+        let expiring = Hash([1u8; 16]);
+        let durable = Hash([2u8; 16]);
+        tx_manager.update_key_if("expiring", b"a", &expiring, 1, Some(100), &Precondition::Absent).unwrap();
+        tx_manager.update_key_if("later", b"b", &durable, 1, Some(500), &Precondition::Absent).unwrap();
+        tx_manager.update_key_if("forever", b"b", &durable, 1, None, &Precondition::Absent).unwrap();
+        assert_eq!(db.expiry_tree().len(), 2);
+
+        let removed = expiry::sweep_expired(&db, 200, 100).unwrap();
+        assert_eq!(removed, 1);
+
+        let key_store = KeyStore::new(db.keys_tree());
+        assert!(!key_store.exists("expiring").unwrap());
+        assert!(key_store.exists("later").unwrap());
+        assert!(key_store.exists("forever").unwrap());
+        assert_eq!(db.expiry_tree().len(), 1);
+
+        // Object only referenced by the expired key is garbage-collected
+        assert!(!db.objects_tree().contains_key(expiring).unwrap());
+        assert!(db.objects_tree().contains_key(durable).unwrap());
+    }
+
+    #[test]
+    fn test_update_clears_expiry() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let hash = Hash([3u8; 16]);
+
+        tx_manager.update_key_atomic("refreshed", b"a", &hash, 1, Some(100)).unwrap();
+        tx_manager.update_key_atomic("refreshed", b"a", &hash, 1, None).unwrap();
+        assert!(db.expiry_tree().is_empty());
+
+        assert!(tx_manager.delete_expired_key_atomic("refreshed", 200).unwrap().is_none());
+        assert!(KeyStore::new(db.keys_tree()).exists("refreshed").unwrap());
+    }
+
+    #[test]
+    fn test_conditional_update_and_delete() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let first = Hash([4u8; 16]);
+        let second = Hash([5u8; 16]);
+
+        // Create-only succeeds once
+        tx_manager.update_key_if("cond", b"a", &first, 1, None, &Precondition::Absent).unwrap();
+        let err = tx_manager.update_key_if("cond", b"a", &first, 1, None, &Precondition::Absent).unwrap_err();
+        assert!(matches!(err, crate::error::Error::PreconditionFailed(_)));
+
+        // Compare-and-swap against a stale hash fails, current hash succeeds
+        let stale = Precondition::HashMatches(vec![second]);
+        assert!(tx_manager.update_key_if("cond", b"b", &second, 1, None, &stale).is_err());
+        let current = Precondition::HashMatches(vec![first]);
+        tx_manager.update_key_if("cond", b"b", &second, 1, None, &current).unwrap();
+        assert_eq!(KeyStore::new(db.keys_tree()).get("cond").unwrap().unwrap().hash, second);
+
+        // Conditional delete only removes the expected version
+        assert!(tx_manager.delete_key_if("cond", &current).is_err());
+        assert!(tx_manager.delete_key_if("cond", &Precondition::HashDiffers(vec![second])).is_err());
+        tx_manager.delete_key_if("cond", &Precondition::HashMatches(vec![second])).unwrap();
+        assert!(!KeyStore::new(db.keys_tree()).exists("cond").unwrap());
+    }
+
+    #[test]
+    fn test_precondition_treats_expired_as_absent() {
+        let meta = KeyMeta::new(Hash([6u8; 16]), 1).with_expires_at(Some(100));
+
+        assert!(Precondition::Absent.check("k", Some(&meta), 99).is_err());
+        assert!(Precondition::Absent.check("k", Some(&meta), 100).is_ok());
+        assert!(Precondition::Present.check("k", Some(&meta), 100).is_err());
+        assert!(Precondition::HashMatches(vec![meta.hash]).check("k", Some(&meta), 100).is_err());
+    }
+
+    #[test]
+    fn test_stats_track_writes() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let first = Hash([7u8; 16]);
+        let second = Hash([8u8; 16]);
+
+        tx_manager.update_key_if("a", b"xxxx", &first, 10, None, &Precondition::Absent).unwrap();
+        // Dedup hit adds a key but no object
+        tx_manager.update_key_if("b", b"xxxx", &first, 10, None, &Precondition::Absent).unwrap();
+        assert_eq!(db.stats().unwrap(), StorageStats { keys: 2, objects: 1, logical_bytes: 20, stored_bytes: 4 });
+
+        // Overwrite replaces the logical size and adds the new object
+        tx_manager.update_key_atomic("a", b"yy", &second, 5, None).unwrap();
+        assert_eq!(db.stats().unwrap(), StorageStats { keys: 2, objects: 2, logical_bytes: 15, stored_bytes: 6 });
+
+        // Deleting the last ref removes the object from the totals
+        tx_manager.delete_key_atomic("b").unwrap();
+        assert_eq!(db.stats().unwrap(), StorageStats { keys: 1, objects: 1, logical_bytes: 5, stored_bytes: 2 });
+    }
+
+    #[test]
+    fn test_stats_initialized_for_existing_db() {
+        let (_temp, db) = setup_test_db();
+        TransactionManager::new(db.clone())
+            .update_key_if("a", b"xxxx", &Hash([9u8; 16]), 10, None, &Precondition::Absent)
+            .unwrap();
+
+        // Simulate a database written before the counters existed
+        db.stats_tree().clear().unwrap();
+        let counted = stats::ensure_initialized(db.keys_tree(), db.objects_tree(), db.stats_tree()).unwrap();
+        assert_eq!(counted, StorageStats { keys: 1, objects: 1, logical_bytes: 10, stored_bytes: 4 });
+        assert_eq!(db.stats().unwrap(), counted);
+
+        // Once initialised, counts are not recomputed
+        db.stats_tree().insert("keys", 5u64.to_be_bytes().to_vec()).unwrap();
+        let stats = stats::ensure_initialized(db.keys_tree(), db.objects_tree(), db.stats_tree()).unwrap();
+        assert_eq!(stats.keys, 5);
+    }
+
+    #[test]
+    fn test_overwrite_collects_orphaned_object() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let old = Hash([10u8; 16]);
+        let shared = Hash([11u8; 16]);
+        let new = Hash([12u8; 16]);
+
+        tx_manager.update_key_atomic("a", b"old", &old, 3, None).unwrap();
+        tx_manager.update_key_atomic("b", b"shared", &shared, 6, None).unwrap();
+        tx_manager.update_key_atomic("c", b"shared", &shared, 6, None).unwrap();
+
+        // Sole reference replaced: object is removed
+        tx_manager.update_key_atomic("a", b"new", &new, 3, None).unwrap();
+        assert!(!db.objects_tree().contains_key(old).unwrap());
+
+        // Another key still points at it: object is kept
+        tx_manager.update_key_atomic("b", b"new", &new, 3, None).unwrap();
+        assert!(db.objects_tree().contains_key(shared).unwrap());
+
+        // Rewriting the same value keeps the object
+        tx_manager.update_key_atomic("a", b"new", &new, 3, None).unwrap();
+        assert!(db.objects_tree().contains_key(new).unwrap());
+        assert_eq!(db.stats().unwrap().objects, 2);
+    }
+
+    #[test]
+    fn test_sweep_orphans() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let live = Hash([13u8; 16]);
+        let orphan = Hash([14u8; 16]);
+
+        tx_manager.update_key_if("live", b"live", &live, 4, None, &Precondition::Absent).unwrap();
+        // Leaked by an older version that did not GC on overwrite
+        db.objects_tree().insert(orphan, b"orphan".to_vec()).unwrap();
+
+        let report = gc::sweep_orphans(&db).unwrap();
+        assert_eq!(report, gc::GcReport { scanned: 2, reclaimed_objects: 1, reclaimed_bytes: 6 });
+        assert!(!db.objects_tree().contains_key(orphan).unwrap());
+        assert!(db.objects_tree().contains_key(live).unwrap());
+
+        assert_eq!(gc::sweep_orphans(&db).unwrap().reclaimed_objects, 0);
+    }
+
+    #[test]
+    fn test_refcounts_track_shared_objects() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let shared = Hash([15u8; 16]);
+        let other = Hash([16u8; 16]);
+        let count = |hash: &Hash| refcounts::read(db.refcounts_tree(), hash).unwrap();
+
+        tx_manager.update_key_if("a", b"shared", &shared, 6, None, &Precondition::Absent).unwrap();
+        tx_manager.update_key_atomic("b", b"shared", &shared, 6, None).unwrap();
+        tx_manager.update_key_atomic("b", b"shared", &shared, 6, None).unwrap();
+        assert_eq!(count(&shared), 2);
+
+        tx_manager.update_key_atomic("a", b"other", &other, 5, None).unwrap();
+        assert_eq!((count(&shared), count(&other)), (1, 1));
+
+        tx_manager.delete_key_atomic("b").unwrap();
+        assert_eq!(count(&shared), 0);
+        assert!(db.refcounts_tree().get(shared).unwrap().is_none());
+        assert!(!db.objects_tree().contains_key(shared).unwrap());
+    }
+
+    #[test]
+    fn test_refcounts_rebuilt_for_existing_db() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let hash = Hash([17u8; 16]);
+        tx_manager.update_key_if("a", b"v", &hash, 1, None, &Precondition::Absent).unwrap();
+        tx_manager.update_key_if("b", b"v", &hash, 1, None, &Precondition::Absent).unwrap();
+
+        // Simulate a database written before reference counts existed
+        db.refcounts_tree().clear().unwrap();
+        db.inner().remove("refcounts_initialized").unwrap();
+
+        refcounts::ensure_initialized(db.inner(), db.refs_tree(), db.refcounts_tree()).unwrap();
+        assert_eq!(refcounts::read(db.refcounts_tree(), &hash).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_put_delete_same_content() {
+        let (_temp, db) = setup_test_db();
+        let hash = Hash([18u8; 16]);
+        let threads = 8;
+        let rounds = 200;
+
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    let tx_manager = TransactionManager::new(db.clone());
+                    for i in 0..rounds {
+                        let key = format!("stress/{}/{}", t, i % 4);
+                        tx_manager.update_key_atomic(&key, b"same", &hash, 4, None).unwrap();
+                        // Our key holds a reference, so no other delete may drop the object
+                        assert!(db.objects_tree().contains_key(hash).unwrap());
+                        if i % 3 != 0 {
+                            tx_manager.delete_key_atomic(&key).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let live = db.keys_tree().len() as u64;
+        assert!(live > 0);
+        assert_eq!(refcounts::read(db.refcounts_tree(), &hash).unwrap(), live);
+        assert_eq!(db.refs_tree().scan_prefix(hash).count() as u64, live);
+        assert!(db.objects_tree().contains_key(hash).unwrap());
+        assert_eq!(db.stats().unwrap().objects, 1);
+
+        for item in db.keys_tree().iter().keys() {
+            let key = String::from_utf8(item.unwrap().to_vec()).unwrap();
+            TransactionManager::new(db.clone()).delete_key_atomic(&key).unwrap();
+        }
+        assert!(db.objects_tree().is_empty());
+        assert!(db.refcounts_tree().is_empty());
+        assert_eq!(db.stats().unwrap(), StorageStats::default());
+    }
+
+    #[test]
+    fn test_upgrade_legacy_objects() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let data = b"stored before the object header existed ".repeat(50);
+        let legacy = zstd::stream::encode_all(&data[..], 1).unwrap();
+        let hash = Hash::compute(&data);
+        TransactionManager::new(db.clone())
+            .update_key_if("legacy", &legacy, &hash, data.len() as u64, None, &Precondition::Absent)
+            .unwrap();
+        db.inner().remove("objects_upgraded").unwrap();
+
+        assert_eq!(objects::upgrade_legacy_objects(db.inner(), db.objects_tree()).unwrap(), 1);
+        let stored = db.objects_tree().get(hash).unwrap().unwrap();
+        assert!(crate::util::compression::verify(&stored).unwrap().is_some());
+        assert_eq!(compressor.decompress(&stored).unwrap(), data);
+
+        // Runs once
+        assert_eq!(objects::upgrade_legacy_objects(db.inner(), db.objects_tree()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_dedup_collision_falls_back_to_sha256() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let data = b"value whose fast hash is already taken".to_vec();
+        let encoded = compressor.compress(&data).unwrap();
+        let hash = HashAlgorithm::Xxh3.compute(&data);
+
+        // A free address, or an identical object, keeps the fast hash
+        let resolved = dedup::resolve_address(&db, &compressor, &encoded, hash, HashAlgorithm::Xxh3).unwrap();
+        assert_eq!(resolved, (hash, HashAlgorithm::Xxh3));
+
+        // Simulate a colliding object stored under the same hash
+        let other = compressor.compress(b"different bytes, same hash").unwrap();
+        TransactionManager::new(db.clone())
+            .update_key_if("victim", &other, &hash, 26, None, &Precondition::Absent)
+            .unwrap();
+        let (safe, algorithm) = dedup::resolve_address(&db, &compressor, &encoded, hash, HashAlgorithm::Xxh3).unwrap();
+        assert_eq!(algorithm, HashAlgorithm::Sha256);
+        assert_eq!(safe, HashAlgorithm::Sha256.compute(&data));
+
+        // A collision on the SHA-256 address has nowhere to go
+        TransactionManager::new(db.clone())
+            .update_key_if("sha-victim", &other, &safe, 26, None, &Precondition::Absent)
+            .unwrap();
+        let refused = dedup::resolve_address(&db, &compressor, &encoded, hash, HashAlgorithm::Xxh3);
+        assert!(matches!(refused, Err(crate::error::Error::Conflict(_))));
+    }
+
+    #[test]
+    fn test_dedup_verified_inside_transaction() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Arc::new(Compressor::new(1));
+        let data = b"value written after the dedup check".to_vec();
+        let encoded = compressor.compress(&data).unwrap();
+        let hash = HashAlgorithm::Xxh3.compute(&data);
+        let resolved = dedup::resolve_address(&db, &compressor, &encoded, hash, HashAlgorithm::Xxh3).unwrap();
+        assert_eq!(resolved, (hash, HashAlgorithm::Xxh3));
+
+        // A colliding object lands between the check and the write
+        let other = compressor.compress(b"different bytes, same hash").unwrap();
+        TransactionManager::new(db.clone())
+            .update_key_if("first", &other, &hash, 26, None, &Precondition::Absent)
+            .unwrap();
+
+        let tx_manager = TransactionManager::new(db.clone()).with_dedup_verify(Some(compressor.clone()));
+        let err = tx_manager.update_key_if("second", &encoded, &hash, data.len() as u64, None, &Precondition::Unconditional).unwrap_err();
+        assert!(matches!(err, crate::error::Error::Conflict(_)));
+        assert!(db.keys_tree().get("second").unwrap().is_none());
+
+        // The same value still deduplicates
+        tx_manager.update_key_if("copy", &other, &hash, 26, None, &Precondition::Unconditional).unwrap();
+        assert_eq!(db.ref_count(&hash).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_rehash_keys() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let data = b"shared value moved to another hash".to_vec();
+        let encoded = compressor.compress(&data).unwrap();
+        let old_hash = HashAlgorithm::Xxh3.compute(&data);
+        let tx_manager = TransactionManager::new(db.clone());
+        tx_manager.update_key_if("a", &encoded, &old_hash, data.len() as u64, Some(u64::MAX), &Precondition::Absent).unwrap();
+        tx_manager.update_key_if("b", &encoded, &old_hash, data.len() as u64, None, &Precondition::Absent).unwrap();
+        let before = KeyStore::new(db.keys_tree()).get("a").unwrap().unwrap();
+
+        let report = rehash::rehash_keys(&db, &compressor, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(report, rehash::RehashReport { scanned: 2, rehashed: 2, skipped: 0 });
+
+        let new_hash = HashAlgorithm::Sha256.compute(&data);
+        let after = KeyStore::new(db.keys_tree()).get("a").unwrap().unwrap();
+        assert_eq!(after.hash, new_hash);
+        assert_eq!(after.hash_algorithm, HashAlgorithm::Sha256);
+        assert_eq!(after.created_at, before.created_at);
+        assert_eq!(after.expires_at, Some(u64::MAX));
+        assert!(!db.objects_tree().contains_key(old_hash).unwrap());
+        assert_eq!(refcounts::read(db.refcounts_tree(), &new_hash).unwrap(), 2);
+        assert_eq!(db.stats().unwrap().objects, 1);
+
+        // Nothing left to move
+        let report = rehash::rehash_keys(&db, &compressor, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(report.rehashed, 0);
+    }
+
+    #[test]
+    fn test_fsck_clean_database() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        for (key, value) in [("a", "shared"), ("b", "shared"), ("c", "other")] {
+            let data = value.as_bytes();
+            tx_manager
+                .update_key_if(key, &compressor.compress(data).unwrap(), &Hash::compute(data), data.len() as u64, None, &Precondition::Absent)
+                .unwrap();
+        }
+
+        let report = fsck::check(&db, &compressor, false).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        assert_eq!((report.keys_checked, report.refs_checked, report.objects_checked), (3, 3, 2));
+        assert!(!report.repaired);
+    }
+
+    #[test]
+    fn test_fsck_detects_and_repairs() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let put = |key: &str, data: &[u8]| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_if(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None, &Precondition::Absent)
+                .unwrap();
+            hash
+        };
+        put("healthy", b"healthy value");
+        let lost = put("lost", b"object removed behind our back");
+        let damaged = put("damaged", b"object overwritten with other bytes");
+        let unindexed = put("unindexed", b"ref entry removed");
+
+        db.objects_tree().remove(lost).unwrap();
+        db.objects_tree().insert(damaged, compressor.compress(b"not the original").unwrap()).unwrap();
+        db.refs_tree().remove([unindexed.as_bytes(), b"unindexed"].concat()).unwrap();
+        db.refs_tree().insert([unindexed.as_bytes(), b"ghost"].concat(), b"1").unwrap();
+        let orphan = Hash::compute(b"orphan");
+        db.objects_tree().insert(orphan, compressor.compress(b"orphan").unwrap()).unwrap();
+        db.keys_tree().insert("garbage", b"not key metadata".to_vec()).unwrap();
+
+        let report = fsck::check(&db, &compressor, false).unwrap();
+        assert_eq!(report.missing_objects.examples, ["lost"]);
+        assert_eq!(report.damaged_keys.examples, ["damaged"]);
+        assert_eq!(report.corrupt_objects.count, 1);
+        assert_eq!(report.missing_refs.examples, ["unindexed"]);
+        assert_eq!(report.dangling_refs.examples[0].key, "ghost");
+        assert_eq!(report.orphan_objects.examples, [orphan.to_hex_string()]);
+        assert_eq!(report.unreadable_keys.examples, ["garbage"]);
+        assert!(report.stats_mismatch.is_some());
+        assert!(!report.repaired);
+
+        let report = fsck::check(&db, &compressor, true).unwrap();
+        assert!(report.repaired);
+
+        let report = fsck::check(&db, &compressor, false).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+        let keys: Vec<_> = db.keys_tree().iter().keys().map(|k| k.unwrap().to_vec()).collect();
+        assert_eq!(keys, [b"healthy".to_vec(), b"unindexed".to_vec()]);
+        assert_eq!(db.stats().unwrap().objects, 2);
+        assert_eq!(refcounts::read(db.refcounts_tree(), &unindexed).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_writes_refused_during_repair() {
+        let (_temp, db) = setup_test_db();
+        let hash = Hash::compute(b"value");
+        let guard = db.exclusive_access();
+        let result = TransactionManager::new(db.clone()).update_key_if("key", b"value", &hash, 5, None, &Precondition::Absent);
+        assert!(matches!(result, Err(crate::error::Error::Unavailable(_))));
+        drop(guard);
+        assert!(TransactionManager::new(db.clone()).update_key_if("key", b"value", &hash, 5, None, &Precondition::Absent).is_ok());
+    }
+
+    #[test]
+    fn test_ref_count_and_scan_refs() {
+        let (_temp, db) = setup_test_db();
+        let tx_manager = TransactionManager::new(db.clone());
+        let shared = Hash::compute(b"shared");
+        let other = Hash::compute(b"other");
+        for key in ["c", "a", "b"] {
+            tx_manager.update_key_if(key, b"shared", &shared, 6, None, &Precondition::Absent).unwrap();
+        }
+        tx_manager.update_key_if("d", b"other", &other, 5, None, &Precondition::Absent).unwrap();
+
+        assert_eq!(db.ref_count(&shared).unwrap(), 3);
+        assert_eq!(db.ref_count(&other).unwrap(), 1);
+        assert_eq!(db.ref_count(&Hash::compute(b"missing")).unwrap(), 0);
+
+        let keys = |after: Option<&[u8]>| -> Vec<Vec<u8>> {
+            db.scan_refs(&shared, after).map(|k| k.unwrap().to_vec()).collect()
+        };
+        assert_eq!(keys(None), [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(Some(b"a")), [b"b".to_vec(), b"c".to_vec()]);
+        assert!(keys(Some(b"c")).is_empty());
+
+        tx_manager.delete_key_atomic("b").unwrap();
+        assert_eq!(db.ref_count(&shared).unwrap(), 2);
+        assert_eq!(keys(None), [b"a".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_link_key_to_existing_object() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let data = b"published artifact";
+        let hash = Hash::compute(data);
+        tx_manager.update_key_if("v1", &compressor.compress(data).unwrap(), &hash, data.len() as u64, None, &Precondition::Absent).unwrap();
+        let before = db.stats().unwrap();
+
+        let (old_hash, size) = tx_manager.link_key_if("v2", &hash, None, &Precondition::Unconditional).unwrap();
+        assert_eq!((old_hash, size), (None, data.len() as u64));
+        let meta = KeyMeta::from_bytes(&db.keys_tree().get("v2").unwrap().unwrap()).unwrap();
+        assert_eq!((meta.hash, meta.size), (hash, data.len() as u64));
+        assert_eq!(db.ref_count(&hash).unwrap(), 2);
+
+        let after = db.stats().unwrap();
+        assert_eq!(after.keys, before.keys + 1);
+        assert_eq!(after.objects, before.objects);
+        assert_eq!(after.logical_bytes, before.logical_bytes + data.len() as u64);
+
+        // Preconditions apply as for a PUT
+        let result = tx_manager.link_key_if("v2", &hash, None, &Precondition::Absent);
+        assert!(matches!(result, Err(crate::error::Error::PreconditionFailed(_))));
+
+        let unknown = Hash::compute(b"never stored");
+        let result = tx_manager.link_key_if("v3", &unknown, None, &Precondition::Unconditional);
+        assert!(matches!(result, Err(crate::error::Error::NotFound(_))));
+        assert!(db.keys_tree().get("v3").unwrap().is_none());
+        assert_eq!(db.ref_count(&unknown).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_versioning_history_restore_and_retention() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let policy = Arc::new(versions::VersionPolicy {
+            prefixes: vec!["cfg/".to_string()],
+            max_versions: 2,
+            max_age_secs: 0,
+        });
+        let tx_manager = TransactionManager::new(db.clone()).with_versioning(policy.clone());
+        let put = |key: &str, data: &[u8]| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hash
+        };
+
+        let v1 = put("cfg/app", b"one");
+        let v2 = put("cfg/app", b"two");
+        let v3 = put("cfg/app", b"three");
+        put("cfg/app", b"four");
+        assert_eq!(versions::current_version(&db, "cfg/app").unwrap(), 4);
+
+        // Only the two newest archived versions are kept
+        let history = versions::list(&db, "cfg/app").unwrap();
+        let kept: Vec<_> = history.iter().map(|v| (v.version, v.meta.hash)).collect();
+        assert_eq!(kept, vec![(2, v2), (3, v3)]);
+        assert!(db.objects_tree().get(v1).unwrap().is_none());
+        assert_eq!(db.ref_count(&v2).unwrap(), 1);
+
+        // Keys outside the prefix keep no history
+        put("other", b"one");
+        put("other", b"two");
+        assert_eq!(versions::current_version(&db, "other").unwrap(), 1);
+
+        // Restoring archives the live value, and retention drops the restored
+        // version without losing the object the key now points at
+        let (hash, version) = versions::restore(&db, &policy, "cfg/app", 2).unwrap();
+        assert_eq!((hash, version), (v2, 5));
+        let live = KeyMeta::from_bytes(&db.keys_tree().get("cfg/app").unwrap().unwrap()).unwrap();
+        assert_eq!(live.hash, v2);
+        assert!(db.objects_tree().contains_key(v2).unwrap());
+        assert!(versions::get(&db, "cfg/app", 2).unwrap().is_none());
+        assert!(matches!(versions::restore(&db, &policy, "cfg/app", 1), Err(crate::error::Error::NotFound(_))));
+
+        // History survives a delete of the key
+        tx_manager.delete_key_atomic("cfg/app").unwrap();
+        assert_eq!(versions::list(&db, "cfg/app").unwrap().len(), 2);
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+
+        let age_limit = versions::VersionPolicy { max_age_secs: 60, ..(*policy).clone() };
+        let report = versions::prune(&db, &age_limit, crate::util::time::unix_now() + 61).unwrap();
+        assert_eq!(report.removed, 2);
+        assert!(versions::list(&db, "cfg/app").unwrap().is_empty());
+        assert_eq!(db.stats().unwrap().keys, 1);
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_stored_algorithm_sees_versions_and_trash() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let policy = Arc::new(versions::VersionPolicy { prefixes: vec!["v/".to_string()], ..Default::default() });
+        let tx_manager = TransactionManager::new(db.clone())
+            .with_hash_algorithm(HashAlgorithm::Sha256)
+            .with_versioning(policy)
+            .with_trash(true);
+        let put = |key: &str, data: &[u8]| {
+            let hash = HashAlgorithm::Sha256.compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hash
+        };
+
+        let deleted = put("gone", b"deleted value");
+        tx_manager.delete_key_atomic("gone").unwrap();
+        let archived = put("v/doc", b"old value");
+        put("v/doc", b"new value");
+
+        assert_eq!(dedup::stored_algorithm(&db, &deleted).unwrap(), Some(HashAlgorithm::Sha256));
+        assert_eq!(dedup::stored_algorithm(&db, &archived).unwrap(), Some(HashAlgorithm::Sha256));
+        assert_eq!(dedup::stored_algorithm(&db, &Hash([3u8; 16])).unwrap(), None);
+
+        // Records from before the algorithm was kept are filled in
+        for hash in [deleted, archived] {
+            let count = refcounts::read(db.refcounts_tree(), &hash).unwrap();
+            db.refcounts_tree().insert(hash, count.to_be_bytes().to_vec()).unwrap();
+            assert_eq!(dedup::stored_algorithm(&db, &hash).unwrap(), None);
+        }
+        refcounts::record_algorithms(db.keys_tree(), db.versions_tree(), db.trash_tree(), db.refcounts_tree()).unwrap();
+        assert_eq!(dedup::stored_algorithm(&db, &deleted).unwrap(), Some(HashAlgorithm::Sha256));
+        assert_eq!(dedup::stored_algorithm(&db, &archived).unwrap(), Some(HashAlgorithm::Sha256));
+        assert_eq!(refcounts::read(db.refcounts_tree(), &archived).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_live_ref_count_leaves_out_versions_and_trash() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let policy = Arc::new(versions::VersionPolicy { prefixes: vec!["v/".to_string()], ..Default::default() });
+        let tx_manager = TransactionManager::new(db.clone()).with_versioning(policy).with_trash(true);
+        let put = |key: &str, data: &[u8]| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hash
+        };
+
+        // The old value of a versioned key and a trashed key both hold the object
+        let shared = put("v/doc", b"shared");
+        put("v/doc", b"newer");
+        put("gone", b"shared");
+        tx_manager.delete_key_atomic("gone").unwrap();
+        put("sibling", b"shared");
+
+        assert_eq!(db.ref_count(&shared).unwrap(), 3);
+        assert_eq!(db.live_ref_count(&shared).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_trash_delete_restore_and_purge() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone()).with_trash(true);
+        let put = |key: &str, data: &[u8]| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hash
+        };
+
+        let first = put("doc", b"first");
+        tx_manager.delete_key_atomic("doc").unwrap();
+        assert!(db.keys_tree().get("doc").unwrap().is_none());
+        assert_eq!(db.stats().unwrap().keys, 0);
+        // The tombstone keeps the object alive
+        assert_eq!(db.ref_count(&first).unwrap(), 1);
+        let entries = trash::list(&db, "", None, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].key.as_str(), entries[0].meta.hash), ("doc", first));
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+
+        // Deleting the key again replaces its tombstone
+        let second = put("doc", b"second");
+        tx_manager.delete_key_atomic("doc").unwrap();
+        assert!(!db.objects_tree().contains_key(first).unwrap());
+        assert_eq!(trash::list(&db, "", None, 10).unwrap()[0].meta.hash, second);
+
+        // A key written under the same name blocks a restore
+        put("doc", b"third");
+        assert!(matches!(tx_manager.restore_deleted("doc"), Err(crate::error::Error::Conflict(_))));
+        TransactionManager::new(db.clone()).delete_key_atomic("doc").unwrap();
+
+        let meta = tx_manager.restore_deleted("doc").unwrap();
+        assert_eq!(meta.hash, second);
+        assert_eq!(db.stats().unwrap().keys, 1);
+        assert!(trash::list(&db, "", None, 10).unwrap().is_empty());
+        assert!(matches!(tx_manager.restore_deleted("doc"), Err(crate::error::Error::NotFound(_))));
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+
+        // Purge only drops entries past the retention period
+        tx_manager.delete_key_atomic("doc").unwrap();
+        let now = crate::util::time::unix_now();
+        assert_eq!(trash::purge(&db, 60, now).unwrap().purged, 0);
+        let report = trash::purge(&db, 60, now + 61).unwrap();
+        assert_eq!((report.purged, report.freed_objects), (1, 1));
+        assert!(!db.objects_tree().contains_key(second).unwrap());
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_trash_list_pages() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone()).with_trash(true);
+        for key in ["a/1", "a/2", "a/3", "b/1"] {
+            let hash = Hash::compute(key.as_bytes());
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(key.as_bytes()).unwrap(), &hash, key.len() as u64, None)
+                .unwrap();
+            tx_manager.delete_key_atomic(key).unwrap();
+        }
+
+        let keys = |start_after: Option<&str>, limit: usize| -> Vec<String> {
+            trash::list(&db, "a/", start_after, limit).unwrap().into_iter().map(|e| e.key).collect()
+        };
+        assert_eq!(keys(None, 2), ["a/1", "a/2"]);
+        assert_eq!(keys(Some("a/2"), 2), ["a/3"]);
+        assert!(keys(Some("a/3"), 2).is_empty());
+        // A cursor before the prefix starts at the prefix
+        assert_eq!(keys(Some("0"), 10), ["a/1", "a/2", "a/3"]);
+    }
+
+    #[test]
+    fn test_apply_batch_all_or_nothing() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let put = |key: &str, data: &[u8], precondition: Precondition| {
+            let hash = Hash::compute(data);
+            BatchWrite::Put {
+                key: key.to_string(),
+                object: compressor.compress(data).unwrap(),
+                hash,
+                size: data.len() as u64,
+                algorithm: HashAlgorithm::default(),
+                expires_at: None,
+                precondition,
+            }
+        };
+        let one = Hash::compute(b"one");
+
+        // Later writes see earlier ones, including their preconditions
+        let previous = tx_manager.apply_batch(&[
+            put("a", b"one", Precondition::Absent),
+            put("b", b"two", Precondition::Unconditional),
+            BatchWrite::Link {
+                key: "c".to_string(),
+                hash: one,
+                algorithm: HashAlgorithm::default(),
+                expires_at: None,
+                precondition: Precondition::Absent,
+            },
+            BatchWrite::Delete { key: "b".to_string(), precondition: Precondition::Present },
+        ]).unwrap();
+        assert_eq!(previous, vec![None, None, None, Some(Hash::compute(b"two"))]);
+        assert_eq!(db.stats().unwrap().keys, 2);
+        assert_eq!(db.ref_count(&one).unwrap(), 2);
+        assert!(!db.objects_tree().contains_key(Hash::compute(b"two")).unwrap());
+
+        // A failed precondition rolls back the writes before it
+        let before = stats::read(db.stats_tree()).unwrap();
+        let failure = tx_manager.apply_batch(&[
+            put("d", b"three", Precondition::Unconditional),
+            BatchWrite::Delete { key: "a".to_string(), precondition: Precondition::HashMatches(vec![Hash::compute(b"other")]) },
+        ]).unwrap_err();
+        assert_eq!(failure.index, Some(1));
+        assert!(matches!(failure.error, crate::error::Error::PreconditionFailed(_)));
+        assert!(db.keys_tree().get("d").unwrap().is_none());
+        assert!(!db.objects_tree().contains_key(Hash::compute(b"three")).unwrap());
+        assert_eq!(stats::read(db.stats_tree()).unwrap(), before);
+
+        // So does deleting a key that does not exist
+        let failure = tx_manager.apply_batch(&[
+            BatchWrite::Delete { key: "a".to_string(), precondition: Precondition::Unconditional },
+            BatchWrite::Delete { key: "missing".to_string(), precondition: Precondition::Unconditional },
+        ]).unwrap_err();
+        assert_eq!(failure.index, Some(1));
+        assert!(db.keys_tree().get("a").unwrap().is_some());
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let put = |key: &str, data: &[u8]| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hash
+        };
+        for i in 0..5 {
+            put(&format!("tenant-a/{}", i), format!("value {}", i).as_bytes());
+        }
+        // Shared with a key outside the prefix, so it must survive
+        let shared = put("tenant-a/shared", b"shared");
+        put("tenant-b/shared", b"shared");
+        let before = stats::read(db.stats_tree()).unwrap();
+
+        let mut updates = 0;
+        let dry = bulk::delete_prefix(&db, b"tenant-a/", true, false, 2, |_| updates += 1).unwrap();
+        assert_eq!((dry.keys, dry.freed_objects, dry.dry_run, dry.done), (6, 5, true, true));
+        assert_eq!(dry.bytes, 5 * 7 + 6);
+        assert!(updates >= 3);
+        assert_eq!(stats::read(db.stats_tree()).unwrap(), before);
+
+        let report = bulk::delete_prefix(&db, b"tenant-a/", false, false, 2, |_| {}).unwrap();
+        assert_eq!((report.keys, report.bytes, report.freed_objects, report.freed_bytes),
+            (dry.keys, dry.bytes, dry.freed_objects, dry.freed_bytes));
+        assert_eq!(db.keys_tree().scan_prefix("tenant-a/").count(), 0);
+        assert_eq!(db.ref_count(&shared).unwrap(), 1);
+        assert!(db.objects_tree().contains_key(shared).unwrap());
+
+        let after = stats::read(db.stats_tree()).unwrap();
+        assert_eq!(after.keys, 1);
+        assert_eq!(after.objects, 1);
+        assert_eq!(after.stored_bytes, before.stored_bytes - report.freed_bytes);
+    }
+
+    #[test]
+    fn test_delete_prefix_to_trash() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone()).with_trash(true);
+        let mut hashes = Vec::new();
+        for i in 0..3 {
+            let data = format!("value {}", i);
+            let hash = Hash::compute(data.as_bytes());
+            tx_manager
+                .update_key_atomic(&format!("tenant-a/{}", i), &compressor.compress(data.as_bytes()).unwrap(), &hash, data.len() as u64, None)
+                .unwrap();
+            hashes.push(hash);
+        }
+
+        let dry = bulk::delete_prefix(&db, b"tenant-a/", true, true, 2, |_| {}).unwrap();
+        assert_eq!((dry.keys, dry.freed_objects), (3, 0));
+        let report = bulk::delete_prefix(&db, b"tenant-a/", false, true, 2, |_| {}).unwrap();
+        assert_eq!((report.keys, report.freed_objects, report.freed_bytes), (3, 0, 0));
+        assert_eq!(db.keys_tree().scan_prefix("tenant-a/").count(), 0);
+        assert_eq!(db.stats().unwrap().keys, 0);
+
+        // Every key is in the trash, still holding its object
+        let entries = trash::list(&db, "tenant-a/", None, 10).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(hashes.iter().all(|hash| db.ref_count(hash).unwrap() == 1));
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+
+        let meta = tx_manager.restore_deleted("tenant-a/1").unwrap();
+        assert_eq!(meta.hash, hashes[1]);
+        assert!(db.keys_tree().get("tenant-a/1").unwrap().is_some());
+        assert_eq!(trash::list(&db, "tenant-a/", None, 10).unwrap().len(), 2);
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_copy_and_rename_keys() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let put = |key: &str, data: &[u8], expires_at: Option<u64>| {
+            let hash = Hash::compute(data);
+            tx_manager
+                .update_key_atomic(key, &compressor.compress(data).unwrap(), &hash, data.len() as u64, expires_at)
+                .unwrap();
+            hash
+        };
+        let expires_at = crate::util::time::unix_now() + 3600;
+        let shared = put("src", b"shared value", Some(expires_at));
+        let replaced = put("dst", b"value being replaced", None);
+
+        // Copy over an existing key: its object goes with its last reference
+        let (meta, old_hash) = tx_manager.copy_key_if("src", "dst", &Precondition::Unconditional).unwrap();
+        assert_eq!((meta.hash, meta.expires_at, old_hash), (shared, Some(expires_at), Some(replaced)));
+        assert_eq!(db.ref_count(&shared).unwrap(), 2);
+        assert!(db.objects_tree().get(replaced).unwrap().is_none());
+        assert!(db.expiry_tree().contains_key(transactions::expiry_index_key(expires_at, "dst")).unwrap());
+
+        let result = tx_manager.copy_key_if("src", "dst", &Precondition::Absent);
+        assert!(matches!(result, Err(crate::error::Error::PreconditionFailed(_))));
+        let result = tx_manager.copy_key_if("missing", "dst2", &Precondition::Unconditional);
+        assert!(matches!(result, Err(crate::error::Error::NotFound(_))));
+        let result = tx_manager.rename_key_if("src", "src", &Precondition::Unconditional);
+        assert!(matches!(result, Err(crate::error::Error::InvalidRequest(_))));
+
+        // Rename keeps the metadata and moves every index entry
+        let created_at = KeyMeta::from_bytes(&db.keys_tree().get("src").unwrap().unwrap()).unwrap().created_at;
+        let (meta, old_hash) = tx_manager.rename_key_if("src", "moved", &Precondition::Absent).unwrap();
+        assert_eq!((meta.created_at, old_hash), (created_at, None));
+        assert!(db.keys_tree().get("src").unwrap().is_none());
+        assert!(!db.expiry_tree().contains_key(transactions::expiry_index_key(expires_at, "src")).unwrap());
+        assert!(db.expiry_tree().contains_key(transactions::expiry_index_key(expires_at, "moved")).unwrap());
+        let keys: Vec<_> = db.scan_refs(&shared, None).map(|k| k.unwrap().to_vec()).collect();
+        assert_eq!(keys, [b"dst".to_vec(), b"moved".to_vec()]);
+        assert_eq!(db.ref_count(&shared).unwrap(), 2);
+
+        // Renaming onto a key sharing the object drops one reference
+        tx_manager.rename_key_if("moved", "dst", &Precondition::Unconditional).unwrap();
+        assert_eq!(db.ref_count(&shared).unwrap(), 1);
+        assert!(db.objects_tree().get(shared).unwrap().is_some());
+
+        let stats = db.stats().unwrap();
+        assert_eq!((stats.keys, stats.objects, stats.logical_bytes), (1, 1, 12));
+        assert!(fsck::check(&db, &compressor, false).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_scrub_records_corrupt_objects() {
+        let (_temp, db) = setup_test_db();
+        let compressor = Compressor::new(1);
+        let tx_manager = TransactionManager::new(db.clone());
+        let mut hashes = Vec::new();
+        for i in 0..5 {
+            let data = format!("scrubbed value {}", i);
+            let hash = Hash::compute(data.as_bytes());
+            tx_manager
+                .update_key_if(&format!("key{}", i), &compressor.compress(data.as_bytes()).unwrap(), &hash, data.len() as u64, None, &Precondition::Absent)
+                .unwrap();
+            hashes.push(hash);
+        }
+        let damaged = hashes[2];
+        let bad_bytes = compressor.compress(b"bit rot").unwrap();
+        db.objects_tree().insert(damaged, bad_bytes.clone()).unwrap();
+
+        // A tiny budget still advances one object per step
+        let mut steps = 0;
+        let mut found = 0;
+        loop {
+            let progress = scrub::scrub_step(&db, &compressor, 1).unwrap();
+            steps += 1;
+            found += progress.corrupt;
+            if progress.pass_complete {
+                break;
+            }
+            assert_eq!(progress.objects, 1);
+        }
+        assert!(steps >= 5);
+        assert_eq!(found, 1);
+        assert_eq!(scrub::corrupt_count(&db), 1);
+
+        let err = scrub::check_known_corrupt(&db, &damaged, &bad_bytes).unwrap_err();
+        assert!(matches!(err, crate::error::Error::Corrupt(_)));
+        assert!(err.to_string().contains(&damaged.to_hex_string()));
+        let healthy = db.objects_tree().get(hashes[0]).unwrap().unwrap();
+        assert!(scrub::check_known_corrupt(&db, &hashes[0], &healthy).is_ok());
+
+        // A second pass does not count the same damage again
+        let progress = scrub::scrub_step(&db, &compressor, u64::MAX).unwrap();
+        assert!(progress.pass_complete);
+        assert_eq!((progress.objects, progress.corrupt), (5, 0));
+
+        // Once the object holds good bytes again the record no longer applies
+        // and the next pass drops it
+        let good = compressor.compress(b"scrubbed value 2").unwrap();
+        db.objects_tree().insert(damaged, good.clone()).unwrap();
+        assert!(scrub::check_known_corrupt(&db, &damaged, &good).is_ok());
+        scrub::scrub_step(&db, &compressor, u64::MAX).unwrap();
+        assert_eq!(scrub::corrupt_count(&db), 0);
+    }
+
+    #[test]
+    fn test_scrub_empty_database() {
+        let (_temp, db) = setup_test_db();
+        let progress = scrub::scrub_step(&db, &Compressor::new(1), 1024).unwrap();
+        assert!(progress.pass_complete);
+        assert_eq!(progress.objects, 0);
+    }
+
     #[test]
     fn test_compression_ratio() {
         let compressor = Compressor::new(1);
@@ -1,70 +1,233 @@
 use crate::error::Error;
 use crate::storage::{StorageDb, KeyMeta};
-use crate::util::hash::Hash;
+use crate::util::hash::{Hash, HashAlgorithm};
+use crate::util::time::unix_now;
+use crate::storage::refcounts;
+use crate::storage::stats::StatsDelta;
+use crate::storage::versions::{self, VersionPolicy};
+use crate::storage::dedup;
+use crate::util::compression::{Compressor, ObjectHeader};
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
 use sled::{self, Transactional};
+use std::cell::Cell;
+use std::sync::Arc;
+
+/// Expiry index entry for a key: big-endian `expires_at` followed by the key bytes,
+/// so a range scan over the index yields keys in expiry order.
+pub fn expiry_index_key(expires_at: u64, key: &str) -> Vec<u8> {
+    let mut index_key = Vec::with_capacity(8 + key.len());
+    index_key.extend_from_slice(&expires_at.to_be_bytes());
+    index_key.extend_from_slice(key.as_bytes());
+    index_key
+}
+
+/// Write precondition, evaluated against the key's current metadata inside the
+/// same transaction as the write. Expired keys count as absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Precondition {
+    #[default]
+    Unconditional,
+    /// `If-None-Match: *` - the key must not exist
+    Absent,
+    /// `If-Match: *` - the key must exist
+    Present,
+    /// `If-Match: <hash>, ...` - the key must currently hold one of these hashes
+    HashMatches(Vec<Hash>),
+    /// `If-None-Match: <hash>, ...` - the key must not hold any of these hashes
+    HashDiffers(Vec<Hash>),
+}
+
+impl Precondition {
+    pub fn check(&self, key: &str, current: Option<&KeyMeta>, now: u64) -> Result<(), Error> {
+        let current = current.filter(|meta| !meta.is_expired(now));
+        let holds = match (self, current) {
+            (Precondition::Unconditional, _) => true,
+            (Precondition::Absent, current) => current.is_none(),
+            (Precondition::Present, current) => current.is_some(),
+            (Precondition::HashMatches(hashes), Some(meta)) => hashes.contains(&meta.hash),
+            (Precondition::HashMatches(_), None) => false,
+            (Precondition::HashDiffers(hashes), Some(meta)) => !hashes.contains(&meta.hash),
+            (Precondition::HashDiffers(_), None) => true,
+        };
+
+        if holds {
+            Ok(())
+        } else {
+            Err(Error::PreconditionFailed(match (self, current) {
+                (Precondition::Absent, _) => format!("Key '{}' already exists", key),
+                (_, None) => format!("Key '{}' does not exist", key),
+                (_, Some(meta)) => format!("Key '{}' has hash {}", key, meta.hash.to_hex_string()),
+            }))
+        }
+    }
+}
 
 pub struct TransactionManager {
     db: StorageDb,
+    hash_algorithm: HashAlgorithm,
+    versioning: Arc<VersionPolicy>,
+    trash: bool,
+    dedup_verify: Option<Arc<Compressor>>,
 }
 
 impl TransactionManager {
     pub fn new(db: StorageDb) -> Self {
-        Self { db }
+        Self {
+            db,
+            hash_algorithm: HashAlgorithm::default(),
+            versioning: Arc::default(),
+            trash: false,
+            dedup_verify: None,
+        }
+    }
+
+    /// Algorithm that produced the hashes passed to this manager's writes,
+    /// recorded in the key metadata
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Keys whose overwritten values are kept as versions
+    pub fn with_versioning(mut self, policy: Arc<VersionPolicy>) -> Self {
+        self.versioning = policy;
+        self
+    }
+
+    /// Move deleted keys to the trash instead of dropping them
+    pub fn with_trash(mut self, enabled: bool) -> Self {
+        self.trash = enabled;
+        self
+    }
+
+    /// Byte-compare, with `compressor`, a new value against the object
+    /// already stored under its hash inside the write transaction, failing
+    /// rather than aliasing a different object. `None` trusts the hash.
+    pub fn with_dedup_verify(mut self, compressor: Option<Arc<Compressor>>) -> Self {
+        self.dedup_verify = compressor;
+        self
+    }
+
+    pub fn delete_key_atomic(&self, key: &str) -> Result<Option<(Hash, u64)>, Error> {
+        self.delete_key_where(key, |_| Ok(true))
+    }
+
+    /// Delete a key if `precondition` holds for its current state.
+    pub fn delete_key_if(&self, key: &str, precondition: &Precondition) -> Result<Option<(Hash, u64)>, Error> {
+        let now = unix_now();
+        self.delete_key_where(key, |meta| {
+            precondition.check(key, meta, now)?;
+            Ok(true)
+        })
+    }
+
+    /// Delete a key only if it is expired at `now`.
+    ///
+    /// The expiry check runs inside the transaction, so a concurrent PUT that
+    /// refreshes the key is never lost. Returns `Ok(None)` if the key is gone
+    /// or no longer expired.
+    pub fn delete_expired_key_atomic(&self, key: &str, now: u64) -> Result<Option<(Hash, u64)>, Error> {
+        self.delete_key_where(key, |meta| Ok(meta.is_some_and(|m| m.is_expired(now))))
+    }
+
+    /// Delete a key if `should_delete` accepts its current metadata, removing the
+    /// object in the same transaction when this was its last reference.
+    /// `should_delete` sees `None` for a missing key; returning `Ok(false)`
+    /// skips the delete, an error aborts the transaction.
+    fn delete_key_where<F>(&self, key: &str, should_delete: F) -> Result<Option<(Hash, u64)>, Error>
+    where
+        F: Fn(Option<&KeyMeta>) -> Result<bool, Error>,
+    {
+        let _writes = self.db.write_access()?;
+        let now = unix_now();
+        let db = &self.db;
+
+        let result = (db.keys_tree(), db.objects_tree(), db.refs_tree(), db.expiry_tree(), db.refcounts_tree(), db.versions_tree(), db.trash_tree(), db.stats_tree()).transaction(|(keys, objects, refs, expiry, refcounts, versions, trash, stats_tree)| {
+            let trees = KeyTrees { keys, objects, refs, expiry, refcounts, versions, trash };
+            let mut delta = StatsDelta::default();
+            let deleted = self.delete_in(&trees, key, &should_delete, now, &mut delta)?;
+            delta.apply(stats_tree)?;
+            Ok(deleted)
+        });
+
+        match result {
+            Ok(deleted) => Ok(deleted),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(_) => {
+                Err(Error::Conflict("Transaction conflict - please retry".to_string()))
+            }
+        }
     }
 
-    pub fn put_key_atomic(
+    /// The body of [`delete_key_where`](Self::delete_key_where), inside a
+    /// transaction over `trees`.
+    fn delete_in<F>(
         &self,
+        trees: &KeyTrees<'_>,
         key: &str,
-        data: &[u8],
-        hash: &Hash,
-        size: u64,
-    ) -> Result<bool, Error> {
-        let db_ref = self.db.clone();
-        let key_owned = key.to_string();
-        let hash_owned = *hash;
-        let data_owned = data.to_vec();
-
-        // Get the trees before starting the transaction (now return &Tree directly)
-        let keys_tree = db_ref.keys_tree();
-        let objects_tree = db_ref.objects_tree();
-        let refs_tree = db_ref.refs_tree();
-
-        // Use sled's transaction API for atomic multi-tree operations
-        let result = (keys_tree, objects_tree, refs_tree).transaction(|(keys_tree, objects_tree, refs_tree)| {
-            // Check if key already exists - this is a conflict
-            if keys_tree.get(key_owned.as_bytes())?.is_some() {
-                return Err(sled::transaction::ConflictableTransactionError::Abort(
-                    Error::Conflict(format!("Key '{}' already exists", key_owned))
-                ));
-            }
-
-            // Check if object exists (for deduplication)
-            let is_new_object = objects_tree.get(hash_owned.as_ref())?.is_none();
-
-            if is_new_object {
-                // Store the compressed object
-                objects_tree.insert(hash_owned.as_ref(), data_owned.as_slice())?;
-            }
-
-            // Create key metadata
-            let meta = KeyMeta::new(hash_owned, size);
-            let meta_bytes = bincode::serialize(&meta)
-                .map_err(|e| sled::transaction::ConflictableTransactionError::Abort(
-                    Error::Storage(e.to_string())
-                ))?;
+        should_delete: &F,
+        now: u64,
+        delta: &mut StatsDelta,
+    ) -> Result<Option<(Hash, u64)>, sled::transaction::ConflictableTransactionError<Error>>
+    where
+        F: Fn(Option<&KeyMeta>) -> Result<bool, Error>,
+    {
+        // Get key metadata inside the transaction to verify it still exists
+        let meta = trees.keys.get(key.as_bytes())?
+            .map(|bytes| KeyMeta::from_bytes(&bytes))
+            .transpose()
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+
+        let proceed = should_delete(meta.as_ref())
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+        if !proceed {
+            return Ok(None);
+        }
 
-            keys_tree.insert(key_owned.as_bytes(), meta_bytes)?;
+        let meta = meta.ok_or_else(|| sled::transaction::ConflictableTransactionError::Abort(
+            Error::NotFound(format!("Key '{}' not found", key))
+        ))?;
 
-            // Add ref entry
-            let mut ref_key = hash_owned.as_ref().to_vec();
-            ref_key.extend_from_slice(key_owned.as_bytes());
-            refs_tree.insert(ref_key.as_slice(), b"1")?;
+        if self.trash && !meta.is_expired(now) {
+            unlink_key(trees.keys, trees.refs, trees.expiry, key, &meta, delta)?;
+            // The tombstone takes over the key's reference; one it
+            // replaces lets go of its own
+            let tombstone = meta.to_stamped_bytes(now)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            if let Some(old) = trees.trash.insert(key.as_bytes(), tombstone)? {
+                let (_, old_meta) = KeyMeta::from_stamped_bytes(&old)
+                    .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+                release_object(trees.objects, trees.refcounts, &old_meta.hash, delta)?;
+            }
+        } else {
+            remove_key(trees.keys, trees.objects, trees.refs, trees.expiry, trees.refcounts, key, &meta, delta)?;
+        }
+
+        Ok(Some((meta.hash, meta.size)))
+    }
 
-            Ok(is_new_object)
+    /// Delete every listed key that exists, in one transaction.
+    ///
+    /// Missing keys are skipped, and with trash enabled the keys move to the
+    /// trash as for a single delete. Returns the change applied to the
+    /// counters, whose negated `objects` and `stored_bytes` are what was freed.
+    pub fn delete_keys_atomic(&self, keys: &[String]) -> Result<StatsDelta, Error> {
+        let _writes = self.db.write_access()?;
+        let now = unix_now();
+        let db = &self.db;
+
+        let result = (db.keys_tree(), db.objects_tree(), db.refs_tree(), db.expiry_tree(), db.refcounts_tree(), db.versions_tree(), db.trash_tree(), db.stats_tree()).transaction(|(keys_tree, objects, refs, expiry, refcounts, versions, trash, stats_tree)| {
+            let trees = KeyTrees { keys: keys_tree, objects, refs, expiry, refcounts, versions, trash };
+            let mut delta = StatsDelta::default();
+            for key in keys {
+                self.delete_in(&trees, key, &|meta: Option<&KeyMeta>| Ok(meta.is_some()), now, &mut delta)?;
+            }
+            delta.apply(stats_tree)?;
+            Ok(delta)
         });
 
         match result {
-            Ok(is_new) => Ok(is_new),
+            Ok(delta) => Ok(delta),
             Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
             Err(_) => {
                 Err(Error::Conflict("Transaction conflict - please retry".to_string()))
@@ -72,62 +235,179 @@ impl TransactionManager {
         }
     }
 
-    pub fn delete_key_atomic(&self, key: &str) -> Result<Option<(Hash, u64)>, Error> {
-        let db_ref = self.db.clone();
-        let key_owned = key.to_string();
+    pub fn update_key_atomic(
+        &self,
+        key: &str,
+        data: &[u8],
+        hash: &Hash,
+        size: u64,
+        expires_at: Option<u64>,
+    ) -> Result<Option<Hash>, Error> {
+        self.update_key_if(key, data, hash, size, expires_at, &Precondition::Unconditional)
+            .map(|(old_hash, _)| old_hash)
+    }
+
+    /// Create or replace a key if `precondition` holds for its current state.
+    /// Returns the previous hash, `None` if the key was created, and whether
+    /// the object was already stored (a dedup hit), as seen by the write
+    /// transaction.
+    pub fn update_key_if(
+        &self,
+        key: &str,
+        data: &[u8],
+        hash: &Hash,
+        size: u64,
+        expires_at: Option<u64>,
+        precondition: &Precondition,
+    ) -> Result<(Option<Hash>, bool), Error> {
+        self.write_key(key, ObjectSource::Data(data, size), hash, expires_at, precondition)
+            .map(|(old_hash, _, deduplicated)| (old_hash, deduplicated))
+    }
 
-        // Get the trees (now return &Tree directly)
-        let keys_tree = db_ref.keys_tree();
-        let objects_tree = db_ref.objects_tree();
-        let refs_tree = db_ref.refs_tree();
+    /// Point a key at the object already stored under `hash`, without
+    /// sending its bytes. The object's presence is checked in the same
+    /// transaction, so it cannot be collected in between.
+    ///
+    /// Returns the previous hash and the value's size. Fails with
+    /// `Error::NotFound` if no such object is stored.
+    pub fn link_key_if(
+        &self,
+        key: &str,
+        hash: &Hash,
+        expires_at: Option<u64>,
+        precondition: &Precondition,
+    ) -> Result<(Option<Hash>, u64), Error> {
+        self.write_key(key, ObjectSource::Existing, hash, expires_at, precondition)
+            .map(|(old_hash, size, _)| (old_hash, size))
+    }
+
+    /// Point `destination` at the same object as `source`, if `precondition`
+    /// holds for the destination's current state. The new key keeps the
+    /// source's TTL.
+    ///
+    /// Returns the destination's new metadata and its previous hash, `None`
+    /// if it was created.
+    pub fn copy_key_if(
+        &self,
+        source: &str,
+        destination: &str,
+        precondition: &Precondition,
+    ) -> Result<(KeyMeta, Option<Hash>), Error> {
+        self.relink_key(source, destination, false, precondition)
+    }
 
-        // First, get the key metadata to find the hash
-        let meta_bytes = keys_tree.get(key_owned.as_bytes())?
-            .ok_or_else(|| Error::NotFound(format!("Key '{}' not found", key_owned)))?;
+    /// Move `source` to `destination`, keeping its metadata, if
+    /// `precondition` holds for the destination's current state.
+    ///
+    /// Returns the destination's new metadata and its previous hash, `None`
+    /// if it was created.
+    pub fn rename_key_if(
+        &self,
+        source: &str,
+        destination: &str,
+        precondition: &Precondition,
+    ) -> Result<(KeyMeta, Option<Hash>), Error> {
+        self.relink_key(source, destination, true, precondition)
+    }
 
-        let meta: KeyMeta = bincode::deserialize(&meta_bytes)?;
-        let hash = meta.hash;
-        let size = meta.size;
+    /// Shared body of copy and rename: only the `keys`, `refs` and `expiry`
+    /// entries move, the object is never read or rewritten.
+    fn relink_key(
+        &self,
+        source: &str,
+        destination: &str,
+        remove_source: bool,
+        precondition: &Precondition,
+    ) -> Result<(KeyMeta, Option<Hash>), Error> {
+        if source == destination {
+            return Err(Error::InvalidRequest("Source and destination are the same key".to_string()));
+        }
+        let _writes = self.db.write_access()?;
+        let now = unix_now();
+        let keys_tree = self.db.keys_tree();
+        let objects_tree = self.db.objects_tree();
+        let refs_tree = self.db.refs_tree();
+        let expiry_tree = self.db.expiry_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+        let versions_tree = self.db.versions_tree();
+        let keep_history = self.versioning.applies_to(destination);
 
-        // Check if we need to GC the object by counting refs
-        let prefix = hash.as_ref();
-        let _ref_count_before = refs_tree.scan_prefix(prefix).count();
+        let result = (keys_tree, objects_tree, refs_tree, expiry_tree, refcounts_tree, stats_tree, versions_tree).transaction(|(keys_tree, objects_tree, refs_tree, expiry_tree, refcounts_tree, stats_tree, versions_tree)| {
+            let read = |key: &str| -> Result<Option<KeyMeta>, sled::transaction::ConflictableTransactionError<Error>> {
+                keys_tree.get(key.as_bytes())?
+                    .map(|bytes| KeyMeta::from_bytes(&bytes))
+                    .transpose()
+                    .map_err(sled::transaction::ConflictableTransactionError::Abort)
+            };
 
-        let result = (keys_tree, objects_tree, refs_tree).transaction(|(keys_tree, _objects_tree, refs_tree)| {
-            // Get key metadata again to verify it still exists
-            let meta_bytes = keys_tree.get(key_owned.as_bytes())?
+            let src = read(source)?
+                .filter(|meta| !meta.is_expired(now))
                 .ok_or_else(|| sled::transaction::ConflictableTransactionError::Abort(
-                    Error::NotFound(format!("Key '{}' not found", key_owned))
+                    Error::NotFound(format!("Key '{}' not found", source))
                 ))?;
+            let existing = read(destination)?;
+            precondition.check(destination, existing.as_ref(), now)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
 
-            let meta: KeyMeta = bincode::deserialize(&meta_bytes)
-                .map_err(|e| sled::transaction::ConflictableTransactionError::Abort(
-                    Error::Storage(e.to_string())
-                ))?;
+            let mut delta = StatsDelta { keys: 1, logical_bytes: src.size as i64, ..Default::default() };
 
-            let hash = meta.hash;
+            // Take the new reference before dropping any, so an object shared
+            // by both keys is never released in between
+            refcounts::increment(refcounts_tree, &src.hash, src.hash_algorithm)?;
+            let old_hash = match &existing {
+                Some(meta) => {
+                    delta.keys -= 1;
+                    delta.logical_bytes -= meta.size as i64;
+                    if let Some(at) = meta.expires_at {
+                        expiry_tree.remove(expiry_index_key(at, destination))?;
+                    }
+                    refs_tree.remove([meta.hash.as_ref(), destination.as_bytes()].concat())?;
+                    if keep_history && !meta.is_expired(now) {
+                        let newest = versions::archive(versions_tree, destination, meta, now)?;
+                        prune_excess_version(versions_tree, objects_tree, refcounts_tree, destination, newest, &self.versioning, &mut delta)?;
+                    } else {
+                        release_object(objects_tree, refcounts_tree, &meta.hash, &mut delta)?;
+                    }
+                    Some(meta.hash).filter(|_| !meta.is_expired(now))
+                }
+                None => None,
+            };
 
-            // Remove key
-            keys_tree.remove(key_owned.as_bytes())?;
+            let meta = if remove_source {
+                keys_tree.remove(source.as_bytes())?;
+                if let Some(at) = src.expires_at {
+                    expiry_tree.remove(expiry_index_key(at, source))?;
+                }
+                refs_tree.remove([src.hash.as_ref(), source.as_bytes()].concat())?;
+                release_object(objects_tree, refcounts_tree, &src.hash, &mut delta)?;
+                delta.keys -= 1;
+                delta.logical_bytes -= src.size as i64;
+                src
+            } else {
+                let mut meta = KeyMeta::new(src.hash, src.size)
+                    .with_expires_at(src.expires_at)
+                    .with_hash_algorithm(src.hash_algorithm);
+                if let Some(old) = existing.as_ref().filter(|meta| !meta.is_expired(now)) {
+                    meta = meta.with_created_at(old.created_at);
+                }
+                meta
+            };
 
-            // Remove ref
-            let mut ref_key = hash.as_ref().to_vec();
-            ref_key.extend_from_slice(key_owned.as_bytes());
-            refs_tree.remove(ref_key.as_slice())?;
+            let meta_bytes = meta.to_bytes()
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            keys_tree.insert(destination.as_bytes(), meta_bytes)?;
+            if let Some(at) = meta.expires_at {
+                expiry_tree.insert(expiry_index_key(at, destination), &[])?;
+            }
+            refs_tree.insert([meta.hash.as_ref(), destination.as_bytes()].concat(), b"1")?;
 
-            Ok((hash, meta.size))
+            delta.apply(stats_tree)?;
+            Ok((meta, old_hash))
         });
 
         match result {
-            Ok(_) => {
-                // Check if object should be deleted (no more refs)
-                // We do this outside the transaction since we can't iterate inside
-                let ref_count_after = refs_tree.scan_prefix(prefix).count();
-                if ref_count_after == 0 {
-                    objects_tree.remove(hash.as_ref())?;
-                }
-                Ok(Some((hash, size)))
-            }
+            Ok(written) => Ok(written),
             Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
             Err(_) => {
                 Err(Error::Conflict("Transaction conflict - please retry".to_string()))
@@ -135,69 +415,290 @@ impl TransactionManager {
         }
     }
 
-    pub fn update_key_atomic(
+    fn write_key(
         &self,
         key: &str,
-        data: &[u8],
+        source: ObjectSource<'_>,
         hash: &Hash,
-        size: u64,
-    ) -> Result<Option<Hash>, Error> {
-        let db_ref = self.db.clone();
-        let key_owned = key.to_string();
-        let hash_owned = *hash;
-        let data_owned = data.to_vec();
-
-        // Get the trees (now return &Tree directly)
-        let keys_tree = db_ref.keys_tree();
-        let objects_tree = db_ref.objects_tree();
-        let refs_tree = db_ref.refs_tree();
-
-        let result = (keys_tree, objects_tree, refs_tree).transaction(|(keys_tree, objects_tree, refs_tree)| {
-            // Get existing metadata
-            let (old_hash, _should_gc_old) = if let Some(meta_bytes) = keys_tree.get(key_owned.as_bytes())? {
-                let meta: KeyMeta = bincode::deserialize(&meta_bytes)
-                    .map_err(|e| sled::transaction::ConflictableTransactionError::Abort(
-                        Error::Storage(e.to_string())
+        expires_at: Option<u64>,
+        precondition: &Precondition,
+    ) -> Result<(Option<Hash>, u64, bool), Error> {
+        let _writes = self.db.write_access()?;
+        let now = unix_now();
+        let db = &self.db;
+        let write = KeyWrite {
+            key,
+            source,
+            hash: *hash,
+            algorithm: self.hash_algorithm,
+            expires_at,
+            precondition,
+        };
+
+        let result = (db.keys_tree(), db.objects_tree(), db.refs_tree(), db.expiry_tree(), db.refcounts_tree(), db.versions_tree(), db.trash_tree(), db.stats_tree()).transaction(|(keys, objects, refs, expiry, refcounts, versions, trash, stats_tree)| {
+            let trees = KeyTrees { keys, objects, refs, expiry, refcounts, versions, trash };
+            let mut delta = StatsDelta::default();
+            let written = self.write_in(&trees, &write, now, &mut delta)?;
+            delta.apply(stats_tree)?;
+            Ok(written)
+        });
+
+        match result {
+            Ok(written) => Ok(written),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(_) => {
+                Err(Error::Conflict("Transaction conflict - please retry".to_string()))
+            }
+        }
+    }
+
+    /// The body of [`write_key`](Self::write_key), inside a transaction over
+    /// `trees`. Returns the hash the key held, if it was live, the value's
+    /// size and whether its object was already stored.
+    fn write_in(
+        &self,
+        trees: &KeyTrees<'_>,
+        write: &KeyWrite<'_>,
+        now: u64,
+        delta: &mut StatsDelta,
+    ) -> Result<(Option<Hash>, u64, bool), sled::transaction::ConflictableTransactionError<Error>> {
+        let KeyWrite { key, source, hash, algorithm, expires_at, precondition } = *write;
+
+        // Get existing metadata
+        let existing = trees.keys.get(key.as_bytes())?
+            .map(|bytes| KeyMeta::from_bytes(&bytes))
+            .transpose()
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+
+        precondition.check(key, existing.as_ref(), now)
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+
+        let size = match source {
+            ObjectSource::Data(_, size) => size,
+            ObjectSource::Existing => {
+                let stored = trees.objects.get(hash.as_ref())?
+                    .ok_or_else(|| sled::transaction::ConflictableTransactionError::Abort(
+                        Error::NotFound(format!("Object '{}' not found", hash.to_hex_string()))
                     ))?;
-                let old_hash = meta.hash;
+                ObjectHeader::parse(&stored)
+                    .ok_or_else(|| sled::transaction::ConflictableTransactionError::Abort(
+                        Error::Corrupt(format!("Object '{}' has no header", hash.to_hex_string()))
+                    ))?
+                    .original_len
+            }
+        };
 
-                // Remove old ref
-                let mut old_ref_key = old_hash.as_ref().to_vec();
-                old_ref_key.extend_from_slice(key_owned.as_bytes());
-                refs_tree.remove(old_ref_key.as_slice())?;
+        delta.logical_bytes += size as i64;
+        match &existing {
+            Some(meta) => delta.logical_bytes -= meta.size as i64,
+            None => delta.keys += 1,
+        }
 
-                // For now, we don't GC in the transaction - we'll do it outside
-                (Some(old_hash), false)
-            } else {
-                (None, false)
-            };
+        // Overwrites keep the original creation time unless the old key had expired
+        let created_at = existing.as_ref()
+            .filter(|meta| !meta.is_expired(now))
+            .map(|meta| meta.created_at);
+
+        // The old value keeps its reference as a version. Otherwise
+        // rewriting the same content keeps the reference as is.
+        let archived = self.versioning.applies_to(key) && existing.as_ref().is_some_and(|meta| !meta.is_expired(now));
+        let unchanged = !archived && existing.as_ref().is_some_and(|meta| meta.hash == hash);
+
+        // Take the new reference before dropping any, so an object shared
+        // with a version pruned below is never released in between
+        if !unchanged {
+            refcounts::increment(trees.refcounts, &hash, algorithm)?;
+        }
+
+        let old_hash = if let Some(meta) = existing {
+            let old_hash = meta.hash;
+
+            if let Some(at) = meta.expires_at {
+                trees.expiry.remove(expiry_index_key(at, key))?;
+            }
+
+            // Remove old ref
+            let mut old_ref_key = old_hash.as_ref().to_vec();
+            old_ref_key.extend_from_slice(key.as_bytes());
+            trees.refs.remove(old_ref_key.as_slice())?;
+
+            if archived {
+                let newest = versions::archive(trees.versions, key, &meta, now)?;
+                prune_excess_version(trees.versions, trees.objects, trees.refcounts, key, newest, &self.versioning, delta)?;
+            } else if !unchanged {
+                release_object(trees.objects, trees.refcounts, &old_hash, delta)?;
+            }
 
-            // Check if new object exists (for deduplication)
-            let is_new_object = objects_tree.get(hash_owned.as_ref())?.is_none();
+            // An expired key counts as absent, so the write reports a create
+            if meta.is_expired(now) { None } else { Some(old_hash) }
+        } else {
+            None
+        };
 
-            if is_new_object {
-                objects_tree.insert(hash_owned.as_ref(), data_owned.as_slice())?;
+        let deduplicated = match source {
+            ObjectSource::Data(data, _) => {
+                if let Some(compressor) = &self.dedup_verify {
+                    dedup::check_stored(trees.objects, compressor, &hash, algorithm, data)?;
+                }
+                !store_object(trees.objects, &hash, data, delta)?
             }
+            ObjectSource::Existing => true,
+        };
+
+        // Create new key metadata
+        let mut meta = KeyMeta::new(hash, size)
+            .with_expires_at(expires_at)
+            .with_hash_algorithm(algorithm);
+        if let Some(created_at) = created_at {
+            meta = meta.with_created_at(created_at);
+        }
+        let meta_bytes = meta.to_bytes()
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+
+        trees.keys.insert(key.as_bytes(), meta_bytes)?;
+
+        if let Some(at) = expires_at {
+            trees.expiry.insert(expiry_index_key(at, key), &[])?;
+        }
+
+        // Add new ref
+        let mut ref_key = hash.as_ref().to_vec();
+        ref_key.extend_from_slice(key.as_bytes());
+        trees.refs.insert(ref_key.as_slice(), b"1")?;
 
-            // Create new key metadata
-            let meta = KeyMeta::new(hash_owned, size);
-            let meta_bytes = bincode::serialize(&meta)
-                .map_err(|e| sled::transaction::ConflictableTransactionError::Abort(
-                    Error::Storage(e.to_string())
+        Ok((old_hash, size, deduplicated))
+    }
+
+    /// Bring `key` back from the trash with the metadata it was deleted with.
+    ///
+    /// Fails with `Error::Conflict` if the key has been written again since;
+    /// an expiry that passed while it was in the trash is dropped.
+    pub fn restore_deleted(&self, key: &str) -> Result<KeyMeta, Error> {
+        let _writes = self.db.write_access()?;
+        let now = unix_now();
+        let keys_tree = self.db.keys_tree();
+        let objects_tree = self.db.objects_tree();
+        let refs_tree = self.db.refs_tree();
+        let expiry_tree = self.db.expiry_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+        let trash_tree = self.db.trash_tree();
+
+        let result = (keys_tree, objects_tree, refs_tree, expiry_tree, refcounts_tree, stats_tree, trash_tree).transaction(|(keys_tree, objects_tree, refs_tree, expiry_tree, refcounts_tree, stats_tree, trash_tree)| {
+            let tombstone = trash_tree.remove(key.as_bytes())?
+                .ok_or_else(|| sled::transaction::ConflictableTransactionError::Abort(
+                    Error::NotFound(format!("Key '{}' not found in trash", key))
                 ))?;
+            let (_, mut meta) = KeyMeta::from_stamped_bytes(&tombstone)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+
+            let mut delta = StatsDelta::default();
+            let existing = keys_tree.get(key.as_bytes())?
+                .map(|bytes| KeyMeta::from_bytes(&bytes))
+                .transpose()
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            if let Some(existing) = existing {
+                if !existing.is_expired(now) {
+                    return Err(sled::transaction::ConflictableTransactionError::Abort(
+                        Error::Conflict(format!("Key '{}' already exists", key))
+                    ));
+                }
+                remove_key(keys_tree, objects_tree, refs_tree, expiry_tree, refcounts_tree, key, &existing, &mut delta)?;
+            }
+
+            // The key takes over the tombstone's reference
+            if meta.is_expired(now) {
+                meta.expires_at = None;
+            }
+            let meta_bytes = meta.to_bytes()
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            keys_tree.insert(key.as_bytes(), meta_bytes)?;
+            if let Some(at) = meta.expires_at {
+                expiry_tree.insert(expiry_index_key(at, key), &[])?;
+            }
+            refs_tree.insert([meta.hash.as_ref(), key.as_bytes()].concat(), b"1")?;
+
+            delta.keys += 1;
+            delta.logical_bytes += meta.size as i64;
+            delta.apply(stats_tree)?;
+            Ok(meta)
+        });
+
+        match result {
+            Ok(meta) => Ok(meta),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(_) => {
+                Err(Error::Conflict("Transaction conflict - please retry".to_string()))
+            }
+        }
+    }
+
+    /// Permanently drop `key` from the trash, releasing its object, if it
+    /// was deleted no later than `deleted_before`.
+    ///
+    /// Returns `None` if there is no such entry, otherwise whether its
+    /// object was freed.
+    pub fn purge_deleted(&self, key: &str, deleted_before: u64) -> Result<Option<bool>, Error> {
+        let _writes = self.db.write_access()?;
+        let trash_tree = self.db.trash_tree();
+        let objects_tree = self.db.objects_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+
+        let result = (trash_tree, objects_tree, refcounts_tree, stats_tree).transaction(|(trash_tree, objects_tree, refcounts_tree, stats_tree)| {
+            let Some(tombstone) = trash_tree.get(key.as_bytes())? else {
+                return Ok(None);
+            };
+            let (deleted_at, meta) = KeyMeta::from_stamped_bytes(&tombstone)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            // Deleted again since the caller looked
+            if deleted_at > deleted_before {
+                return Ok(None);
+            }
+            trash_tree.remove(key.as_bytes())?;
+
+            let mut delta = StatsDelta::default();
+            release_object(objects_tree, refcounts_tree, &meta.hash, &mut delta)?;
+            delta.apply(stats_tree)?;
+            Ok(Some(delta.objects < 0))
+        });
+
+        match result {
+            Ok(purged) => Ok(purged),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(_) => {
+                Err(Error::Conflict("Transaction conflict - please retry".to_string()))
+            }
+        }
+    }
 
-            keys_tree.insert(key_owned.as_bytes(), meta_bytes)?;
+    /// Drop archived version `version` of `key`, releasing its object.
+    ///
+    /// Returns `None` if the version is already gone, otherwise whether its
+    /// object was freed.
+    pub fn drop_version(&self, key: &str, version: u64) -> Result<Option<bool>, Error> {
+        let _writes = self.db.write_access()?;
+        let versions_tree = self.db.versions_tree();
+        let objects_tree = self.db.objects_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+        let version_key = versions::version_key(key, version);
 
-            // Add new ref
-            let mut ref_key = hash_owned.as_ref().to_vec();
-            ref_key.extend_from_slice(key_owned.as_bytes());
-            refs_tree.insert(ref_key.as_slice(), b"1")?;
+        let result = (versions_tree, objects_tree, refcounts_tree, stats_tree).transaction(|(versions_tree, objects_tree, refcounts_tree, stats_tree)| {
+            let Some(record) = versions_tree.remove(version_key.as_slice())? else {
+                return Ok(None);
+            };
+            let (_, meta) = KeyMeta::from_stamped_bytes(&record)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
 
-            Ok((old_hash, is_new_object))
+            let mut delta = StatsDelta::default();
+            release_object(objects_tree, refcounts_tree, &meta.hash, &mut delta)?;
+            delta.apply(stats_tree)?;
+            Ok(Some(delta.objects < 0))
         });
 
         match result {
-            Ok((old_hash, _)) => Ok(old_hash),
+            Ok(dropped) => Ok(dropped),
             Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
             Err(_) => {
                 Err(Error::Conflict("Transaction conflict - please retry".to_string()))
@@ -205,16 +706,337 @@ impl TransactionManager {
         }
     }
 
-    pub fn batch_put(&self, operations: Vec<(String, Vec<u8>, Hash, u64)>) -> Result<Vec<Result<bool, Error>>, Error> {
-        let mut results = Vec::new();
+    /// Re-address `key` from `old_hash` to `new_hash`, keeping its metadata.
+    ///
+    /// `data` is the encoded object, stored under the new hash unless it
+    /// already exists there. Returns `false` without writing if the key is
+    /// gone or no longer points at `old_hash`.
+    pub fn rehash_key(&self, key: &str, old_hash: &Hash, new_hash: &Hash, data: &[u8]) -> Result<bool, Error> {
+        let _writes = self.db.write_access()?;
+        let keys_tree = self.db.keys_tree();
+        let objects_tree = self.db.objects_tree();
+        let refs_tree = self.db.refs_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+
+        let result = (keys_tree, objects_tree, refs_tree, refcounts_tree, stats_tree).transaction(|(keys_tree, objects_tree, refs_tree, refcounts_tree, stats_tree)| {
+            let Some(bytes) = keys_tree.get(key.as_bytes())? else {
+                return Ok(false);
+            };
+            let mut meta = KeyMeta::from_bytes(&bytes)
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            if meta.hash != *old_hash {
+                return Ok(false);
+            }
+
+            let mut delta = StatsDelta::default();
+            if old_hash != new_hash {
+                let mut old_ref_key = old_hash.as_ref().to_vec();
+                old_ref_key.extend_from_slice(key.as_bytes());
+                refs_tree.remove(old_ref_key)?;
+
+                store_object(objects_tree, new_hash, data, &mut delta)?;
+                refcounts::increment(refcounts_tree, new_hash, self.hash_algorithm)?;
+                release_object(objects_tree, refcounts_tree, old_hash, &mut delta)?;
+
+                let mut ref_key = new_hash.as_ref().to_vec();
+                ref_key.extend_from_slice(key.as_bytes());
+                refs_tree.insert(ref_key, b"1")?;
+            }
+
+            meta.hash = *new_hash;
+            meta.hash_algorithm = self.hash_algorithm;
+            let meta_bytes = meta.to_bytes()
+                .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+            keys_tree.insert(key.as_bytes(), meta_bytes)?;
+
+            delta.apply(stats_tree)?;
+            Ok(true)
+        });
+
+        result.map_err(|e: sled::transaction::TransactionError<Error>| match e {
+            sled::transaction::TransactionError::Abort(e) => e,
+            sled::transaction::TransactionError::Storage(e) => e.into(),
+        })
+    }
+
+    /// Remove an object if no key references it.
+    ///
+    /// The reference count is checked in the same transaction as the removal,
+    /// so a concurrent PUT of the same content either sees the object or
+    /// writes it again. Returns the stored bytes reclaimed, `None` if the
+    /// object is referenced or already gone.
+    pub fn gc_object(&self, hash: &Hash) -> Result<Option<u64>, Error> {
+        let _writes = self.db.write_access()?;
+        let objects_tree = self.db.objects_tree();
+        let refcounts_tree = self.db.refcounts_tree();
+        let stats_tree = self.db.stats_tree();
+
+        let result = (objects_tree, refcounts_tree, stats_tree).transaction(|(objects_tree, refcounts_tree, stats_tree)| {
+            if refcounts::get(refcounts_tree, hash)? > 0 {
+                return Ok(None);
+            }
+            let Some(removed) = objects_tree.remove(hash.as_ref())? else {
+                return Ok(None);
+            };
+            StatsDelta { objects: -1, stored_bytes: -(removed.len() as i64), ..Default::default() }
+                .apply(stats_tree)?;
+            Ok(Some(removed.len() as u64))
+        });
+
+        result.map_err(|e: sled::transaction::TransactionError<Error>| match e {
+            sled::transaction::TransactionError::Abort(e) => e,
+            sled::transaction::TransactionError::Storage(e) => e.into(),
+        })
+    }
+
+    /// Apply `writes` in order in one transaction, so either all of them
+    /// take effect or none does.
+    ///
+    /// Each write sees the ones before it: a precondition is checked against
+    /// the batch's own earlier writes, and a delete of a missing key fails.
+    /// Returns, per write, the hash the key held before it.
+    pub fn apply_batch(&self, writes: &[BatchWrite]) -> Result<Vec<Option<Hash>>, BatchFailure> {
+        let _writes = self.db.write_access().map_err(BatchFailure::from)?;
+        let now = unix_now();
+        let db = &self.db;
+        // Which write aborted the transaction, if one did
+        let failed_at = Cell::new(None);
 
-        for (key, data, hash, size) in operations {
-            match self.put_key_atomic(&key, &data, &hash, size) {
-                Ok(is_new) => results.push(Ok(is_new)),
-                Err(e) => results.push(Err(e)),
+        let result = (db.keys_tree(), db.objects_tree(), db.refs_tree(), db.expiry_tree(), db.refcounts_tree(), db.versions_tree(), db.trash_tree(), db.stats_tree()).transaction(|(keys, objects, refs, expiry, refcounts, versions, trash, stats_tree)| {
+            let trees = KeyTrees { keys, objects, refs, expiry, refcounts, versions, trash };
+            let mut delta = StatsDelta::default();
+            let mut previous = Vec::with_capacity(writes.len());
+
+            for (index, write) in writes.iter().enumerate() {
+                failed_at.set(Some(index));
+                let old_hash = match write {
+                    BatchWrite::Put { key, object, hash, size, algorithm, expires_at, precondition } => {
+                        let write = KeyWrite {
+                            key,
+                            source: ObjectSource::Data(object, *size),
+                            hash: *hash,
+                            algorithm: *algorithm,
+                            expires_at: *expires_at,
+                            precondition,
+                        };
+                        self.write_in(&trees, &write, now, &mut delta)?.0
+                    }
+                    BatchWrite::Link { key, hash, algorithm, expires_at, precondition } => {
+                        let write = KeyWrite {
+                            key,
+                            source: ObjectSource::Existing,
+                            hash: *hash,
+                            algorithm: *algorithm,
+                            expires_at: *expires_at,
+                            precondition,
+                        };
+                        self.write_in(&trees, &write, now, &mut delta)?.0
+                    }
+                    BatchWrite::Delete { key, precondition } => {
+                        let should_delete = |meta: Option<&KeyMeta>| precondition.check(key, meta, now).map(|()| true);
+                        self.delete_in(&trees, key, &should_delete, now, &mut delta)?
+                            .map(|(hash, _)| hash)
+                    }
+                };
+                previous.push(old_hash);
             }
+
+            failed_at.set(None);
+            delta.apply(stats_tree)?;
+            Ok(previous)
+        });
+
+        match result {
+            Ok(previous) => Ok(previous),
+            Err(sled::transaction::TransactionError::Abort(error)) => {
+                Err(BatchFailure { index: failed_at.get(), error })
+            }
+            Err(_) => Err(BatchFailure::from(
+                Error::Conflict("Transaction conflict - please retry".to_string())
+            )),
+        }
+    }
+}
+
+/// One write of an atomic batch, see [`TransactionManager::apply_batch`]
+#[derive(Debug, Clone)]
+pub enum BatchWrite {
+    /// Store `object`, the encoded form of a `size`-byte value, under `key`
+    Put {
+        key: String,
+        object: Vec<u8>,
+        hash: Hash,
+        size: u64,
+        algorithm: HashAlgorithm,
+        expires_at: Option<u64>,
+        precondition: Precondition,
+    },
+    /// Point `key` at the object already stored under `hash`
+    Link {
+        key: String,
+        hash: Hash,
+        algorithm: HashAlgorithm,
+        expires_at: Option<u64>,
+        precondition: Precondition,
+    },
+    Delete {
+        key: String,
+        precondition: Precondition,
+    },
+}
+
+impl BatchWrite {
+    pub fn key(&self) -> &str {
+        match self {
+            BatchWrite::Put { key, .. } | BatchWrite::Link { key, .. } | BatchWrite::Delete { key, .. } => key,
         }
+    }
+}
+
+/// Why an atomic batch was rolled back
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// Position of the write that failed, `None` if the batch failed as a whole
+    pub index: Option<usize>,
+    pub error: Error,
+}
+
+impl From<Error> for BatchFailure {
+    fn from(error: Error) -> Self {
+        Self { index: None, error }
+    }
+}
+
+/// The trees a key write or delete touches, as seen inside one transaction
+struct KeyTrees<'a> {
+    keys: &'a TransactionalTree,
+    objects: &'a TransactionalTree,
+    refs: &'a TransactionalTree,
+    expiry: &'a TransactionalTree,
+    refcounts: &'a TransactionalTree,
+    versions: &'a TransactionalTree,
+    trash: &'a TransactionalTree,
+}
+
+/// A key write, as applied inside a transaction
+#[derive(Clone, Copy)]
+struct KeyWrite<'a> {
+    key: &'a str,
+    source: ObjectSource<'a>,
+    hash: Hash,
+    algorithm: HashAlgorithm,
+    expires_at: Option<u64>,
+    precondition: &'a Precondition,
+}
+
+/// Where the object for a key write comes from
+#[derive(Clone, Copy)]
+enum ObjectSource<'a> {
+    /// Encoded object bytes and the value's size
+    Data(&'a [u8], u64),
+    /// The object already stored under the key's hash
+    Existing,
+}
+
+/// Store `data` under `hash` unless the object already exists (deduplication).
+/// Returns whether the object was written.
+fn store_object(
+    objects_tree: &TransactionalTree,
+    hash: &Hash,
+    data: &[u8],
+    delta: &mut StatsDelta,
+) -> Result<bool, UnabortableTransactionError> {
+    let is_new_object = objects_tree.get(hash.as_ref())?.is_none();
+    if is_new_object {
+        objects_tree.insert(hash.as_ref(), data)?;
+        delta.objects += 1;
+        delta.stored_bytes += data.len() as i64;
+    }
+    Ok(is_new_object)
+}
+
+/// After archiving version `newest` of `key`, drop the one it pushed past
+/// `policy.max_versions`. Versions beyond a lowered limit are left to
+/// [`versions::prune`].
+#[allow(clippy::too_many_arguments)]
+fn prune_excess_version(
+    versions_tree: &TransactionalTree,
+    objects_tree: &TransactionalTree,
+    refcounts_tree: &TransactionalTree,
+    key: &str,
+    newest: u64,
+    policy: &VersionPolicy,
+    delta: &mut StatsDelta,
+) -> Result<(), sled::transaction::ConflictableTransactionError<Error>> {
+    if policy.max_versions == 0 {
+        return Ok(());
+    }
+    let Some(excess) = newest.checked_sub(policy.max_versions).filter(|&v| v > 0) else {
+        return Ok(());
+    };
+    if let Some(record) = versions_tree.remove(versions::version_key(key, excess))? {
+        let (_, meta) = KeyMeta::from_stamped_bytes(&record)
+            .map_err(sled::transaction::ConflictableTransactionError::Abort)?;
+        release_object(objects_tree, refcounts_tree, &meta.hash, delta)?;
+    }
+    Ok(())
+}
+
+/// Remove `key`, its index entries and its reference to the object, whose
+/// metadata the caller already read in the same transaction.
+#[allow(clippy::too_many_arguments)]
+fn remove_key(
+    keys_tree: &TransactionalTree,
+    objects_tree: &TransactionalTree,
+    refs_tree: &TransactionalTree,
+    expiry_tree: &TransactionalTree,
+    refcounts_tree: &TransactionalTree,
+    key: &str,
+    meta: &KeyMeta,
+    delta: &mut StatsDelta,
+) -> Result<(), UnabortableTransactionError> {
+    unlink_key(keys_tree, refs_tree, expiry_tree, key, meta, delta)?;
+    release_object(objects_tree, refcounts_tree, &meta.hash, delta)
+}
 
-        Ok(results)
+/// Remove `key` and its index entries, leaving its reference to the object
+/// to the caller.
+fn unlink_key(
+    keys_tree: &TransactionalTree,
+    refs_tree: &TransactionalTree,
+    expiry_tree: &TransactionalTree,
+    key: &str,
+    meta: &KeyMeta,
+    delta: &mut StatsDelta,
+) -> Result<(), UnabortableTransactionError> {
+    keys_tree.remove(key.as_bytes())?;
+
+    let mut ref_key = meta.hash.as_ref().to_vec();
+    ref_key.extend_from_slice(key.as_bytes());
+    refs_tree.remove(ref_key.as_slice())?;
+
+    if let Some(at) = meta.expires_at {
+        expiry_tree.remove(expiry_index_key(at, key))?;
+    }
+
+    delta.keys -= 1;
+    delta.logical_bytes -= meta.size as i64;
+    Ok(())
+}
+
+/// Drop a reference on `hash`, removing the object with the last one.
+fn release_object(
+    objects_tree: &TransactionalTree,
+    refcounts_tree: &TransactionalTree,
+    hash: &Hash,
+    delta: &mut StatsDelta,
+) -> Result<(), UnabortableTransactionError> {
+    if refcounts::decrement(refcounts_tree, hash)? == 0 {
+        if let Some(removed) = objects_tree.remove(hash.as_ref())? {
+            delta.objects -= 1;
+            delta.stored_bytes -= removed.len() as i64;
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,82 @@
+//! Soft-deleted keys
+//!
+//! With trash enabled, a delete moves the key's metadata into the `trash`
+//! tree instead of releasing its object, so the key disappears from reads
+//! and listings but can be brought back until the retention period passes.
+//! A tombstone is only a reference to an object, like a version.
+//!
+//! Layout: key -> deletion time (u64, big-endian) + `KeyMeta`
+//!
+//! Each key has at most one tombstone; deleting it again replaces the older
+//! one. Keys that had already expired, expiry sweeps and the source of a
+//! rename bypass the trash.
+
+use crate::error::Error;
+use crate::storage::{KeyMeta, KeyRange, StorageDb, TransactionManager};
+
+/// One deleted key
+#[derive(Debug, Clone)]
+pub struct TrashEntry {
+    pub key: String,
+    /// Unix time the key was deleted
+    pub deleted_at: u64,
+    /// Metadata the key had when it was deleted
+    pub meta: KeyMeta,
+}
+
+/// Deleted keys starting with `prefix`, in key order, resuming after
+/// `start_after` if given. Each page seeks to its cursor, so it costs
+/// O(log n + limit) however deep it is.
+pub fn list(db: &StorageDb, prefix: &str, start_after: Option<&str>, limit: usize) -> Result<Vec<TrashEntry>, Error> {
+    let range = KeyRange {
+        prefix: Some(prefix.as_bytes().to_vec()),
+        start_after: start_after.map(|after| after.as_bytes().to_vec()),
+        ..Default::default()
+    };
+    let mut entries = Vec::new();
+    for item in db.scan_trash(&range).take(limit) {
+        let (tree_key, value) = item?;
+        let key = String::from_utf8_lossy(&tree_key).into_owned();
+        let (deleted_at, meta) = KeyMeta::from_stamped_bytes(&value)?;
+        entries.push(TrashEntry { key, deleted_at, meta });
+    }
+    Ok(entries)
+}
+
+/// Outcome of a purge pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeReport {
+    /// Tombstones dropped
+    pub purged: u64,
+    /// Objects freed because the dropped tombstones were their last reference
+    pub freed_objects: u64,
+}
+
+/// Drop keys deleted more than `retention_secs` before `now`.
+///
+/// With a retention of 0 the trash is off, so anything left in it from an
+/// earlier run is purged. Each tombstone is dropped in its own transaction,
+/// which re-checks its deletion time, so the pass can run alongside normal
+/// traffic.
+pub fn purge(db: &StorageDb, retention_secs: u64, now: u64) -> Result<PurgeReport, Error> {
+    let mut report = PurgeReport::default();
+    let deleted_before = now.saturating_sub(retention_secs);
+    let tx_manager = TransactionManager::new(db.clone());
+
+    for item in db.trash_tree().iter() {
+        let (tree_key, value) = item?;
+        let (deleted_at, _) = KeyMeta::from_stamped_bytes(&value)?;
+        if retention_secs > 0 && deleted_at > deleted_before {
+            continue;
+        }
+        let key = String::from_utf8_lossy(&tree_key).into_owned();
+        if let Some(freed) = tx_manager.purge_deleted(&key, deleted_before.max(deleted_at))? {
+            report.purged += 1;
+            if freed {
+                report.freed_objects += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
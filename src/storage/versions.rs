@@ -0,0 +1,199 @@
+//! History of overwritten keys
+//!
+//! For keys under a versioned prefix, an overwrite moves the previous
+//! metadata into the `versions` tree instead of releasing its object, so
+//! older values stay readable and can be restored. A version is only a
+//! reference to an object, so with deduplication an unchanged value costs a
+//! few bytes. Retention limits drop the oldest versions, releasing their
+//! objects as a delete would.
+//!
+//! With `p` the key length (u32, big-endian) followed by the key:
+//! - `p` -> number of the newest archived version (u64, big-endian)
+//! - `p` + version (u64, big-endian) -> archive time (u64, big-endian) + `KeyMeta`
+//!
+//! Versions are numbered from 1 per key; the live value is one past the
+//! newest archived version. History outlives a delete of the key, so a
+//! deleted value can be restored until retention drops it.
+
+use std::sync::Arc;
+
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::error::Error;
+use crate::storage::{KeyMeta, Precondition, StorageDb, TransactionManager};
+use crate::util::hash::Hash;
+
+/// Which keys keep history, and for how long
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionPolicy {
+    /// Key prefixes that keep history; an empty prefix covers every key
+    pub prefixes: Vec<String>,
+    /// Archived versions kept per key, 0 for no limit
+    pub max_versions: u64,
+    /// Seconds an archived version is kept, 0 for no limit
+    pub max_age_secs: u64,
+}
+
+impl VersionPolicy {
+    /// Whether overwrites of `key` are archived
+    pub fn applies_to(&self, key: &str) -> bool {
+        self.prefixes.iter().any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    /// Whether old versions are ever dropped
+    pub fn has_retention(&self) -> bool {
+        self.max_versions > 0 || self.max_age_secs > 0
+    }
+}
+
+/// One entry of a key's history
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub version: u64,
+    pub meta: KeyMeta,
+    /// Unix time the value was replaced, `None` for the live value
+    pub archived_at: Option<u64>,
+}
+
+/// Common prefix of the history entries of `key`
+pub fn history_prefix(key: &str) -> Vec<u8> {
+    let mut prefix = (key.len() as u32).to_be_bytes().to_vec();
+    prefix.extend_from_slice(key.as_bytes());
+    prefix
+}
+
+/// Tree key of archived version `version` of `key`
+pub fn version_key(key: &str, version: u64) -> Vec<u8> {
+    let mut tree_key = history_prefix(key);
+    tree_key.extend_from_slice(&version.to_be_bytes());
+    tree_key
+}
+
+/// Whether a `versions` tree key is the head entry of a key's history
+/// rather than an archived version
+pub fn is_head_entry(tree_key: &[u8]) -> bool {
+    tree_key.get(..4)
+        .and_then(|len| len.try_into().ok())
+        .map(u32::from_be_bytes)
+        .is_some_and(|key_len| tree_key.len() == 4 + key_len as usize)
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    bytes.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
+
+/// Move `meta`, the value `key` is losing, into its history inside a write
+/// transaction. The reference `meta` holds on its object moves with it.
+///
+/// Returns the new version's number.
+pub fn archive(
+    versions_tree: &TransactionalTree,
+    key: &str,
+    meta: &KeyMeta,
+    now: u64,
+) -> Result<u64, ConflictableTransactionError<Error>> {
+    let head_key = history_prefix(key);
+    let version = versions_tree.get(&head_key)?.map_or(0, |v| decode_u64(&v)) + 1;
+    let record = meta.to_stamped_bytes(now).map_err(ConflictableTransactionError::Abort)?;
+    versions_tree.insert(head_key, version.to_be_bytes().to_vec())?;
+    versions_tree.insert(version_key(key, version), record)?;
+    Ok(version)
+}
+
+/// Number of the live value of `key`, whether or not it exists.
+pub fn current_version(db: &StorageDb, key: &str) -> Result<u64, Error> {
+    Ok(db.versions_tree().get(history_prefix(key))?.map_or(0, |v| decode_u64(&v)) + 1)
+}
+
+/// Archived versions of `key`, oldest first.
+pub fn list(db: &StorageDb, key: &str) -> Result<Vec<Version>, Error> {
+    let prefix = history_prefix(key);
+    let mut versions = Vec::new();
+    for item in db.versions_tree().scan_prefix(&prefix) {
+        let (tree_key, value) = item?;
+        // The head entry is the prefix itself
+        if tree_key.len() != prefix.len() + 8 {
+            continue;
+        }
+        let (archived_at, meta) = KeyMeta::from_stamped_bytes(&value)?;
+        versions.push(Version {
+            version: decode_u64(&tree_key[prefix.len()..]),
+            meta,
+            archived_at: Some(archived_at),
+        });
+    }
+    Ok(versions)
+}
+
+/// Archived version `version` of `key`, if it is still kept.
+pub fn get(db: &StorageDb, key: &str, version: u64) -> Result<Option<Version>, Error> {
+    let Some(value) = db.versions_tree().get(version_key(key, version))? else {
+        return Ok(None);
+    };
+    let (archived_at, meta) = KeyMeta::from_stamped_bytes(&value)?;
+    Ok(Some(Version { version, meta, archived_at: Some(archived_at) }))
+}
+
+/// Make archived version `version` of `key` its live value again.
+///
+/// This is an ordinary overwrite: the value being replaced is archived in
+/// turn if `policy` covers the key. The restored key does not expire.
+/// Returns the restored hash and the version number it now has.
+pub fn restore(db: &StorageDb, policy: &Arc<VersionPolicy>, key: &str, version: u64) -> Result<(Hash, u64), Error> {
+    let archived = get(db, key, version)?
+        .ok_or_else(|| Error::NotFound(format!("Version {} of key '{}' not found", version, key)))?;
+
+    let tx_manager = TransactionManager::new(db.clone())
+        .with_hash_algorithm(archived.meta.hash_algorithm)
+        .with_versioning(policy.clone());
+    tx_manager.link_key_if(key, &archived.meta.hash, None, &Precondition::Unconditional)?;
+    Ok((archived.meta.hash, current_version(db, key)?))
+}
+
+/// Outcome of a retention pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Versions dropped
+    pub removed: u64,
+    /// Objects freed because the dropped versions were their last reference
+    pub freed_objects: u64,
+}
+
+/// Drop versions beyond `policy`'s limits at `now`.
+///
+/// Each version is dropped in its own transaction, which re-checks that it
+/// still exists, so the pass can run alongside normal traffic.
+pub fn prune(db: &StorageDb, policy: &VersionPolicy, now: u64) -> Result<PruneReport, Error> {
+    let mut report = PruneReport::default();
+    if !policy.has_retention() {
+        return Ok(report);
+    }
+    let tx_manager = TransactionManager::new(db.clone());
+
+    for item in db.versions_tree().iter() {
+        let (tree_key, value) = item?;
+        // Only head entries start a key's history
+        if !is_head_entry(&tree_key) {
+            continue;
+        }
+        let newest = decode_u64(&value);
+        let key = String::from_utf8_lossy(&tree_key[4..]).into_owned();
+
+        for version in list(db, &key)? {
+            let too_many = policy.max_versions > 0 && version.version + policy.max_versions <= newest;
+            let too_old = policy.max_age_secs > 0
+                && version.archived_at.is_some_and(|at| at.saturating_add(policy.max_age_secs) <= now);
+            if !too_many && !too_old {
+                continue;
+            }
+            if let Some(freed) = tx_manager.drop_version(&key, version.version)? {
+                report.removed += 1;
+                if freed {
+                    report.freed_objects += 1;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
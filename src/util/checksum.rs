@@ -0,0 +1,193 @@
+//! Client-supplied upload checksums
+//!
+//! A PUT may say what its body should hash to, via `X-Expected-Hash` (the
+//! server's own content hash, hex), `Content-MD5` (base64 MD5) or `Digest`
+//! (RFC 3230, `sha-256=<base64>` and/or `md5=<base64>`). The body is checked
+//! against every one given before anything is committed, so an upload
+//! truncated in transit is rejected instead of stored.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hyper::HeaderMap;
+use md5::{Digest, Md5};
+use ring::digest::{Context, SHA256};
+
+use crate::error::Error;
+use crate::util::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expected {
+    Hash(Hash),
+    Md5([u8; 16]),
+    Sha256([u8; 32]),
+}
+
+/// Checksums the client expects the body to match
+#[derive(Debug, Clone, Default)]
+pub struct ExpectedChecksums {
+    /// Each expectation with the header it came from
+    checks: Vec<(&'static str, Expected)>,
+}
+
+impl ExpectedChecksums {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, Error> {
+        let header = |name: &str| -> Result<Option<&str>, Error> {
+            headers
+                .get(name)
+                .map(|v| v.to_str().map_err(|_| Error::InvalidRequest(format!("Invalid {} header", name))))
+                .transpose()
+        };
+        Self::parse(header("X-Expected-Hash")?, header("Content-MD5")?, header("Digest")?)
+    }
+
+    /// Parse the three header values; batch operations carry them as fields.
+    pub fn parse(
+        expected_hash: Option<&str>,
+        content_md5: Option<&str>,
+        digest: Option<&str>,
+    ) -> Result<Self, Error> {
+        let mut checks = Vec::new();
+
+        if let Some(value) = expected_hash {
+            let hash = Hash::from_hex(value.trim().trim_matches('"'))
+                .ok_or_else(|| Error::InvalidRequest(format!("Invalid X-Expected-Hash: {}", value)))?;
+            checks.push(("X-Expected-Hash", Expected::Hash(hash)));
+        }
+
+        if let Some(value) = content_md5 {
+            let md5 = decode_base64::<16>(value)
+                .ok_or_else(|| Error::InvalidRequest(format!("Invalid Content-MD5: {}", value)))?;
+            checks.push(("Content-MD5", Expected::Md5(md5)));
+        }
+
+        if let Some(value) = digest {
+            let before = checks.len();
+            for item in value.split(',') {
+                let Some((algorithm, encoded)) = item.trim().split_once('=') else {
+                    return Err(Error::InvalidRequest(format!("Invalid Digest: {}", value)));
+                };
+                let invalid = || Error::InvalidRequest(format!("Invalid Digest {}: {}", algorithm, encoded));
+                match algorithm.trim().to_ascii_lowercase().as_str() {
+                    "sha-256" => checks.push(("Digest", Expected::Sha256(decode_base64(encoded).ok_or_else(invalid)?))),
+                    "md5" => checks.push(("Digest", Expected::Md5(decode_base64(encoded).ok_or_else(invalid)?))),
+                    _ => {}
+                }
+            }
+            // Silently accepting an unverifiable Digest would defeat its purpose
+            if checks.len() == before {
+                return Err(Error::InvalidRequest(format!(
+                    "Digest has no supported algorithm (sha-256, md5): {}", value
+                )));
+            }
+        }
+
+        Ok(Self { checks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    /// Start checking a body; digests are only computed if something needs them.
+    pub fn verifier(self) -> ChecksumVerifier {
+        let wants = |f: fn(&Expected) -> bool| self.checks.iter().any(|(_, e)| f(e));
+        ChecksumVerifier {
+            md5: wants(|e| matches!(e, Expected::Md5(_))).then(Md5::new),
+            sha256: wants(|e| matches!(e, Expected::Sha256(_))).then(|| Context::new(&SHA256)),
+            expected: self,
+        }
+    }
+}
+
+/// Digests of a body as it streams in, checked against the expectations
+pub struct ChecksumVerifier {
+    expected: ExpectedChecksums,
+    md5: Option<Md5>,
+    sha256: Option<Context>,
+}
+
+impl ChecksumVerifier {
+    pub fn update(&mut self, data: &[u8]) {
+        if let Some(md5) = &mut self.md5 {
+            md5.update(data);
+        }
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(data);
+        }
+    }
+
+    /// Check the finished body, whose content hash is `hash`.
+    ///
+    /// Fails with `400 Bad Request` naming the header and both values.
+    pub fn verify(self, hash: &Hash) -> Result<(), Error> {
+        let md5: Option<[u8; 16]> = self.md5.map(|md5| md5.finalize().into());
+        let sha256 = self.sha256.map(|ctx| ctx.finish());
+
+        for (header, expected) in &self.expected.checks {
+            let (expected, actual) = match expected {
+                Expected::Hash(h) if h != hash => (h.to_hex_string(), hash.to_hex_string()),
+                Expected::Md5(m) if Some(m) != md5.as_ref() => {
+                    (BASE64.encode(m), BASE64.encode(md5.unwrap_or_default()))
+                }
+                Expected::Sha256(s) if Some(&s[..]) != sha256.as_ref().map(|d| d.as_ref()) => {
+                    (BASE64.encode(s), sha256.as_ref().map(|d| BASE64.encode(d)).unwrap_or_default())
+                }
+                _ => continue,
+            };
+            return Err(Error::InvalidRequest(format!(
+                "{} mismatch: expected {}, computed {}", header, expected, actual
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn decode_base64<const N: usize>(value: &str) -> Option<[u8; N]> {
+    BASE64.decode(value.trim()).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hash::HashAlgorithm;
+
+    fn check(checks: &ExpectedChecksums, data: &[u8]) -> Result<(), Error> {
+        let mut verifier = checks.clone().verifier();
+        verifier.update(data);
+        verifier.verify(&HashAlgorithm::Xxh3.compute(data))
+    }
+
+    #[test]
+    fn test_expected_hash() {
+        let hash = HashAlgorithm::Xxh3.compute(b"payload").to_hex_string();
+        let checks = ExpectedChecksums::parse(Some(&format!("\"{}\"", hash)), None, None).unwrap();
+        assert!(check(&checks, b"payload").is_ok());
+
+        let err = check(&checks, b"payl").unwrap_err().to_string();
+        assert!(err.contains("X-Expected-Hash mismatch"));
+        assert!(err.contains(&hash));
+        assert!(err.contains(&HashAlgorithm::Xxh3.compute(b"payl").to_hex_string()));
+
+        assert!(ExpectedChecksums::parse(Some("xyz"), None, None).is_err());
+    }
+
+    #[test]
+    fn test_content_md5_and_digest() {
+        // md5("abc") and sha-256("abc")
+        let md5 = "kAFQmDzST7DWlj99KOF/cg==";
+        let sha256 = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+
+        let checks = ExpectedChecksums::parse(None, Some(md5), None).unwrap();
+        assert!(check(&checks, b"abc").is_ok());
+        assert!(check(&checks, b"abd").unwrap_err().to_string().contains("Content-MD5 mismatch"));
+
+        let digest = format!("SHA-256={}, md5={}", sha256, md5);
+        let checks = ExpectedChecksums::parse(None, None, Some(&digest)).unwrap();
+        assert!(check(&checks, b"abc").is_ok());
+        assert!(check(&checks, b"ab").unwrap_err().to_string().contains("Digest mismatch"));
+
+        assert!(ExpectedChecksums::parse(None, None, Some("sha-512=AAAA")).is_err());
+        assert!(ExpectedChecksums::parse(None, Some("not base64"), None).is_err());
+        assert!(ExpectedChecksums::parse(None, None, None).unwrap().is_empty());
+    }
+}
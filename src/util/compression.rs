@@ -1,5 +1,165 @@
+//! Stored object encoding
+//!
+//! Every object written to the `objects` tree starts with a fixed header:
+//!
+//! | Bytes    | Field                                  |
+//! |----------|----------------------------------------|
+//! | `0..4`   | magic `KVOB`                           |
+//! | `4`      | format version (1)                     |
+//! | `5`      | codec: 0 = raw, 1 = zstd               |
+//! | `6..8`   | reserved, zero                         |
+//! | `8..16`  | original length, little-endian         |
+//! | `16..24` | xxHash3-64 of the payload, little-endian |
+//!
+//! Values are stored raw when compression does not make them smaller.
+//! Objects written before the header existed have no valid header; they are
+//! still decoded the old way (zstd if they start with the zstd magic number)
+//! until [`upgrade_legacy`] rewrites them.
+
 use crate::error::Error;
-use zstd::stream::{encode_all, decode_all};
+use std::io::{Cursor, Read, Write};
+use twox_hash::XxHash3_64;
+use zstd::stream::{decode_all, read::Decoder, write::Encoder};
+
+/// Size of the object header
+pub const HEADER_LEN: usize = 24;
+
+const MAGIC: &[u8; 4] = b"KVOB";
+const FORMAT_VERSION: u8 = 1;
+const ZSTD_MAGIC: u32 = 0xFD2FB528;
+
+/// Streamed values are compressed and kept raw side by side up to this size,
+/// then whichever encoding is winning is kept
+const STREAM_SAMPLE_SIZE: u64 = 256 * 1024;
+
+/// How an object's payload is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Raw = 0,
+    Zstd = 1,
+}
+
+/// Parsed object header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectHeader {
+    pub codec: Codec,
+    /// Length of the value before encoding
+    pub original_len: u64,
+    /// xxHash3-64 of the payload following the header
+    pub checksum: u64,
+}
+
+impl ObjectHeader {
+    /// Parses the header at the start of `data`, `None` for legacy objects.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let header = data.get(..HEADER_LEN)?;
+        if &header[0..4] != MAGIC || header[4] != FORMAT_VERSION || header[6..8] != [0, 0] {
+            return None;
+        }
+        let codec = match header[5] {
+            0 => Codec::Raw,
+            1 => Codec::Zstd,
+            _ => return None,
+        };
+        Some(Self {
+            codec,
+            original_len: u64::from_le_bytes(header[8..16].try_into().ok()?),
+            checksum: u64::from_le_bytes(header[16..24].try_into().ok()?),
+        })
+    }
+
+    fn write_to(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(MAGIC);
+        out[4] = FORMAT_VERSION;
+        out[5] = self.codec as u8;
+        out[6..8].copy_from_slice(&[0, 0]);
+        out[8..16].copy_from_slice(&self.original_len.to_le_bytes());
+        out[16..24].copy_from_slice(&self.checksum.to_le_bytes());
+    }
+}
+
+/// Fills in the header of `buf`, whose first `HEADER_LEN` bytes are reserved.
+fn seal(mut buf: Vec<u8>, codec: Codec, original_len: u64) -> Vec<u8> {
+    let header = ObjectHeader {
+        codec,
+        original_len,
+        checksum: XxHash3_64::oneshot(&buf[HEADER_LEN..]),
+    };
+    header.write_to(&mut buf[..HEADER_LEN]);
+    buf
+}
+
+fn frame_raw(data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + data.len());
+    buf.resize(HEADER_LEN, 0);
+    buf.extend_from_slice(data);
+    seal(buf, Codec::Raw, data.len() as u64)
+}
+
+/// An object split into its header and payload.
+enum Stored<'a> {
+    Framed(ObjectHeader, &'a [u8]),
+    Legacy(&'a [u8]),
+}
+
+/// Splits `data` and verifies the payload checksum.
+///
+/// # Errors
+/// Returns `Error::Corrupt` if the payload does not match the header.
+fn inspect(data: &[u8]) -> Result<Stored<'_>, Error> {
+    let Some(header) = ObjectHeader::parse(data) else {
+        return Ok(Stored::Legacy(data));
+    };
+    let payload = &data[HEADER_LEN..];
+    if XxHash3_64::oneshot(payload) != header.checksum {
+        return Err(Error::Corrupt("Object checksum mismatch".to_string()));
+    }
+    if header.codec == Codec::Raw && payload.len() as u64 != header.original_len {
+        return Err(Error::Corrupt("Object length does not match its header".to_string()));
+    }
+    Ok(Stored::Framed(header, payload))
+}
+
+/// Verifies a stored object's checksum.
+///
+/// Returns its header, or `None` for a legacy object, which has no checksum.
+pub fn verify(data: &[u8]) -> Result<Option<ObjectHeader>, Error> {
+    match inspect(data)? {
+        Stored::Framed(header, _) => Ok(Some(header)),
+        Stored::Legacy(_) => Ok(None),
+    }
+}
+
+/// Rewrites a legacy headerless object with a header, keeping its payload.
+/// Returns `None` if the object already has one.
+pub fn upgrade_legacy(data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    if ObjectHeader::parse(data).is_some() {
+        return Ok(None);
+    }
+
+    if is_legacy_zstd(data) {
+        // Count the decoded length without holding the value
+        if let Ok(original_len) = Decoder::new(data).and_then(|mut d| std::io::copy(&mut d, &mut std::io::sink())) {
+            let mut buf = Vec::with_capacity(HEADER_LEN + data.len());
+            buf.resize(HEADER_LEN, 0);
+            buf.extend_from_slice(data);
+            return Ok(Some(seal(buf, Codec::Zstd, original_len)));
+        }
+    }
+    Ok(Some(frame_raw(data)))
+}
+
+fn is_legacy_zstd(data: &[u8]) -> bool {
+    data.len() >= 4 && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == ZSTD_MAGIC
+}
+
+fn compression_error(e: std::io::Error) -> Error {
+    Error::Compression(format!("Compression failed: {}", e))
+}
+
+fn decompression_error(e: std::io::Error) -> Error {
+    Error::Compression(format!("Decompression failed: {}", e))
+}
 
 pub struct Compressor {
     level: i32,
@@ -14,37 +174,99 @@ impl Compressor {
         }
     }
 
+    /// Encodes a value for storage, compressed only if that makes it smaller.
     pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        // Level 0 = compression disabled
-        if self.level == 0 {
-            return Ok(data.to_vec());
+        // Level 0 = compression disabled; very small data isn't worth the overhead
+        if !self.should_compress(data.len()) {
+            return Ok(frame_raw(data));
         }
 
-        // Skip compression for very small data (overhead not worth it)
-        if data.len() < self.min_compress_size {
-            return Ok(data.to_vec());
-        }
+        let mut encoder = Encoder::new(vec![0u8; HEADER_LEN], self.level).map_err(compression_error)?;
+        encoder.write_all(data).map_err(compression_error)?;
+        let buf = encoder.finish().map_err(compression_error)?;
 
-        encode_all(data, self.level)
-            .map_err(|e| Error::Compression(format!("Compression failed: {}", e)))
+        if buf.len() - HEADER_LEN >= data.len() {
+            return Ok(frame_raw(data));
+        }
+        Ok(seal(buf, Codec::Zstd, data.len() as u64))
     }
 
+    /// Decodes a stored object.
+    ///
+    /// # Errors
+    /// Returns `Error::Corrupt` if the object fails its checksum.
     pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-        // Quick check: if data doesn't look like zstd, return as-is
-        if data.len() < 4 {
-            return Ok(data.to_vec());
+        match inspect(data)? {
+            Stored::Framed(header, payload) => match header.codec {
+                Codec::Raw => Ok(payload.to_vec()),
+                Codec::Zstd => {
+                    let out = decode_all(payload).map_err(decompression_error)?;
+                    if out.len() as u64 != header.original_len {
+                        return Err(Error::Corrupt("Object length does not match its header".to_string()));
+                    }
+                    Ok(out)
+                }
+            },
+            Stored::Legacy(data) => Ok(legacy_decompress(data)),
         }
+    }
 
-        // Check for zstd magic number to avoid unnecessary decompression attempts
-        let magic = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-        if magic != 0xFD2FB528 {
-            return Ok(data.to_vec());
+    /// Decompresses only the first `len` bytes of the value.
+    ///
+    /// Used for range reads so a request for the head of a large blob does not
+    /// inflate the whole object. Data stored uncompressed is sliced directly.
+    pub fn decompress_prefix(&self, data: &[u8], len: u64) -> Result<Vec<u8>, Error> {
+        let (payload, compressed) = match inspect(data)? {
+            Stored::Framed(header, payload) => (payload, header.codec == Codec::Zstd),
+            Stored::Legacy(data) => (data, is_legacy_zstd(data)),
+        };
+        if !compressed {
+            return Ok(payload[..payload.len().min(len as usize)].to_vec());
         }
 
-        // Try to decompress - if it fails, return as-is (handles uncompressed data)
-        match decode_all(data) {
+        let decoded = Decoder::new(payload).and_then(|decoder| {
+            let mut out = Vec::with_capacity(len.min(64 * 1024 * 1024) as usize);
+            decoder.take(len).read_to_end(&mut out)?;
+            Ok(out)
+        });
+
+        match decoded {
             Ok(result) => Ok(result),
-            Err(_) => Ok(data.to_vec()),
+            Err(e) if ObjectHeader::parse(data).is_some() => Err(decompression_error(e)),
+            // Same fallback as legacy decompress: undecodable data was stored as-is
+            Err(_) => Ok(payload[..payload.len().min(len as usize)].to_vec()),
+        }
+    }
+
+    /// Returns a reader over the decompressed value, for streaming responses.
+    ///
+    /// The checksum is verified before any bytes are produced.
+    pub fn reader<D>(&self, data: D) -> Result<Box<dyn Read + Send>, Error>
+    where
+        D: AsRef<[u8]> + Send + 'static,
+    {
+        let (offset, compressed) = match inspect(data.as_ref())? {
+            Stored::Framed(header, _) => (HEADER_LEN as u64, header.codec == Codec::Zstd),
+            Stored::Legacy(bytes) => (0, is_legacy_zstd(bytes)),
+        };
+
+        let mut cursor = Cursor::new(data);
+        cursor.set_position(offset);
+        if !compressed {
+            return Ok(Box::new(cursor));
+        }
+
+        let decoder = Decoder::new(cursor).map_err(decompression_error)?;
+        Ok(Box::new(decoder))
+    }
+
+    /// Starts an incremental compression, for values received in chunks.
+    pub fn stream(&self) -> StreamCompressor {
+        StreamCompressor {
+            level: self.level,
+            min_compress_size: self.min_compress_size,
+            len: 0,
+            state: StreamState::Buffering(vec![0u8; HEADER_LEN]),
         }
     }
 
@@ -54,6 +276,98 @@ impl Compressor {
     }
 }
 
+/// Decodes a headerless object written before the object header existed.
+fn legacy_decompress(data: &[u8]) -> Vec<u8> {
+    if !is_legacy_zstd(data) {
+        return data.to_vec();
+    }
+    // Undecodable data was stored as-is
+    decode_all(data).unwrap_or_else(|_| data.to_vec())
+}
+
+/// Incremental counterpart of [`Compressor::compress`].
+///
+/// Input below the compression threshold is buffered as-is. Past it, the
+/// value is compressed as it arrives while the raw bytes are also kept, up to
+/// `STREAM_SAMPLE_SIZE`; then only the smaller encoding is continued, so
+/// incompressible values are stored raw without buffering them twice.
+pub struct StreamCompressor {
+    level: i32,
+    min_compress_size: usize,
+    len: u64,
+    state: StreamState,
+}
+
+/// Output buffers reserve `HEADER_LEN` leading bytes for the header
+enum StreamState {
+    Buffering(Vec<u8>),
+    Sampling { raw: Vec<u8>, encoder: Encoder<'static, Vec<u8>> },
+    Compressing(Encoder<'static, Vec<u8>>),
+    Raw(Vec<u8>),
+}
+
+impl StreamCompressor {
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.len += data.len() as u64;
+
+        // An error abandons the value, so the placeholder is never finished
+        let state = std::mem::replace(&mut self.state, StreamState::Raw(Vec::new()));
+        self.state = match state {
+            StreamState::Buffering(mut raw) => {
+                raw.extend_from_slice(data);
+                if self.level > 0 && raw.len() - HEADER_LEN >= self.min_compress_size {
+                    let mut encoder = Encoder::new(vec![0u8; HEADER_LEN], self.level).map_err(compression_error)?;
+                    encoder.write_all(&raw[HEADER_LEN..]).map_err(compression_error)?;
+                    StreamState::Sampling { raw, encoder }
+                } else {
+                    StreamState::Buffering(raw)
+                }
+            }
+            StreamState::Sampling { mut raw, mut encoder } => {
+                raw.extend_from_slice(data);
+                encoder.write_all(data).map_err(compression_error)?;
+                if self.len < STREAM_SAMPLE_SIZE {
+                    StreamState::Sampling { raw, encoder }
+                } else {
+                    encoder.flush().map_err(compression_error)?;
+                    if encoder.get_ref().len() < raw.len() {
+                        StreamState::Compressing(encoder)
+                    } else {
+                        StreamState::Raw(raw)
+                    }
+                }
+            }
+            StreamState::Compressing(mut encoder) => {
+                encoder.write_all(data).map_err(compression_error)?;
+                StreamState::Compressing(encoder)
+            }
+            StreamState::Raw(mut raw) => {
+                raw.extend_from_slice(data);
+                StreamState::Raw(raw)
+            }
+        };
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, Error> {
+        match self.state {
+            StreamState::Buffering(raw) | StreamState::Raw(raw) => Ok(seal(raw, Codec::Raw, self.len)),
+            StreamState::Sampling { raw, encoder } => {
+                let compressed = encoder.finish().map_err(compression_error)?;
+                if compressed.len() < raw.len() {
+                    Ok(seal(compressed, Codec::Zstd, self.len))
+                } else {
+                    Ok(seal(raw, Codec::Raw, self.len))
+                }
+            }
+            StreamState::Compressing(encoder) => {
+                let compressed = encoder.finish().map_err(compression_error)?;
+                Ok(seal(compressed, Codec::Zstd, self.len))
+            }
+        }
+    }
+}
+
 impl Default for Compressor {
     fn default() -> Self {
         Self::new(1)
@@ -86,8 +400,9 @@ mod tests {
         let decompressed = compressor.decompress(&compressed).unwrap();
 
         assert_eq!(original.to_vec(), decompressed);
-        // Small data should not be compressed (returned as-is)
-        assert_eq!(original.to_vec(), compressed);
+        // Small data should not be compressed (stored raw after the header)
+        assert_eq!(ObjectHeader::parse(&compressed).unwrap().codec, Codec::Raw);
+        assert_eq!(original.to_vec(), &compressed[HEADER_LEN..]);
     }
 
     #[test]
@@ -97,7 +412,7 @@ mod tests {
         // Small data (< 1KB) should not be compressed
         let small_data = b"x".repeat(100);
         let compressed = compressor.compress(&small_data).unwrap();
-        assert_eq!(small_data.len(), compressed.len()); // Same size = not compressed
+        assert_eq!(small_data.len() + HEADER_LEN, compressed.len()); // Same size = not compressed
 
         // Large data (> 1KB) should be compressed
         let large_data = b"hello world, this is a test. ".repeat(100);
@@ -105,6 +420,48 @@ mod tests {
         assert!(compressed.len() < large_data.len()); // Smaller = compressed
     }
 
+    #[test]
+    fn test_decompress_prefix() {
+        let compressor = Compressor::new(1);
+        let original = b"0123456789".repeat(1000);
+        let compressed = compressor.compress(&original).unwrap();
+
+        assert_eq!(compressor.decompress_prefix(&compressed, 15).unwrap(), &original[..15]);
+        assert_eq!(compressor.decompress_prefix(&compressed, 1_000_000).unwrap(), original);
+
+        // Uncompressed values are sliced
+        assert_eq!(compressor.decompress_prefix(b"small value", 5).unwrap(), b"small");
+    }
+
+    #[test]
+    fn test_stream_compress_roundtrip() {
+        let compressor = Compressor::new(1);
+        // Long enough to get past the raw-or-compressed sample
+        let original = b"streamed chunk of data, ".repeat(20_000);
+
+        let mut stream = compressor.stream();
+        for chunk in original.chunks(100) {
+            stream.write(chunk).unwrap();
+        }
+        let compressed = stream.finish().unwrap();
+        assert!(compressed.len() < original.len());
+        assert_eq!(compressor.decompress(&compressed).unwrap(), original);
+
+        let mut decompressed = Vec::new();
+        compressor.reader(compressed).unwrap().read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, original);
+    }
+
+    #[test]
+    fn test_stream_compress_small_data() {
+        let compressor = Compressor::new(1);
+        let mut stream = compressor.stream();
+        stream.write(b"hi").unwrap();
+        let stored = stream.finish().unwrap();
+        assert_eq!(stored, Compressor::new(1).compress(b"hi").unwrap());
+        assert_eq!(&stored[HEADER_LEN..], b"hi");
+    }
+
     #[test]
     fn test_decompress_uncompressed() {
         let compressor = Compressor::new(1);
@@ -114,4 +471,75 @@ mod tests {
         let result = compressor.decompress(data).unwrap();
         assert_eq!(data.to_vec(), result);
     }
+
+    #[test]
+    fn test_zstd_magic_value_roundtrip() {
+        // Small raw value that begins with the zstd frame magic number
+        let compressor = Compressor::new(1);
+        let original = [0x28, 0xB5, 0x2F, 0xFD, 1, 2, 3];
+        let stored = compressor.compress(&original).unwrap();
+        assert_eq!(compressor.decompress(&stored).unwrap(), original);
+        assert_eq!(compressor.decompress_prefix(&stored, 5).unwrap(), &original[..5]);
+    }
+
+    #[test]
+    fn test_incompressible_stored_raw() {
+        let compressor = Compressor::new(3);
+        // xorshift noise does not compress
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let noise: Vec<u8> = (0..STREAM_SAMPLE_SIZE * 2)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let stored = compressor.compress(&noise[..4096]).unwrap();
+        assert_eq!(ObjectHeader::parse(&stored).unwrap().codec, Codec::Raw);
+        assert_eq!(stored.len(), 4096 + HEADER_LEN);
+
+        // Streaming settles on raw after the sample and keeps a single copy
+        let mut stream = compressor.stream();
+        for chunk in noise.chunks(8192) {
+            stream.write(chunk).unwrap();
+        }
+        assert!(matches!(stream.state, StreamState::Raw(_)));
+        let streamed = stream.finish().unwrap();
+        assert_eq!(ObjectHeader::parse(&streamed).unwrap().codec, Codec::Raw);
+        assert_eq!(compressor.decompress(&streamed).unwrap(), noise);
+    }
+
+    #[test]
+    fn test_corrupt_object_detected() {
+        let compressor = Compressor::new(1);
+        let mut stored = compressor.compress(&b"checksummed value ".repeat(100)).unwrap();
+        let last = stored.len() - 1;
+        stored[last] ^= 0xFF;
+
+        assert!(matches!(compressor.decompress(&stored), Err(Error::Corrupt(_))));
+        assert!(matches!(compressor.decompress_prefix(&stored, 4), Err(Error::Corrupt(_))));
+        assert!(matches!(compressor.reader(stored.clone()), Err(Error::Corrupt(_))));
+        assert!(verify(&stored).is_err());
+    }
+
+    #[test]
+    fn test_legacy_objects_readable_and_upgraded() {
+        let compressor = Compressor::new(1);
+        let original = b"legacy value, ".repeat(100);
+        let legacy = zstd::stream::encode_all(&original[..], 1).unwrap();
+
+        assert_eq!(compressor.decompress(&legacy).unwrap(), original);
+        assert_eq!(verify(&legacy).unwrap(), None);
+
+        let upgraded = upgrade_legacy(&legacy).unwrap().unwrap();
+        let header = verify(&upgraded).unwrap().unwrap();
+        assert_eq!((header.codec, header.original_len), (Codec::Zstd, original.len() as u64));
+        assert_eq!(compressor.decompress(&upgraded).unwrap(), original);
+        assert!(upgrade_legacy(&upgraded).unwrap().is_none());
+
+        let raw = upgrade_legacy(b"plain").unwrap().unwrap();
+        assert_eq!(compressor.decompress(&raw).unwrap(), b"plain");
+    }
 }
@@ -1,9 +1,10 @@
 use bytes::Bytes;
+use ring::digest::{Context, SHA256};
 use serde::{Deserialize, Serialize};
 
 pub type XxHash128 = [u8; 16];
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Hash(pub XxHash128);
 
 impl AsRef<[u8]> for Hash {
@@ -17,11 +18,9 @@ impl Hash {
     /// Compute xxHash3-128 - fast non-cryptographic 128-bit hash
     #[inline]
     pub fn compute(data: &[u8]) -> Self {
-        use twox_hash::XxHash3_128;
-        let mut hasher = XxHash3_128::default();
-        hasher.write(data);
-        let result = hasher.finish_128();
-        Hash(result.to_be_bytes())
+        let mut hasher = Hasher::new();
+        hasher.update(data);
+        hasher.finalize()
     }
 
     #[inline]
@@ -38,6 +37,129 @@ impl Hash {
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
+
+    /// Parse a hash from its hex representation (as returned in `X-Hash`)
+    pub fn from_hex(s: &str) -> Option<Self> {
+        let mut bytes = [0u8; 16];
+        hex::decode_to_slice(s, &mut bytes).ok()?;
+        Some(Hash(bytes))
+    }
+
+    /// Read a hash from raw bytes, e.g. an `objects` tree key
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Hash)
+    }
+}
+
+/// Content hash used to address objects
+///
+/// Every hash is 16 bytes; SHA-256 is truncated to its first 128 bits so all
+/// algorithms share the same object key layout. That leaves about 64 bits of
+/// collision resistance (the birthday bound), so no address is trusted on
+/// its own: every dedup hit is byte-compared, see `storage::dedup`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// xxHash3-128: fast, but not collision-resistant against crafted input
+    #[default]
+    Xxh3,
+    /// SHA-256 truncated to 128 bits: preimage-resistant, but a collision
+    /// takes only about 2^64 work
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Name reported in `X-Hash-Algorithm` and listings
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Xxh3 => "xxhash3",
+            HashAlgorithm::Sha256 => "sha256-128",
+        }
+    }
+
+    /// Parse a configured algorithm name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "xxhash3" | "xxh3" | "xxhash3-128" => Some(HashAlgorithm::Xxh3),
+            "sha256" | "sha-256" | "sha256-128" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// One-byte tag stored on disk next to an object's reference count
+    pub fn id(&self) -> u8 {
+        match self {
+            HashAlgorithm::Xxh3 => 0,
+            HashAlgorithm::Sha256 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(HashAlgorithm::Xxh3),
+            1 => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_cryptographic(&self) -> bool {
+        *self != HashAlgorithm::Xxh3
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Xxh3 => Hasher::new(),
+            HashAlgorithm::Sha256 => Hasher(HasherState::Sha256(Context::new(&SHA256))),
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> Hash {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Incremental content hash, for data that arrives in chunks.
+/// Defaults to xxHash3-128.
+pub struct Hasher(HasherState);
+
+enum HasherState {
+    Xxh3(twox_hash::XxHash3_128),
+    Sha256(Context),
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self(HasherState::Xxh3(twox_hash::XxHash3_128::new()))
+    }
+}
+
+impl Hasher {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HasherState::Xxh3(h) => h.write(data),
+            HasherState::Sha256(ctx) => ctx.update(data),
+        }
+    }
+
+    #[inline]
+    pub fn finalize(self) -> Hash {
+        match self.0 {
+            HasherState::Xxh3(h) => Hash(h.finish_128().to_be_bytes()),
+            HasherState::Sha256(ctx) => {
+                let mut bytes = [0u8; 16];
+                bytes.copy_from_slice(&ctx.finish().as_ref()[..16]);
+                Hash(bytes)
+            }
+        }
+    }
 }
 
 pub fn hash_bytes(data: &[u8]) -> Hash {
@@ -87,4 +209,44 @@ mod tests {
         let hex = hash.to_hex_string();
         assert_eq!(hex.len(), 32); // 16 bytes = 32 hex chars
     }
+
+    #[test]
+    fn test_hasher_incremental() {
+        let mut hasher = Hasher::new();
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert_eq!(hasher.finalize(), Hash::compute(b"hello world"));
+    }
+
+    #[test]
+    fn test_hash_from_hex_roundtrip() {
+        let hash = Hash::compute(b"test");
+        assert_eq!(Hash::from_hex(&hash.to_hex_string()), Some(hash));
+        assert_eq!(Hash::from_hex("not-hex"), None);
+        assert_eq!(Hash::from_hex("abcd"), None);
+    }
+
+    #[test]
+    fn test_sha256_algorithm() {
+        let hash = HashAlgorithm::Sha256.compute(b"abc");
+        // First 128 bits of SHA-256("abc")
+        assert_eq!(hash.to_hex_string(), "ba7816bf8f01cfea414140de5dae2223");
+
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        assert_eq!(hasher.finalize(), hash);
+
+        assert_eq!(HashAlgorithm::Xxh3.compute(b"abc"), Hash::compute(b"abc"));
+    }
+
+    #[test]
+    fn test_hash_algorithm_parse() {
+        assert_eq!(HashAlgorithm::parse("SHA256"), Some(HashAlgorithm::Sha256));
+        assert_eq!(HashAlgorithm::parse("xxhash3"), Some(HashAlgorithm::Xxh3));
+        assert_eq!(HashAlgorithm::parse("md5"), None);
+        for algorithm in [HashAlgorithm::Xxh3, HashAlgorithm::Sha256] {
+            assert_eq!(HashAlgorithm::parse(algorithm.name()), Some(algorithm));
+        }
+    }
 }
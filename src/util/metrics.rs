@@ -5,10 +5,19 @@ pub struct Metrics {
     pub keys_total: AtomicU64,
     pub objects_total: AtomicU64,
     pub bytes_total: AtomicU64,
+    pub logical_bytes_total: AtomicU64,
     pub puts_total: AtomicU64,
     pub gets_total: AtomicU64,
     pub deletes_total: AtomicU64,
     pub dedup_hits: AtomicU64,
+    pub expired_total: AtomicU64,
+    pub gc_reclaimed_objects: AtomicU64,
+    pub gc_reclaimed_bytes: AtomicU64,
+    pub scrub_objects: AtomicU64,
+    pub scrub_bytes: AtomicU64,
+    pub scrub_corrupt_found: AtomicU64,
+    pub scrub_passes: AtomicU64,
+    pub scrub_corrupt_objects: AtomicU64,
 }
 
 impl Metrics {
@@ -18,10 +27,19 @@ impl Metrics {
             keys_total: AtomicU64::new(0),
             objects_total: AtomicU64::new(0),
             bytes_total: AtomicU64::new(0),
+            logical_bytes_total: AtomicU64::new(0),
             puts_total: AtomicU64::new(0),
             gets_total: AtomicU64::new(0),
             deletes_total: AtomicU64::new(0),
             dedup_hits: AtomicU64::new(0),
+            expired_total: AtomicU64::new(0),
+            gc_reclaimed_objects: AtomicU64::new(0),
+            gc_reclaimed_bytes: AtomicU64::new(0),
+            scrub_objects: AtomicU64::new(0),
+            scrub_bytes: AtomicU64::new(0),
+            scrub_corrupt_found: AtomicU64::new(0),
+            scrub_passes: AtomicU64::new(0),
+            scrub_corrupt_objects: AtomicU64::new(0),
         }
     }
 
@@ -40,11 +58,45 @@ impl Metrics {
         self.deletes_total.fetch_add(1, Ordering::Relaxed);
     }
 
+    #[inline]
+    pub fn add_deletes(&self, count: u64) {
+        self.deletes_total.fetch_add(count, Ordering::Relaxed);
+    }
+
     #[inline]
     pub fn inc_dedup_hits(&self) {
         self.dedup_hits.fetch_add(1, Ordering::Relaxed);
     }
 
+    #[inline]
+    pub fn add_expired(&self, count: u64) {
+        self.expired_total.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Orphaned objects removed by a GC sweep
+    #[inline]
+    pub fn add_gc_reclaimed(&self, objects: u64, bytes: u64) {
+        self.gc_reclaimed_objects.fetch_add(objects, Ordering::Relaxed);
+        self.gc_reclaimed_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Work done by a background scrub step
+    #[inline]
+    pub fn add_scrubbed(&self, objects: u64, bytes: u64, corrupt_found: u64, pass_complete: bool) {
+        self.scrub_objects.fetch_add(objects, Ordering::Relaxed);
+        self.scrub_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.scrub_corrupt_found.fetch_add(corrupt_found, Ordering::Relaxed);
+        if pass_complete {
+            self.scrub_passes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Objects currently recorded as damaged
+    #[inline]
+    pub fn set_scrub_corrupt_objects(&self, count: u64) {
+        self.scrub_corrupt_objects.store(count, Ordering::Relaxed);
+    }
+
     #[inline]
     pub fn set_keys(&self, count: u64) {
         self.keys_total.store(count, Ordering::Relaxed);
@@ -55,20 +107,16 @@ impl Metrics {
         self.objects_total.store(count, Ordering::Relaxed);
     }
 
+    /// Stored (compressed) bytes
     #[inline]
-    pub fn add_bytes(&self, bytes: u64) {
-        self.bytes_total.fetch_add(bytes, Ordering::Relaxed);
+    pub fn set_bytes(&self, bytes: u64) {
+        self.bytes_total.store(bytes, Ordering::Relaxed);
     }
 
-    /// Saturating subtract to prevent underflow
+    /// Uncompressed bytes across all keys
     #[inline]
-    pub fn sub_bytes(&self, bytes: u64) {
-        // Use a lock-free approach for saturating subtraction
-        let _ = self.bytes_total.fetch_update(
-            Ordering::Relaxed,
-            Ordering::Relaxed,
-            |current| Some(current.saturating_sub(bytes))
-        );
+    pub fn set_logical_bytes(&self, bytes: u64) {
+        self.logical_bytes_total.store(bytes, Ordering::Relaxed);
     }
 
     pub fn to_prometheus(&self) -> String {
@@ -82,6 +130,9 @@ impl Metrics {
              # HELP kv_storage_bytes_total Total storage bytes\n\
              # TYPE kv_storage_bytes_total gauge\n\
              kv_storage_bytes_total {}\n\
+             # HELP kv_storage_logical_bytes_total Total uncompressed bytes across all keys\n\
+             # TYPE kv_storage_logical_bytes_total gauge\n\
+             kv_storage_logical_bytes_total {}\n\
              # HELP kv_storage_ops_total Total operations\n\
              # TYPE kv_storage_ops_total counter\n\
              kv_storage_ops_total{{operation=\"put\"}} {}\n\
@@ -89,14 +140,47 @@ impl Metrics {
              kv_storage_ops_total{{operation=\"delete\"}} {}\n\
              # HELP kv_storage_dedup_hits_total Total deduplication hits\n\
              # TYPE kv_storage_dedup_hits_total counter\n\
-             kv_storage_dedup_hits_total {}\n",
+             kv_storage_dedup_hits_total {}\n\
+             # HELP kv_storage_expired_keys_total Keys removed by TTL expiry\n\
+             # TYPE kv_storage_expired_keys_total counter\n\
+             kv_storage_expired_keys_total {}\n\
+             # HELP kv_storage_gc_reclaimed_objects_total Orphaned objects removed by GC sweeps\n\
+             # TYPE kv_storage_gc_reclaimed_objects_total counter\n\
+             kv_storage_gc_reclaimed_objects_total {}\n\
+             # HELP kv_storage_gc_reclaimed_bytes_total Stored bytes reclaimed by GC sweeps\n\
+             # TYPE kv_storage_gc_reclaimed_bytes_total counter\n\
+             kv_storage_gc_reclaimed_bytes_total {}\n\
+             # HELP kv_storage_scrub_objects_total Objects re-verified by the scrubber\n\
+             # TYPE kv_storage_scrub_objects_total counter\n\
+             kv_storage_scrub_objects_total {}\n\
+             # HELP kv_storage_scrub_bytes_total Stored bytes re-verified by the scrubber\n\
+             # TYPE kv_storage_scrub_bytes_total counter\n\
+             kv_storage_scrub_bytes_total {}\n\
+             # HELP kv_storage_scrub_corrupt_found_total Corrupt objects found by the scrubber\n\
+             # TYPE kv_storage_scrub_corrupt_found_total counter\n\
+             kv_storage_scrub_corrupt_found_total {}\n\
+             # HELP kv_storage_scrub_passes_total Completed scrub passes over all objects\n\
+             # TYPE kv_storage_scrub_passes_total counter\n\
+             kv_storage_scrub_passes_total {}\n\
+             # HELP kv_storage_scrub_corrupt_objects Objects currently recorded as corrupt\n\
+             # TYPE kv_storage_scrub_corrupt_objects gauge\n\
+             kv_storage_scrub_corrupt_objects {}\n",
             self.keys_total.load(Ordering::Relaxed),
             self.objects_total.load(Ordering::Relaxed),
             self.bytes_total.load(Ordering::Relaxed),
+            self.logical_bytes_total.load(Ordering::Relaxed),
             self.puts_total.load(Ordering::Relaxed),
             self.gets_total.load(Ordering::Relaxed),
             self.deletes_total.load(Ordering::Relaxed),
-            self.dedup_hits.load(Ordering::Relaxed)
+            self.dedup_hits.load(Ordering::Relaxed),
+            self.expired_total.load(Ordering::Relaxed),
+            self.gc_reclaimed_objects.load(Ordering::Relaxed),
+            self.gc_reclaimed_bytes.load(Ordering::Relaxed),
+            self.scrub_objects.load(Ordering::Relaxed),
+            self.scrub_bytes.load(Ordering::Relaxed),
+            self.scrub_corrupt_found.load(Ordering::Relaxed),
+            self.scrub_passes.load(Ordering::Relaxed),
+            self.scrub_corrupt_objects.load(Ordering::Relaxed)
         )
     }
 }
@@ -1,3 +1,5 @@
 pub mod hash;
+pub mod checksum;
 pub mod compression;
 pub mod metrics;
+pub mod time;
@@ -14,6 +14,7 @@ fn make_auth_request(method: &str, path: &str, body: Option<&[u8]>) -> Result<(S
 
     let client = reqwest::blocking::Client::builder()
         .timeout(Duration::from_secs(30))
+        .http2_prior_knowledge()
         .build()?;
 
     let mut request = match method {
@@ -241,6 +242,307 @@ fn test_head_nonexistent() {
     assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
 }
 
+// ========== TTL Tests ==========
+
+#[test]
+fn test_put_with_ttl_expires() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let response = client
+        .put(format!("{}/ttl_test_key", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("X-TTL", "1")
+        .body("short-lived")
+        .send()
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = client
+        .head(format!("{}/ttl_test_key", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response.headers().contains_key("x-expires-at"));
+    let ttl: u64 = response.headers()["x-ttl"].to_str().unwrap().parse().unwrap();
+    assert!(ttl <= 1);
+
+    std::thread::sleep(Duration::from_millis(2100));
+
+    let (_, status) = make_auth_request("GET", "/ttl_test_key", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    // Cleanup (the sweeper may already have removed it)
+    let _ = make_auth_request("DELETE", "/ttl_test_key", None);
+}
+
+#[test]
+fn test_put_with_invalid_ttl() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let response = client
+        .put(format!("{}/ttl_invalid_key", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("X-TTL", "forever")
+        .body("value")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn test_conditional_put_and_delete() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/conditional_test_key", base_url);
+    let auth = format!("Bearer {}", token);
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+
+    // If-None-Match: * creates only once
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("If-None-Match", "*")
+        .body("v1")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let v1 = response.headers()["x-hash"].to_str().unwrap().to_string();
+
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("If-None-Match", "*")
+        .body("v1")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+    // Compare-and-swap with If-Match
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("If-Match", format!("\"{}\"", v1))
+        .body("v2")
+        .send()
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = client
+        .delete(&url)
+        .header("Authorization", &auth)
+        .header("If-Match", &v1)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+    let response = client
+        .delete(&url)
+        .header("Authorization", &auth)
+        .header("If-Match", "*")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+}
+
+#[test]
+fn test_get_not_modified() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/etag_test_key", base_url);
+    let auth = format!("Bearer {}", token);
+
+    client.put(&url).header("Authorization", &auth).body("cached").send().unwrap();
+
+    let response = client.get(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["last-modified"].to_str().unwrap().to_string();
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("If-None-Match", &etag)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["etag"].to_str().unwrap(), etag);
+
+    let response = client
+        .head(&url)
+        .header("Authorization", &auth)
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+
+    // A stale tag gets the full body
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("If-None-Match", "\"00000000000000000000000000000000\"")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().unwrap(), "cached");
+
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+}
+
+#[test]
+fn test_get_range() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/range_test_key", base_url);
+    let auth = format!("Bearer {}", token);
+    let value = "0123456789".repeat(200);
+
+    client.put(&url).header("Authorization", &auth).body(value.clone()).send().unwrap();
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=10-19")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["content-range"].to_str().unwrap(), "bytes 10-19/2000");
+    assert_eq!(response.text().unwrap(), &value[10..20]);
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=0-1,-2")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("multipart/byteranges"));
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=5000-")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()["content-range"].to_str().unwrap(), "bytes */2000");
+
+    // Overlapping ranges asking for more than the value get the value once
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=0-,0-,0-")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.text().unwrap(), value);
+
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+}
+
+#[test]
+fn test_large_value_streaming() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/streaming_test_key", base_url);
+    let auth = format!("Bearer {}", token);
+
+    // 4 MiB, mildly compressible, sent without Content-Length
+    let value: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8 ^ (i >> 13) as u8).collect();
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .body(reqwest::blocking::Body::new(std::io::Cursor::new(value.clone())))
+        .send()
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = client.get(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.bytes().unwrap().as_ref(), value.as_slice());
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=3000000-3000099")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.bytes().unwrap().as_ref(), &value[3_000_000..3_000_100]);
+
+    let response = client
+        .get(&url)
+        .header("Authorization", &auth)
+        .header("Range", "bytes=3000000-3000009,10-19,15-24")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+    let length: usize = response.headers()["content-length"].to_str().unwrap().parse().unwrap();
+    let body = response.bytes().unwrap();
+    assert_eq!(body.len(), length);
+    let first = body.windows(15).position(|w| w == &value[10..25]).unwrap();
+    let second = body.windows(10).position(|w| w == &value[3_000_000..3_000_010]).unwrap();
+    assert!(first < second);
+
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+}
+
+#[test]
+fn test_max_value_size() {
+    // Only meaningful against a server started with a small KV_MAX_VALUE_SIZE
+    let Some(limit) = std::env::var("KV_MAX_VALUE_SIZE").ok().and_then(|s| s.parse::<usize>().ok()) else {
+        return;
+    };
+    if limit == 0 || limit > 8 * 1024 * 1024 {
+        return;
+    }
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/max_value_size_key", base_url);
+    let auth = format!("Bearer {}", token);
+    let value = vec![7u8; limit + 1];
+
+    // Refused up front with Content-Length, and part-way through without
+    let response = client.put(&url).header("Authorization", &auth).body(value.clone()).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .body(reqwest::blocking::Body::new(std::io::Cursor::new(value)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    let response = client.get(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let response = client.put(&url).header("Authorization", &auth).body(vec![7u8; limit]).send().unwrap();
+    assert!(response.status().is_success());
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+}
+
 // ========== Key Listing Tests ==========
 
 #[test]
@@ -249,6 +551,99 @@ fn test_list_keys_empty() {
     assert_eq!(status, reqwest::StatusCode::OK);
 }
 
+#[test]
+fn test_list_keys_cursor() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+    for i in 0..5 {
+        client
+            .put(format!("{}/cursor_test/{}", base_url, i))
+            .header("Authorization", &auth)
+            .body(format!("value{}", i))
+            .send()
+            .unwrap();
+    }
+
+    let list = |query: &str| -> serde_json::Value {
+        client
+            .get(format!("{}/keys?{}", base_url, query))
+            .header("Authorization", &auth)
+            .send()
+            .unwrap()
+            .json()
+            .unwrap()
+    };
+    let names = |page: &serde_json::Value| -> Vec<String> {
+        page["keys"].as_array().unwrap().iter().map(|k| k["key"].as_str().unwrap().to_string()).collect()
+    };
+
+    // Walk the prefix two keys at a time
+    let mut seen = Vec::new();
+    let mut page = list("prefix=cursor_test/&limit=2");
+    loop {
+        seen.extend(names(&page));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => page = list(&format!("prefix=cursor_test/&limit=2&cursor={}", cursor)),
+            None => break,
+        }
+    }
+    assert_eq!(seen, (0..5).map(|i| format!("cursor_test/{}", i)).collect::<Vec<_>>());
+
+    let page = list("prefix=cursor_test/&reverse=true&start_after=cursor_test/3&end=cursor_test/0");
+    assert_eq!(names(&page), ["cursor_test/2", "cursor_test/1"]);
+    assert!(page.get("next_cursor").is_none());
+
+    for i in 0..5 {
+        let _ = client.delete(format!("{}/cursor_test/{}", base_url, i)).header("Authorization", &auth).send();
+    }
+}
+
+#[test]
+fn test_list_keys_delimiter() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+    let keys = ["dir_test/a/1", "dir_test/a/2", "dir_test/b/1", "dir_test/c", "dir_test/d/x/1"];
+    for key in keys {
+        client.put(format!("{}/{}", base_url, key)).header("Authorization", &auth).body("v").send().unwrap();
+    }
+
+    let list = |query: &str| -> serde_json::Value {
+        client
+            .get(format!("{}/keys?{}", base_url, query))
+            .header("Authorization", &auth)
+            .send()
+            .unwrap()
+            .json()
+            .unwrap()
+    };
+
+    let page = list("prefix=dir_test/&delimiter=/");
+    assert_eq!(page["keys"].as_array().unwrap().len(), 1);
+    assert_eq!(page["keys"][0]["key"], "dir_test/c");
+    assert_eq!(page["common_prefixes"], serde_json::json!(["dir_test/a/", "dir_test/b/", "dir_test/d/"]));
+
+    // Folders count towards the limit and are not repeated across pages
+    let first = list("prefix=dir_test/&delimiter=/&limit=2");
+    assert_eq!(first["common_prefixes"], serde_json::json!(["dir_test/a/", "dir_test/b/"]));
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let second = list(&format!("prefix=dir_test/&delimiter=/&limit=2&cursor={}", cursor));
+    assert_eq!(second["keys"][0]["key"], "dir_test/c");
+    assert_eq!(second["common_prefixes"], serde_json::json!(["dir_test/d/"]));
+    assert!(second.get("next_cursor").is_none());
+
+    for key in keys {
+        let _ = client.delete(format!("{}/{}", base_url, key)).header("Authorization", &auth).send();
+    }
+}
+
 #[test]
 fn test_list_keys_pagination() {
     // Clean up first
@@ -394,6 +789,193 @@ fn test_batch_update_key() {
     make_auth_request("DELETE", "/batch_update_new", None).unwrap();
 }
 
+#[test]
+fn test_batch_binary_values() {
+    use base64::Engine;
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let binary: Vec<u8> = (0..=255u8).collect();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&binary);
+
+    let batch = serde_json::json!({"encoding": "base64", "ops": [
+        {"op": "put", "key": "batch_binary_json", "value": encoded},
+        {"op": "get", "key": "batch_binary_json"}
+    ]}).to_string();
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["results"][1]["get"]["value"], encoded.as_str());
+    let response = client.get(format!("{}/batch_binary_json", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .unwrap();
+    assert_eq!(response.bytes().unwrap().as_ref(), binary.as_slice());
+
+    // CBOR carries values as byte strings both ways
+    let op = |op: &str, key: &str, value: Option<&[u8]>| {
+        let mut fields = vec![("op".into(), op.into()), ("key".into(), key.into())];
+        if let Some(value) = value {
+            fields.push(("value".into(), ciborium::Value::Bytes(value.to_vec())));
+        }
+        ciborium::Value::Map(fields)
+    };
+    let ops = ciborium::Value::Array(vec![
+        op("put", "batch_binary_cbor", Some(&binary)),
+        op("get", "batch_binary_cbor", None),
+    ]);
+    let mut request = Vec::new();
+    ciborium::into_writer(&ops, &mut request).unwrap();
+    let response = client.post(format!("{}/batch", base_url))
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Content-Type", "application/cbor")
+        .body(request)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+    let reply: ciborium::Value = ciborium::from_reader(response.bytes().unwrap().as_ref()).unwrap();
+    let results = reply.as_map().unwrap()[0].1.as_array().unwrap();
+    let get = results[1].as_map().unwrap()[0].1.as_map().unwrap();
+    let value = get.iter().find(|(k, _)| k.as_text() == Some("value")).unwrap();
+    assert_eq!(value.1.as_bytes().unwrap(), &binary);
+
+    make_auth_request("DELETE", "/batch_binary_json", None).unwrap();
+    make_auth_request("DELETE", "/batch_binary_cbor", None).unwrap();
+}
+
+#[test]
+fn test_batch_atomic() {
+    make_auth_request("PUT", "/batch_atomic_a", Some(b"first")).unwrap();
+
+    // The second op's precondition fails, so the first is rolled back
+    let batch = r#"{"atomic": true, "ops": [
+        {"op": "put", "key": "batch_atomic_b", "value": "new"},
+        {"op": "put", "key": "batch_atomic_a", "value": "second", "if_none_match": "*"}
+    ]}"#;
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::PRECONDITION_FAILED);
+    let failure: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(failure["index"], 1);
+    assert_eq!(failure["key"], "batch_atomic_a");
+    let (_, status) = make_auth_request("GET", "/batch_atomic_b", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let batch = r#"{"atomic": true, "ops": [
+        {"op": "put", "key": "batch_atomic_b", "value": "new", "if_none_match": "*"},
+        {"op": "delete", "key": "batch_atomic_a", "if_match": "*"}
+    ]}"#;
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let results = json["results"].as_array().unwrap();
+    assert_eq!(results[0]["put"]["created"], true);
+    assert_eq!(results[1]["delete"]["deleted"], true);
+    let (body, _) = make_auth_request("GET", "/batch_atomic_b", None).unwrap();
+    assert_eq!(body, "new");
+
+    // Reads and copies cannot be part of an atomic batch
+    let batch = r#"{"atomic": true, "ops": [{"op": "get", "key": "batch_atomic_b"}]}"#;
+    let (_, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    make_auth_request("DELETE", "/batch_atomic_b", None).unwrap();
+}
+
+#[test]
+fn test_batch_validates_keys() {
+    // Keys a single-key PUT refuses fail their op...
+    let batch = r#"[
+        {"op": "put", "key": "", "value": "empty"},
+        {"op": "put", "key": "trash", "value": "reserved"},
+        {"op": "put", "key": "batch_valid_key", "value": "fine"}
+    ]"#;
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let results = json["results"].as_array().unwrap();
+    assert!(results[0]["error"]["error"].as_str().unwrap().contains("empty"));
+    assert!(results[1]["error"].is_object());
+    assert_eq!(results[2]["put"]["created"], true);
+
+    // ...and abort an atomic batch
+    let batch = r#"{"atomic": true, "ops": [
+        {"op": "delete", "key": "batch_valid_key"},
+        {"op": "put", "key": "", "value": "empty"}
+    ]}"#;
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let failure: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(failure["index"], 1);
+    let (body, _) = make_auth_request("GET", "/batch_valid_key", None).unwrap();
+    assert_eq!(body, "fine");
+
+    make_auth_request("DELETE", "/batch_valid_key", None).unwrap();
+}
+
+#[test]
+fn test_batch_head_exists_cas_list() {
+    let batch = r#"[
+        {"op": "put", "key": "batch_ops/a", "value": "one"},
+        {"op": "put", "key": "batch_ops/b", "value": "two"},
+        {"op": "put", "key": "batch_ops/c", "value": "three"}
+    ]"#;
+    let (body, _) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let hash_a = json["results"][0]["put"]["hash"].as_str().unwrap().to_string();
+
+    let batch = format!(r#"[
+        {{"op": "head", "key": "batch_ops/a"}},
+        {{"op": "head", "key": "batch_ops/missing"}},
+        {{"op": "exists", "key": "batch_ops/b"}},
+        {{"op": "exists", "key": "batch_ops/missing"}},
+        {{"op": "cas", "key": "batch_ops/a", "value": "uno", "expected": "{hash_a}"}},
+        {{"op": "cas", "key": "batch_ops/b", "value": "dos", "expected": "{hash_a}"}},
+        {{"op": "compare_and_swap", "key": "batch_ops/d", "value": "four"}},
+        {{"op": "list", "prefix": "batch_ops/", "limit": 2}},
+        {{"op": "list", "prefix": "batch_ops/", "start_after": "batch_ops/b"}}
+    ]"#);
+    let (body, status) = make_auth_request("POST", "/batch", Some(batch.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let results = json["results"].as_array().unwrap();
+
+    assert_eq!(results[0]["head"]["found"], true);
+    assert_eq!(results[0]["head"]["hash"], hash_a.as_str());
+    assert_eq!(results[0]["head"]["size"], 3);
+    assert!(results[0]["head"].get("value").is_none());
+    assert_eq!(results[1]["head"]["found"], false);
+    assert_eq!(results[2]["exists"]["exists"], true);
+    assert_eq!(results[3]["exists"]["exists"], false);
+
+    // A mismatch reports the current hash instead of failing the op
+    assert_eq!(results[4]["cas"]["swapped"], true);
+    assert_eq!(results[5]["cas"]["swapped"], false);
+    assert_ne!(results[5]["cas"]["hash"], hash_a.as_str());
+    assert_eq!(results[6]["cas"]["swapped"], true);
+    let (body, _) = make_auth_request("GET", "/batch_ops/a", None).unwrap();
+    assert_eq!(body, "uno");
+    let (body, _) = make_auth_request("GET", "/batch_ops/b", None).unwrap();
+    assert_eq!(body, "two");
+
+    let listed: Vec<_> = results[7]["list"]["keys"].as_array().unwrap().iter()
+        .map(|k| k["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(listed, vec!["batch_ops/a", "batch_ops/b"]);
+    assert_eq!(results[7]["list"]["more"], true);
+    let listed: Vec<_> = results[8]["list"]["keys"].as_array().unwrap().iter()
+        .map(|k| k["key"].as_str().unwrap())
+        .collect();
+    assert_eq!(listed, vec!["batch_ops/c", "batch_ops/d"]);
+    assert_eq!(results[8]["list"]["more"], false);
+
+    for key in ["a", "b", "c", "d"] {
+        make_auth_request("DELETE", &format!("/batch_ops/{}", key), None).unwrap();
+    }
+}
+
 // ========== Authentication Tests ==========
 
 #[test]
@@ -486,6 +1068,545 @@ fn test_metrics_count_operations() {
     assert_ne!(initial_metrics, updated_metrics);
 }
 
+#[test]
+fn test_metrics_storage_totals() {
+    let gauge = |metrics: &str, name: &str| -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    make_auth_request("PUT", "/metrics_totals_key", Some(b"metrics totals value")).unwrap();
+    let (metrics, status) = make_auth_request("GET", "/metrics", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    assert!(gauge(&metrics, "kv_storage_keys_total") >= 1);
+    assert!(gauge(&metrics, "kv_storage_objects_total") >= 1);
+    assert!(gauge(&metrics, "kv_storage_bytes_total") > 0);
+    assert!(gauge(&metrics, "kv_storage_logical_bytes_total") >= 20);
+
+    make_auth_request("DELETE", "/metrics_totals_key", None).unwrap();
+}
+
+#[test]
+fn test_metrics_scrub() {
+    let (metrics, status) = make_auth_request("GET", "/metrics", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    for name in [
+        "kv_storage_scrub_objects_total",
+        "kv_storage_scrub_bytes_total",
+        "kv_storage_scrub_corrupt_found_total",
+        "kv_storage_scrub_passes_total",
+    ] {
+        assert!(metrics.contains(&format!("# TYPE {} counter", name)), "{} missing", name);
+    }
+    assert!(metrics.contains("\nkv_storage_scrub_corrupt_objects 0\n"));
+}
+
+#[test]
+fn test_admin_gc() {
+    make_auth_request("PUT", "/gc_test_key", Some(b"gc value one")).unwrap();
+    make_auth_request("PUT", "/gc_test_key", Some(b"gc value two")).unwrap();
+
+    let (body, status) = make_auth_request("POST", "/admin/gc", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(report["scanned"].as_u64().unwrap() >= 1);
+    assert!(report["reclaimed_objects"].is_u64());
+    assert!(report["reclaimed_bytes"].is_u64());
+
+    let (metrics, _) = make_auth_request("GET", "/metrics", None).unwrap();
+    assert!(metrics.contains("kv_storage_gc_reclaimed_bytes_total"));
+
+    let (value, _) = make_auth_request("GET", "/gc_test_key", None).unwrap();
+    assert_eq!(value, "gc value two");
+    make_auth_request("DELETE", "/gc_test_key", None).unwrap();
+}
+
+#[test]
+fn test_admin_fsck() {
+    make_auth_request("PUT", "/fsck_test_key", Some(b"fsck value")).unwrap();
+
+    let (body, status) = make_auth_request("POST", "/admin/fsck", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(report["keys_checked"].as_u64().unwrap() >= 1);
+    assert!(report["objects_checked"].as_u64().unwrap() >= 1);
+    assert!(report["corrupt_objects"]["count"].is_u64());
+    assert_eq!(report["repaired"], false);
+
+    let (_, status) = make_auth_request("POST", "/admin/fsck?repair=maybe", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    make_auth_request("DELETE", "/fsck_test_key", None).unwrap();
+}
+
+#[test]
+fn test_put_expected_checksums() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/checksum_test_key", base_url);
+    let auth = format!("Bearer {}", token);
+
+    // A body cut short in transit must not be stored
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("Content-MD5", "kAFQmDzST7DWlj99KOF/cg==")
+        .body("ab")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let message = response.text().unwrap();
+    assert!(message.contains("kAFQmDzST7DWlj99KOF/cg=="));
+    let (_, status) = make_auth_request("GET", "/checksum_test_key", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("Digest", "sha-256=ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=")
+        .body("abc")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let hash = response.headers()["x-hash"].to_str().unwrap().to_string();
+
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("X-Expected-Hash", &hash)
+        .body("abd")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    assert!(response.text().unwrap().contains(&hash));
+
+    // Large bodies are checked as they stream in
+    let response = client
+        .put(&url)
+        .header("Authorization", &auth)
+        .header("Digest", "sha-256=ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=")
+        .body(vec![b'x'; 200 * 1024])
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Batch puts carry the same checks as fields
+    let ops = format!(
+        r#"[{{"op":"put","key":"checksum_batch_key","value":"abc","expected_hash":"{}"}},
+            {{"op":"put","key":"checksum_batch_bad","value":"ab","content_md5":"kAFQmDzST7DWlj99KOF/cg=="}}]"#,
+        hash
+    );
+    let (body, status) = make_auth_request("POST", "/batch", Some(ops.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(response["results"][0]["put"].is_object());
+    assert!(response["results"][1]["error"]["error"].as_str().unwrap().contains("Content-MD5 mismatch"));
+
+    let (value, _) = make_auth_request("GET", "/checksum_test_key", None).unwrap();
+    assert_eq!(value, "abc");
+    make_auth_request("DELETE", "/checksum_test_key", None).unwrap();
+    make_auth_request("DELETE", "/checksum_batch_key", None).unwrap();
+}
+
+#[test]
+fn test_hash_algorithm_reported() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let url = format!("{}/hash_algorithm_key", base_url);
+    let auth = format!("Bearer {}", token);
+
+    let response = client.put(&url).header("Authorization", &auth).body("hashed").send().unwrap();
+    let algorithm = response.headers()["x-hash-algorithm"].to_str().unwrap().to_string();
+    assert!(algorithm == "xxhash3" || algorithm == "sha256-128");
+
+    let response = client.head(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.headers()["x-hash-algorithm"].to_str().unwrap(), algorithm);
+
+    let (body, _) = make_auth_request("GET", "/keys?prefix=hash_algorithm_key", None).unwrap();
+    let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list["keys"][0]["hash_algorithm"], algorithm.as_str());
+
+    // Keys already on the configured algorithm are left alone
+    let (body, status) = make_auth_request("POST", "/admin/rehash", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(report["scanned"].as_u64().unwrap() >= 1);
+    assert_eq!(report["rehashed"], 0);
+
+    let _ = client.delete(&url).header("Authorization", &auth).send();
+}
+
+#[test]
+fn test_shared_object_refs() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+    let value = "value shared by the refs test keys";
+
+    let response = client.put(format!("{}/refs_test_a", base_url)).header("Authorization", &auth).body(value).send().unwrap();
+    let hash = response.headers()["x-hash"].to_str().unwrap().to_string();
+    client.put(format!("{}/refs_test_b", base_url)).header("Authorization", &auth).body(value).send().unwrap();
+
+    let response = client.head(format!("{}/refs_test_a", base_url)).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.headers()["x-refs"], "2");
+
+    let (body, _) = make_auth_request("GET", "/keys?prefix=refs_test_", None).unwrap();
+    let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list["keys"][0]["refs"], 2);
+    assert_eq!(list["keys"][1]["refs"], 2);
+
+    let (body, status) = make_auth_request("GET", &format!("/objects/{}/keys", hash), None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let refs: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(refs["refs"], 2);
+    assert_eq!(refs["keys"], serde_json::json!(["refs_test_a", "refs_test_b"]));
+    assert!(refs.get("next_cursor").is_none());
+
+    // Paging with a cursor
+    let (body, _) = make_auth_request("GET", &format!("/objects/{}/keys?limit=1", hash), None).unwrap();
+    let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(page["keys"], serde_json::json!(["refs_test_a"]));
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (body, _) = make_auth_request("GET", &format!("/objects/{}/keys?limit=1&cursor={}", hash, cursor), None).unwrap();
+    let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(page["keys"], serde_json::json!(["refs_test_b"]));
+
+    client.delete(format!("{}/refs_test_b", base_url)).header("Authorization", &auth).send().unwrap();
+    let response = client.head(format!("{}/refs_test_a", base_url)).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.headers()["x-refs"], "1");
+
+    client.delete(format!("{}/refs_test_a", base_url)).header("Authorization", &auth).send().unwrap();
+    let (_, status) = make_auth_request("GET", &format!("/objects/{}/keys", hash), None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_objects_by_hash() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+
+    // Large enough to be streamed, and incompressible
+    let mut state = 0x2545F4914F6CDD1Du64;
+    let value: Vec<u8> = (0..200_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let response = client.put(format!("{}/objects_test_key", base_url))
+        .header("Authorization", &auth)
+        .body(value.clone())
+        .send()
+        .unwrap();
+    let hash = response.headers()["x-hash"].to_str().unwrap().to_string();
+    let algorithm = response.headers()["x-hash-algorithm"].clone();
+
+    let url = format!("{}/objects/{}", base_url, hash);
+    let response = client.head(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers()["content-length"], "200000");
+    assert_eq!(response.headers()["x-refs"], "1");
+    assert_eq!(response.headers()["x-hash"].to_str().unwrap(), hash);
+    assert_eq!(response.headers()["x-hash-algorithm"], algorithm);
+
+    // A key the object route would shadow cannot be written
+    let (_, status) = make_auth_request("PUT", &format!("/objects/{}", hash), Some(b"shadowed")).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (_, status) = make_auth_request("PUT", &format!("/objects/{}/keys", hash), Some(b"shadowed")).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    let response = client.get(&url).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.bytes().unwrap().as_ref(), value.as_slice());
+
+    let unknown = "00000000000000000000000000000000";
+    let body = serde_json::json!({"hashes": [hash, unknown]}).to_string();
+    let (body, status) = make_auth_request("POST", "/objects/missing", Some(body.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(result["missing"], serde_json::json!([unknown]));
+
+    let (_, status) = make_auth_request("POST", "/objects/missing", Some(br#"{"hashes": ["nothex"]}"#)).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    let (_, status) = make_auth_request("GET", &format!("/objects/{}", unknown), None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    client.delete(format!("{}/objects_test_key", base_url)).header("Authorization", &auth).send().unwrap();
+    let (_, status) = make_auth_request("GET", &format!("/objects/{}", hash), None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_put_by_content_hash() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+    let value = "artifact published under several keys";
+
+    let response = client.put(format!("{}/link_test_source", base_url)).header("Authorization", &auth).body(value).send().unwrap();
+    let hash = response.headers()["x-hash"].to_str().unwrap().to_string();
+
+    let response = client.put(format!("{}/link_test_copy", base_url))
+        .header("Authorization", &auth)
+        .header("X-Content-Hash", &hash)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    assert_eq!(response.headers()["x-deduplicated"], "true");
+    assert_eq!(response.headers()["x-hash"].to_str().unwrap(), hash);
+
+    let (body, status) = make_auth_request("GET", "/link_test_copy", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(body, value);
+    let response = client.head(format!("{}/link_test_copy", base_url)).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.headers()["content-length"], value.len().to_string().as_str());
+    assert_eq!(response.headers()["x-refs"], "2");
+
+    // Preconditions still apply
+    let response = client.put(format!("{}/link_test_copy", base_url))
+        .header("Authorization", &auth)
+        .header("X-Content-Hash", &hash)
+        .header("If-None-Match", "*")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+    let response = client.put(format!("{}/link_test_unknown", base_url))
+        .header("Authorization", &auth)
+        .header("X-Content-Hash", "00000000000000000000000000000000")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let response = client.put(format!("{}/link_test_unknown", base_url))
+        .header("Authorization", &auth)
+        .header("X-Content-Hash", &hash)
+        .body("unexpected body")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let ops = serde_json::json!([
+        {"op": "link", "key": "link_test_batch", "hash": hash},
+        {"op": "link", "key": "link_test_missing", "hash": "00000000000000000000000000000000"},
+    ]).to_string();
+    let (body, status) = make_auth_request("POST", "/batch", Some(ops.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["results"][0]["put"]["created"], true);
+    assert!(response["results"][1]["error"]["error"].as_str().unwrap().contains("not found"));
+
+    for key in ["link_test_source", "link_test_copy", "link_test_batch"] {
+        client.delete(format!("{}/{}", base_url, key)).header("Authorization", &auth).send().unwrap();
+    }
+}
+
+#[test]
+fn test_copy_and_rename() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+    for key in ["copy_test_source", "copy_test_dest", "copy_test_renamed", "copy_test_batch"] {
+        client.delete(format!("{}/{}", base_url, key)).header("Authorization", &auth).send().unwrap();
+    }
+    make_auth_request("PUT", "/copy_test_source", Some(b"shared value")).unwrap();
+
+    let body = serde_json::json!({"source": "copy_test_source", "destination": "copy_test_dest"}).to_string();
+    let (body, status) = make_auth_request("POST", "/copy", Some(body.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::CREATED);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["created"], true);
+    let response = client.head(format!("{}/copy_test_dest", base_url)).header("Authorization", &auth).send().unwrap();
+    assert_eq!(response.headers()["x-refs"], "2");
+
+    let body = serde_json::json!({"source": "copy_test_source", "destination": "copy_test_dest", "overwrite": false}).to_string();
+    let (_, status) = make_auth_request("POST", "/copy", Some(body.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::PRECONDITION_FAILED);
+
+    let body = serde_json::json!({"source": "copy_test_dest", "destination": "copy_test_renamed"}).to_string();
+    let (_, status) = make_auth_request("POST", "/rename", Some(body.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::CREATED);
+    let (_, status) = make_auth_request("GET", "/copy_test_dest", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    let (body, _) = make_auth_request("GET", "/copy_test_renamed", None).unwrap();
+    assert_eq!(body, "shared value");
+
+    let body = serde_json::json!({"source": "copy_test_missing", "destination": "copy_test_dest"}).to_string();
+    let (_, status) = make_auth_request("POST", "/rename", Some(body.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let ops = serde_json::json!([
+        {"op": "copy", "source": "copy_test_source", "destination": "copy_test_batch"},
+        {"op": "rename", "source": "copy_test_batch", "destination": "copy_test_renamed", "overwrite": false},
+    ]).to_string();
+    let (body, status) = make_auth_request("POST", "/batch", Some(ops.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(response["results"][0]["copy"]["key"], "copy_test_batch");
+    assert_eq!(response["results"][0]["copy"]["created"], true);
+    assert!(response["results"][1]["error"].is_object());
+
+    for key in ["copy_test_source", "copy_test_renamed", "copy_test_batch"] {
+        client.delete(format!("{}/{}", base_url, key)).header("Authorization", &auth).send().unwrap();
+    }
+}
+
+#[test]
+fn test_delete_prefix() {
+    for i in 0..5 {
+        make_auth_request("PUT", &format!("/prefix_delete_test/{}", i), Some(format!("value {}", i).as_bytes())).unwrap();
+    }
+    make_auth_request("PUT", "/prefix_delete_test_other", Some(b"kept")).unwrap();
+
+    let (_, status) = make_auth_request("DELETE", "/keys?prefix=", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    let (body, status) = make_auth_request("DELETE", "/keys?prefix=prefix_delete_test/&dry_run=true", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(report["keys"], 5);
+    assert_eq!(report["bytes"], 35);
+    assert_eq!(report["dry_run"], true);
+    let (_, status) = make_auth_request("GET", "/prefix_delete_test/0", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    let (body, status) = make_auth_request("DELETE", "/keys?prefix=prefix_delete_test/&progress=true&chunk_size=2", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let lines: Vec<serde_json::Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert!(lines.len() >= 3);
+    let last = lines.last().unwrap();
+    assert_eq!(last["done"], true);
+    assert_eq!(last["keys"], 5);
+    assert_eq!(last["freed_objects"], 5);
+
+    for i in 0..5 {
+        let (_, status) = make_auth_request("GET", &format!("/prefix_delete_test/{}", i), None).unwrap();
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    }
+    let (body, _) = make_auth_request("GET", "/prefix_delete_test_other", None).unwrap();
+    assert_eq!(body, "kept");
+    make_auth_request("DELETE", "/prefix_delete_test_other", None).unwrap();
+}
+
+/// Full history checks need the server started with
+/// `KV_VERSIONED_PREFIXES=versioned_test/`; otherwise only the live value is listed.
+#[test]
+fn test_versioning() {
+    let (base_url, token) = get_config();
+    let client = reqwest::blocking::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let auth = format!("Bearer {}", token);
+
+    make_auth_request("PUT", "/versioned_test/config", Some(b"first")).unwrap();
+    make_auth_request("PUT", "/versioned_test/config", Some(b"second")).unwrap();
+
+    let (body, status) = make_auth_request("GET", "/versioned_test/config?versions", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let listing: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let history = listing["versions"].as_array().unwrap();
+    assert_eq!(history[0]["current"], true);
+    let current = history[0]["version"].as_u64().unwrap();
+
+    let response = client.get(format!("{}/versioned_test/config?version={}", base_url, current))
+        .header("Authorization", &auth)
+        .send()
+        .unwrap();
+    assert_eq!(response.headers()["x-version"], current.to_string().as_str());
+    assert_eq!(response.text().unwrap(), "second");
+
+    let (_, status) = make_auth_request("GET", "/versioned_test/config?version=zero", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    if history.len() > 1 {
+        let previous = history[1]["version"].as_u64().unwrap();
+        assert!(history[1]["archived_at"].is_u64());
+        let (body, _) = make_auth_request("GET", &format!("/versioned_test/config?version={}", previous), None).unwrap();
+        assert_eq!(body, "first");
+
+        let restore = serde_json::json!({"key": "versioned_test/config", "version": previous}).to_string();
+        let (body, status) = make_auth_request("POST", "/restore", Some(restore.as_bytes())).unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+        let restored: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(restored["version"], current + 1);
+        let (body, _) = make_auth_request("GET", "/versioned_test/config", None).unwrap();
+        assert_eq!(body, "first");
+    }
+
+    let restore = serde_json::json!({"key": "versioned_test/config", "version": 999_999}).to_string();
+    let (_, status) = make_auth_request("POST", "/restore", Some(restore.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    make_auth_request("DELETE", "/versioned_test/config", None).unwrap();
+}
+
+#[test]
+fn test_trash() {
+    // GET /trash lists the trash, so no key may take that name
+    let (_, status) = make_auth_request("PUT", "/trash", Some(b"shadowed")).unwrap();
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    make_auth_request("PUT", "/trash_test/doc", Some(b"keep me")).unwrap();
+    let (_, status) = make_auth_request("DELETE", "/trash_test/doc", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    let (_, status) = make_auth_request("GET", "/trash_test/doc", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let (body, status) = make_auth_request("GET", "/trash?prefix=trash_test/", None).unwrap();
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let listing: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let items = listing["items"].as_array().unwrap();
+
+    let restore = serde_json::json!({"key": "trash_test/doc"}).to_string();
+    let (body, status) = make_auth_request("POST", "/trash/restore", Some(restore.as_bytes())).unwrap();
+    // Only a server with KV_TRASH_RETENTION_SECS set keeps deleted keys
+    if items.is_empty() {
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        return;
+    }
+    assert_eq!(items[0]["key"], "trash_test/doc");
+    assert!(items[0]["purge_at"].as_u64().unwrap() > items[0]["deleted_at"].as_u64().unwrap());
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let restored: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(restored["size"], 7);
+
+    let (body, _) = make_auth_request("GET", "/trash_test/doc", None).unwrap();
+    assert_eq!(body, "keep me");
+    let (_, status) = make_auth_request("POST", "/trash/restore", Some(restore.as_bytes())).unwrap();
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    make_auth_request("DELETE", "/trash_test/doc", None).unwrap();
+}
+
 // ========== Edge Case Tests ==========
 
 #[test]
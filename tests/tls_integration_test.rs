@@ -508,7 +508,6 @@ fn test_tls_server_multiple_concurrent_connections() {
     let mut handles = vec![];
 
     for i in 0..10 {
-        let port = port.clone();
         let counter = success_count.clone();
         handles.push(thread::spawn(move || {
             let client = reqwest::blocking::Client::builder()